@@ -2,7 +2,7 @@ use bevy_derive::{Deref, DerefMut};
 use bevy_math::IVec3;
 
 /// Абсолютные координаты блока
-#[derive(Default, Copy, Clone, Deref, DerefMut)]
+#[derive(Default, Copy, Clone, PartialEq, Eq, Hash, Debug, Deref, DerefMut)]
 pub struct AbsoluteBlockPos {
     pos: IVec3,
 }
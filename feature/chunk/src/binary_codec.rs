@@ -0,0 +1,128 @@
+use std::io;
+use std::io::{Read, Write};
+
+/// Бинарное представление данных для сохранения чанков на диск или передачи по сети.
+///
+/// Реализуется типами блоков и метаданных чанка, сам формат чанка описан в реализации для [crate::Chunk]
+pub trait BinaryCodec: Sized {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self>;
+
+    /// Читает значение записанное старой версией `version` формата чанка, см [crate::CHUNK_FORMAT_VERSION].
+    /// По умолчанию старые версии не поддерживаются
+    fn decode_legacy<R: Read>(_reader: &mut R, version: u8) -> io::Result<Self> {
+        Err(invalid_data(&format!("unsupported format version {}", version)))
+    }
+}
+
+/// Возвращает ошибку о некорректных данных
+pub(crate) fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+impl BinaryCodec for () {
+    fn encode<W: Write>(&self, _writer: &mut W) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn decode<R: Read>(_reader: &mut R) -> io::Result<Self> {
+        Ok(())
+    }
+}
+
+impl BinaryCodec for bool {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u8).encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        match u8::decode(reader)? {
+            0 => { Ok(false) }
+            1 => { Ok(true) }
+            _ => { Err(invalid_data("incorrect bool value")) }
+        }
+    }
+}
+
+macro_rules! impl_binary_codec_for_number {
+    ($($t:ty),*) => {
+        $(
+            impl BinaryCodec for $t {
+                fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+
+                fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+                    let mut bytes = [0; std::mem::size_of::<$t>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    };
+}
+
+impl_binary_codec_for_number!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl<T: BinaryCodec> BinaryCodec for Option<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            None => { false.encode(writer) }
+            Some(value) => {
+                true.encode(writer)?;
+                value.encode(writer)
+            }
+        }
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        if bool::decode(reader)? {
+            Ok(Some(T::decode(reader)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn decode_legacy<R: Read>(reader: &mut R, version: u8) -> io::Result<Self> {
+        if bool::decode(reader)? {
+            Ok(Some(T::decode_legacy(reader, version)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BinaryCodec;
+
+    fn round_trip<T: BinaryCodec>(value: &T) -> T {
+        let mut bytes = Vec::new();
+        value.encode(&mut bytes).unwrap();
+        T::decode(&mut bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn numbers_round_trip() {
+        assert_eq!(round_trip(&0xABu8), 0xAB);
+        assert_eq!(round_trip(&-12345i32), -12345);
+        assert_eq!(round_trip(&u64::MAX), u64::MAX);
+        assert_eq!(round_trip(&1.5f32), 1.5);
+    }
+
+    #[test]
+    fn option_round_trip() {
+        assert_eq!(round_trip(&Some(7u16)), Some(7));
+        assert_eq!(round_trip(&None::<u16>), None);
+    }
+
+    #[test]
+    fn incorrect_bool_is_error() {
+        assert!(bool::decode(&mut [2u8].as_slice()).is_err());
+    }
+
+    #[test]
+    fn not_enough_data_is_error() {
+        assert!(u32::decode(&mut [1u8, 2].as_slice()).is_err());
+    }
+}
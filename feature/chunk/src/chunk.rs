@@ -1,44 +1,167 @@
+use std::io;
+use std::io::{Read, Write};
 use std::ops::{Index, IndexMut};
+use crate::binary_codec::{BinaryCodec, invalid_data};
 use crate::chunk_block_pos::ChunkBlockPos;
-
-type Array3<BLOCK, const SIZE: usize> = [[[BLOCK; SIZE]; SIZE]; SIZE];
+use crate::palette_storage::PaletteStorage;
 
 /// Размер чанка
 pub const CHUNK_SIZE: usize = 16;
 
+/// Версия бинарного формата чанка, см [BinaryCodec] для [Chunk].
+///
+/// Версии отличаются только форматом блоков: чанки старых версий читаются через [BinaryCodec::decode_legacy]
+pub const CHUNK_FORMAT_VERSION: u8 = 2;
+
 /// Сущность описывающая один игровой чанк.
 ///
 /// [BLOCK] - тип блоков в чанке.
 ///
 /// [METADATA] - любые дополнительные данные
+///
+/// Блоки хранятся в [PaletteStorage], поэтому чанк целиком состоящий из одинаковых блоков (например воздуха)
+/// практически не занимает памяти
+#[derive(Clone)]
 pub struct Chunk<BLOCK, METADATA> {
     metadata: METADATA,
-    blocks: Array3<Option<BLOCK>, CHUNK_SIZE>,
+    blocks: PaletteStorage<Option<BLOCK>>,
 }
 
 impl<BLOCK, METADATA> Chunk<BLOCK, METADATA> {
     pub fn new(metadata: METADATA) -> Self {
         Self {
             metadata,
-            blocks: std::array::from_fn(|_| std::array::from_fn(|_| std::array::from_fn(|_| None))),
+            blocks: PaletteStorage::new(None),
         }
     }
 
     pub fn get_metadata(&self) -> &METADATA {
         &self.metadata
     }
+
+    /// Возвращает true если чанк целиком состоит из одинаковых блоков
+    pub fn is_uniform(&self) -> bool {
+        self.blocks.is_uniform()
+    }
+
+    /// Возвращает количество различных блоков в чанке
+    pub fn palette_len(&self) -> usize {
+        self.blocks.palette_len()
+    }
+}
+
+impl<BLOCK: Clone + PartialEq, METADATA> Chunk<BLOCK, METADATA> {
+    /// Устанавливает блок по переданным координатам.
+    /// В отличие от [IndexMut] сразу кладет блок в палитру, поэтому предпочтительнее при массовой записи
+    pub fn set(&mut self, pos: &ChunkBlockPos, block: Option<BLOCK>) {
+        self.blocks.set(to_index(pos), block)
+    }
+}
+
+/// Переводит координаты блока в индекс внутри [PaletteStorage], порядок совпадает с порядком обхода [ChunkIter]
+fn to_index(pos: &ChunkBlockPos) -> usize {
+    (pos.x as usize * CHUNK_SIZE + pos.y as usize) * CHUNK_SIZE + pos.z as usize
 }
 
 impl<BLOCK, METADATA> Index<&ChunkBlockPos> for Chunk<BLOCK, METADATA> {
     type Output = Option<BLOCK>;
 
     fn index(&self, index: &ChunkBlockPos) -> &Self::Output {
-        &self.blocks[index.x as usize][index.y as usize][index.z as usize]
+        self.blocks.get(to_index(index))
     }
 }
 
-impl<BLOCK, METADATA> IndexMut<&ChunkBlockPos> for Chunk<BLOCK, METADATA> {
+impl<BLOCK: Clone + PartialEq, METADATA> IndexMut<&ChunkBlockPos> for Chunk<BLOCK, METADATA> {
     fn index_mut(&mut self, index: &ChunkBlockPos) -> &mut Self::Output {
-        &mut self.blocks[index.x as usize][index.y as usize][index.z as usize]
+        self.blocks.get_mut(to_index(index))
+    }
+}
+
+/// Формат: версия формата (u8), метаданные, блоки в формате [PaletteStorage]
+impl<BLOCK: BinaryCodec, METADATA: BinaryCodec> BinaryCodec for Chunk<BLOCK, METADATA> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        CHUNK_FORMAT_VERSION.encode(writer)?;
+        self.metadata.encode(writer)?;
+        self.blocks.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let version = u8::decode(reader)?;
+        if version == 0 || version > CHUNK_FORMAT_VERSION {
+            return Err(invalid_data(&format!("unsupported chunk format version {}", version)));
+        }
+        let metadata = METADATA::decode(reader)?;
+        let blocks = if version == CHUNK_FORMAT_VERSION {
+            PaletteStorage::decode(reader)?
+        } else {
+            PaletteStorage::decode_legacy(reader, version)?
+        };
+        Ok(Self { metadata, blocks })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::uvec3;
+    use crate::{BinaryCodec, Chunk, CHUNK_FORMAT_VERSION, CHUNK_SIZE};
+
+    #[test]
+    fn new_chunk_is_empty_and_uniform() {
+        let chunk = Chunk::<u8, ()>::new(());
+        assert!(chunk.is_uniform());
+        assert!(chunk.into_iter().all(|(_, block)| block.is_none()));
+    }
+
+    #[test]
+    fn index_mut_and_set_are_visible_through_iter() {
+        let mut chunk = Chunk::<u8, ()>::new(());
+        chunk[&uvec3(1, 2, 3).try_into().unwrap()] = Some(1);
+        chunk.set(&uvec3(15, 15, 15).try_into().unwrap(), Some(2));
+
+        let blocks: Vec<_> = chunk.into_iter()
+            .filter_map(|(pos, block)| block.map(|block| (*pos, block)))
+            .collect();
+        assert_eq!(blocks, vec![(uvec3(1, 2, 3), 1), (uvec3(15, 15, 15), 2)]);
+        assert_eq!(chunk.palette_len(), 3);
+    }
+
+    #[test]
+    fn filled_chunk_becomes_uniform() {
+        let mut chunk = Chunk::<u8, ()>::new(());
+        for (x, y, z) in (0..CHUNK_SIZE as u32)
+            .flat_map(|x| (0..CHUNK_SIZE as u32).flat_map(move |y| (0..CHUNK_SIZE as u32).map(move |z| (x, y, z))))
+        {
+            chunk.set(&uvec3(x, y, z).try_into().unwrap(), Some(1));
+        }
+        assert!(chunk.is_uniform());
+        assert_eq!(chunk[&uvec3(7, 7, 7).try_into().unwrap()], Some(1));
+    }
+
+    #[test]
+    fn codec_round_trip() {
+        let mut chunk = Chunk::<u16, u32>::new(42);
+        chunk.set(&uvec3(0, 0, 0).try_into().unwrap(), Some(1));
+        chunk.set(&uvec3(3, 4, 5).try_into().unwrap(), Some(1000));
+
+        let mut bytes = Vec::new();
+        chunk.encode(&mut bytes).unwrap();
+        let decoded = Chunk::<u16, u32>::decode(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(*decoded.get_metadata(), 42);
+        assert!(chunk.into_iter().zip(decoded.into_iter()).all(|((_, a), (_, b))| a == b));
+    }
+
+    #[test]
+    fn codec_rejects_unknown_version() {
+        let mut chunk = Chunk::<u8, ()>::new(());
+        chunk.set(&uvec3(1, 1, 1).try_into().unwrap(), Some(1));
+        let mut bytes = Vec::new();
+        chunk.encode(&mut bytes).unwrap();
+        bytes[0] = 255;
+        assert!(Chunk::<u8, ()>::decode(&mut bytes.as_slice()).is_err());
+
+        // Блоки без поддержки старых версий формата не читаются из старых чанков
+        bytes[0] = CHUNK_FORMAT_VERSION - 1;
+        assert!(Chunk::<u8, ()>::decode(&mut bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,174 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use bevy_math::{IVec3, ivec3};
+use crate::{AbsoluteBlockPos, ChunkPos};
+
+/// Обходит чанки области вокруг центра от ближних к дальним по евклидову расстоянию, см [ChunkPos::spiral].
+///
+/// Область ограничена радиусами: смещение чанка от центра по горизонтали (x и y) меньше `radius`, по z меньше
+/// `vertical_radius`. Форму области внутри радиусов задает `contains`, получающий смещение чанка от центра.
+/// Чанки перебираются слоями кубов вокруг центра, поэтому первые чанки доступны сразу, без перебора всей области
+pub struct ChunkSpiralIter<F> {
+    center: ChunkPos,
+    radius: u32,
+    vertical_radius: u32,
+    contains: F,
+
+    /// Следующий слой, все чанки в нем отстоят от центра на `shell` хотя бы по одной оси
+    shell: i32,
+
+    /// Чанки пройденных слоев которые еще не отданы, по квадрату расстояния до центра
+    pending: BinaryHeap<Reverse<(i32, [i32; 3])>>,
+}
+
+impl<F: Fn(IVec3) -> bool> ChunkSpiralIter<F> {
+    pub fn new(center: ChunkPos, radius: u32, vertical_radius: u32, contains: F) -> Self {
+        Self {
+            center,
+            radius,
+            vertical_radius,
+            contains,
+            shell: 0,
+            pending: BinaryHeap::new(),
+        }
+    }
+
+    fn push_shell(&mut self) {
+        let k = self.shell;
+        let (r, vr) = (self.radius as i64, self.vertical_radius as i64);
+        for z in -k..=k {
+            for y in -k..=k {
+                // Внутри слоя нужны только крайние чанки ряда, если ряд не лежит на грани куба целиком
+                let step = if z.abs() == k || y.abs() == k { 1 } else { 2 * k as usize };
+                for x in (-k..=k).step_by(step) {
+                    let offset = ivec3(x, y, z);
+                    let in_radius = (x.abs() as i64) < r && (y.abs() as i64) < r && (z.abs() as i64) < vr;
+                    if in_radius && (self.contains)(offset) {
+                        self.pending.push(Reverse((offset.length_squared(), offset.to_array())));
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<F: Fn(IVec3) -> bool> Iterator for ChunkSpiralIter<F> {
+    type Item = ChunkPos;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let last_shell = self.radius.max(self.vertical_radius) as i32 - 1;
+        loop {
+            // Непройденные слои лежат не ближе `shell`, поэтому более близкие чанки уже можно отдавать
+            if let Some(Reverse((distance, _))) = self.pending.peek() {
+                if self.shell > last_shell || *distance <= self.shell * self.shell {
+                    let Reverse((_, offset)) = self.pending.pop().unwrap();
+                    return Some((*self.center + IVec3::from_array(offset)).into());
+                }
+            }
+            if self.shell > last_shell {
+                return None;
+            }
+            self.push_shell();
+            self.shell += 1;
+        }
+    }
+}
+
+/// Обходит чанки пересекающиеся с областью блоков, см [ChunkPos::intersecting]
+pub struct ChunkAabbIter {
+    min: IVec3,
+    max: IVec3,
+    next: Option<IVec3>,
+}
+
+impl ChunkAabbIter {
+    /// Область задается крайними блоками `min` и `max`, оба входят в нее
+    pub fn new(min: AbsoluteBlockPos, max: AbsoluteBlockPos) -> Self {
+        let (min, max) = (*ChunkPos::from(min), *ChunkPos::from(max));
+        let (min, max) = (min.min(max), min.max(max));
+        Self { min, max, next: Some(min) }
+    }
+}
+
+impl Iterator for ChunkAabbIter {
+    type Item = ChunkPos;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pos = self.next?;
+        let mut next = pos;
+        next.x += 1;
+        if next.x > self.max.x {
+            next.x = self.min.x;
+            next.y += 1;
+            if next.y > self.max.y {
+                next.y = self.min.y;
+                next.z += 1;
+            }
+        }
+        self.next = (next.z <= self.max.z).then_some(next);
+        Some(pos.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use bevy_math::{ivec3, IVec3};
+    use crate::ChunkPos;
+
+    #[test]
+    fn spiral_is_ordered_by_distance() {
+        let center: ChunkPos = ivec3(5, -3, 2).into();
+        let shapes: [fn(IVec3) -> bool; 3] = [
+            |_| true,
+            |offset| offset.x * offset.x + offset.y * offset.y < 9,
+            |offset| offset.z >= 0,
+        ];
+        for contains in shapes {
+            for (radius, vertical_radius) in [(0, 0), (1, 1), (3, 3), (4, 1), (1, 5), (6, 0)] {
+                let chunks: Vec<ChunkPos> = center.spiral(radius, vertical_radius, contains).collect();
+                let distances: Vec<i32> = chunks.iter().map(|pos| center.distance_squared(**pos)).collect();
+                assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+
+                // Обход совпадает с полным перебором области
+                let r = radius as i32;
+                let vr = vertical_radius as i32;
+                let expected: HashSet<IVec3> = (1 - r..r)
+                    .flat_map(|x| (1 - r..r).flat_map(move |y| (1 - vr..vr).map(move |z| ivec3(x, y, z))))
+                    .filter(|offset| contains(*offset))
+                    .collect();
+                let offsets: HashSet<IVec3> = chunks.iter().map(|pos| **pos - *center).collect();
+                assert_eq!(offsets.len(), chunks.len());
+                assert_eq!(offsets, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn spiral_area_is_limited_by_radii() {
+        let count = |radius: u32, vertical_radius: u32, contains: fn(IVec3) -> bool| {
+            ChunkPos::default().spiral(radius, vertical_radius, contains).count()
+        };
+        assert_eq!(count(3, 2, |_| true), 5 * 5 * 3);
+        assert_eq!(count(3, 2, |offset| offset.z == 0), 5 * 5);
+        assert_eq!(count(1, 1, |_| true), 1);
+        assert_eq!(count(0, 2, |_| true), 0);
+        assert_eq!(count(2, 0, |_| true), 0);
+        assert_eq!(ChunkPos::default().spiral(3, 3, |_| true).next(), Some(ChunkPos::default()));
+    }
+
+    #[test]
+    fn aabb_chunks() {
+        let (min, max) = (ivec3(-1, 0, 15), ivec3(16, 15, 16));
+        let chunks: Vec<ChunkPos> = ChunkPos::intersecting(min.into(), max.into()).collect();
+        assert_eq!(chunks.len(), 6);
+        assert!(chunks.contains(&ivec3(-1, 0, 0).into()));
+        assert!(chunks.contains(&ivec3(1, 0, 1).into()));
+
+        // Порядок крайних блоков не важен
+        let reversed: Vec<ChunkPos> = ChunkPos::intersecting(max.into(), min.into()).collect();
+        assert_eq!(reversed, chunks);
+
+        assert_eq!(ChunkPos::intersecting(ivec3(3, 3, 3).into(), ivec3(3, 3, 3).into()).count(), 1);
+    }
+}
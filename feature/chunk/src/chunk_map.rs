@@ -1,12 +1,207 @@
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock};
-use bevy_derive::{Deref, DerefMut};
+use bevy_math::{IVec3, ivec3};
 use crate::{Chunk, ChunkPos};
 
 /// Карта с чанками
-pub type ChunkMap<BLOCK, METADATA> = Arc<RwLock<ChunkMapInternal<BLOCK, METADATA>>>;
+pub type ChunkMap<BLOCK, METADATA> = Arc<ShardedChunkMap<Arc<RwLock<Chunk<BLOCK, METADATA>>>>>;
 
-#[derive(Default, Deref, DerefMut)]
-pub struct ChunkMapInternal<BLOCK, METADATA> {
-    chunks: HashMap<ChunkPos, Arc<RwLock<Chunk<BLOCK, METADATA>>>>,
+const SHARD_BITS: u32 = 5;
+const SHARD_COUNT: usize = 1 << SHARD_BITS;
+
+/// Потокобезопасная карта значений по позициям чанков.
+///
+/// Позиции распределены по шардам, у каждого шарда своя блокировка, поэтому чтения из фоновых задач почти не
+/// пересекаются с добавлением и удалением чанков в основном потоке. Значения отдаются копиями, обычно это [Arc],
+/// так что шард блокируется только на время поиска. Одновременно удерживается не больше одной блокировки шарда
+pub struct ShardedChunkMap<T> {
+    shards: Vec<RwLock<HashMap<ChunkPos, T>>>,
+}
+
+// Ручная реализация, derive требовал бы Default от T
+impl<T> Default for ShardedChunkMap<T> {
+    fn default() -> Self {
+        Self { shards: (0..SHARD_COUNT).map(|_| RwLock::new(HashMap::new())).collect() }
+    }
+}
+
+impl<T> ShardedChunkMap<T> {
+    pub fn contains_key(&self, pos: &ChunkPos) -> bool {
+        self.shard(pos).read().unwrap().contains_key(pos)
+    }
+
+    /// Добавляет значение, возвращает предыдущее значение по этой позиции
+    pub fn insert(&self, pos: ChunkPos, value: T) -> Option<T> {
+        self.shard(&pos).write().unwrap().insert(pos, value)
+    }
+
+    pub fn remove(&self, pos: &ChunkPos) -> Option<T> {
+        self.shard(pos).write().unwrap().remove(pos)
+    }
+
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().unwrap().len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shards.iter().all(|shard| shard.read().unwrap().is_empty())
+    }
+
+    /// Позиции всех значений. Шарды читаются по очереди, поэтому изменения сделанные во время обхода могут как
+    /// попасть, так и не попасть в результат
+    pub fn keys(&self) -> Vec<ChunkPos> {
+        self.shards.iter().flat_map(|shard| shard.read().unwrap().keys().copied().collect::<Vec<_>>()).collect()
+    }
+
+    fn shard(&self, pos: &ChunkPos) -> &RwLock<HashMap<ChunkPos, T>> {
+        &self.shards[shard_index(pos)]
+    }
+}
+
+impl<T: Clone> ShardedChunkMap<T> {
+    pub fn get(&self, pos: &ChunkPos) -> Option<T> {
+        self.shard(pos).read().unwrap().get(pos).cloned()
+    }
+
+    /// Возвращает значения чанка `center` и 26 его соседей. Каждый нужный шард блокируется один раз
+    pub fn neighbourhood(&self, center: ChunkPos) -> ChunkNeighbourhood<T> {
+        let mut order: Vec<(usize, usize)> = (0..NEIGHBOURHOOD_SIZE)
+            .map(|index| (shard_index(&(*center + neighbourhood_offset(index)).into()), index))
+            .collect();
+        order.sort_unstable();
+
+        let mut chunks: [Option<T>; NEIGHBOURHOOD_SIZE] = std::array::from_fn(|_| None);
+        for group in order.chunk_by(|a, b| a.0 == b.0) {
+            let shard = self.shards[group[0].0].read().unwrap();
+            for (_, index) in group {
+                chunks[*index] = shard.get(&(*center + neighbourhood_offset(*index)).into()).cloned();
+            }
+        }
+        ChunkNeighbourhood { center, chunks }
+    }
+}
+
+/// Соседние чанки обычно попадают в разные шарды
+fn shard_index(pos: &ChunkPos) -> usize {
+    let hash = (pos.x as u32).wrapping_mul(0x9E37_79B1)
+        ^ (pos.y as u32).wrapping_mul(0x85EB_CA77)
+        ^ (pos.z as u32).wrapping_mul(0xC2B2_AE3D);
+    (hash >> (32 - SHARD_BITS)) as usize
+}
+
+const NEIGHBOURHOOD_SIZE: usize = 27;
+
+fn neighbourhood_offset(index: usize) -> IVec3 {
+    let index = index as i32;
+    ivec3(index % 3 - 1, index / 3 % 3 - 1, index / 9 - 1)
+}
+
+/// Снимок чанка и 26 его соседей, см [ShardedChunkMap::neighbourhood]
+pub struct ChunkNeighbourhood<T> {
+    center: ChunkPos,
+    chunks: [Option<T>; NEIGHBOURHOOD_SIZE],
+}
+
+impl<T> ChunkNeighbourhood<T> {
+    pub fn center_pos(&self) -> ChunkPos {
+        self.center
+    }
+
+    pub fn center(&self) -> Option<&T> {
+        self.get(&self.center)
+    }
+
+    /// Возвращает значение чанка `pos`, [None] если чанк не был загружен или не входит в окрестность
+    pub fn get(&self, pos: &ChunkPos) -> Option<&T> {
+        let offset = **pos - *self.center;
+        if offset.abs().max_element() > 1 {
+            return None;
+        }
+        let offset = offset + IVec3::ONE;
+        self.chunks[(offset.z * 9 + offset.y * 3 + offset.x) as usize].as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, mpsc, RwLock};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::time::Duration;
+    use bevy_math::{ivec3, uvec3};
+    use crate::{Chunk, ChunkMap, ChunkPos};
+
+    #[test]
+    fn neighbourhood_contains_loaded_neighbours() {
+        let chunk_map = ChunkMap::<u8, ()>::default();
+        for pos in [ivec3(0, 0, 0), ivec3(1, 1, 1), ivec3(-1, 0, 1), ivec3(2, 0, 0)] {
+            chunk_map.insert(pos.into(), Arc::new(RwLock::new(Chunk::new(()))));
+        }
+        assert_eq!(chunk_map.len(), 4);
+        assert!(chunk_map.contains_key(&ivec3(2, 0, 0).into()));
+
+        let neighbourhood = chunk_map.neighbourhood(ivec3(0, 0, 0).into());
+        assert!(neighbourhood.center().is_some());
+        assert!(neighbourhood.get(&ivec3(1, 1, 1).into()).is_some());
+        assert!(neighbourhood.get(&ivec3(-1, 0, 1).into()).is_some());
+        assert!(neighbourhood.get(&ivec3(1, 0, 0).into()).is_none());
+        // Чанк за пределами окрестности не попадает в снимок
+        assert!(neighbourhood.get(&ivec3(2, 0, 0).into()).is_none());
+
+        chunk_map.remove(&ivec3(1, 1, 1).into());
+        assert!(neighbourhood.get(&ivec3(1, 1, 1).into()).is_some());
+        assert!(chunk_map.neighbourhood(ivec3(0, 0, 0).into()).get(&ivec3(1, 1, 1).into()).is_none());
+        assert_eq!(chunk_map.keys().len(), 3);
+    }
+
+    /// Несколько потоков строящих окрестности чанков, как задачи построения мешей, работают одновременно с
+    /// добавлением, изменением и удалением чанков
+    #[test]
+    fn concurrent_readers_and_writers() {
+        let chunk_map = ChunkMap::<u8, ()>::default();
+        let stop = Arc::new(AtomicBool::new(false));
+        let block_pos = uvec3(0, 0, 0).try_into().unwrap();
+
+        let readers: Vec<_> = (0..4).map(|reader| {
+            let chunk_map = Arc::clone(&chunk_map);
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    for x in -4..4 {
+                        let neighbourhood = chunk_map.neighbourhood(ivec3(x, reader, 0).into());
+                        for dx in -1..=1 {
+                            let pos: ChunkPos = ivec3(x + dx, reader, 1).into();
+                            if let Some(chunk) = neighbourhood.get(&pos) {
+                                assert!(chunk.read().unwrap()[&block_pos].is_none_or(|block| block == 1));
+                            }
+                        }
+                    }
+                }
+            })
+        }).collect();
+
+        let writers: Vec<_> = (0..2).map(|writer| {
+            let chunk_map = Arc::clone(&chunk_map);
+            std::thread::spawn(move || {
+                for iteration in 0..2000 {
+                    let pos: ChunkPos = ivec3(iteration % 8 - 4, iteration % 4, writer).into();
+                    match chunk_map.get(&pos) {
+                        Some(chunk) if iteration % 3 == 0 => { chunk.write().unwrap().set(&block_pos, Some(1)) }
+                        Some(_) => { chunk_map.remove(&pos); }
+                        None => { chunk_map.insert(pos, Arc::new(RwLock::new(Chunk::new(())))); }
+                    }
+                }
+            })
+        }).collect();
+
+        // Потоки ожидаются в отдельном потоке, поэтому взаимная блокировка завершает тест по таймауту
+        let (done_sender, done_receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let writers_ok = writers.into_iter().all(|writer| writer.join().is_ok());
+            stop.store(true, Ordering::Relaxed);
+            let readers_ok = readers.into_iter().all(|reader| reader.join().is_ok());
+            done_sender.send(writers_ok && readers_ok).unwrap();
+        });
+        let result = done_receiver.recv_timeout(Duration::from_secs(60));
+        assert_eq!(result, Ok(true), "Chunk map threads failed or deadlocked");
+    }
 }
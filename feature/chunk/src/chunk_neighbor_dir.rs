@@ -3,7 +3,7 @@ use bevy_math::IVec3;
 use strum_macros::EnumIter;
 use crate::ChunkPos;
 
-#[derive(EnumIter, Copy, Clone)]
+#[derive(EnumIter, Copy, Clone, PartialEq, Eq, Debug)]
 pub enum ChunkNeighborDir {
     PosX,
     NegX,
@@ -13,19 +13,25 @@ pub enum ChunkNeighborDir {
     NegZ,
 }
 
-/// Сумма [ChunkPos] + [ChunkNeighborDir] = [ChunkPos]
-impl Add<ChunkNeighborDir> for ChunkPos {
-    type Output = ChunkPos;
-
-    fn add(self, rhs: ChunkNeighborDir) -> Self::Output {
-        let vec = match rhs {
+impl From<ChunkNeighborDir> for IVec3 {
+    fn from(value: ChunkNeighborDir) -> Self {
+        match value {
             ChunkNeighborDir::PosX => { IVec3::X }
             ChunkNeighborDir::NegX => { IVec3::NEG_X }
             ChunkNeighborDir::PosY => { IVec3::Y }
             ChunkNeighborDir::NegY => { IVec3::NEG_Y }
             ChunkNeighborDir::PosZ => { IVec3::Z }
             ChunkNeighborDir::NegZ => { IVec3::NEG_Z }
-        };
+        }
+    }
+}
+
+/// Сумма [ChunkPos] + [ChunkNeighborDir] = [ChunkPos]
+impl Add<ChunkNeighborDir> for ChunkPos {
+    type Output = ChunkPos;
+
+    fn add(self, rhs: ChunkNeighborDir) -> Self::Output {
+        let vec: IVec3 = rhs.into();
         (*(self.deref()) + vec).into()
     }
 }
@@ -2,15 +2,41 @@ use std::ops::Deref;
 use bevy_math::{IVec3, ivec3};
 use crate::ChunkPos;
 
+/// Какие соседи чанка перечисляются, см [ChunkPos::get_neighbors]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ChunkNeighbors {
+    /// Соседи по граням, 6 штук
+    Faces,
+
+    /// Соседи по граням и ребрам, 18 штук
+    Edges,
+
+    /// Все соседи включая соседей по вершинам, 26 штук
+    All,
+}
+
+impl ChunkNeighbors {
+    /// Сколько координат смещения соседа могут быть ненулевыми
+    fn max_shifted_axes(&self) -> i32 {
+        match self {
+            ChunkNeighbors::Faces => { 1 }
+            ChunkNeighbors::Edges => { 2 }
+            ChunkNeighbors::All => { 3 }
+        }
+    }
+}
+
 pub struct ChunkNeighborsIter {
     center: ChunkPos,
-    index: u32,
+    neighbors: ChunkNeighbors,
+    index: i32,
 }
 
 impl ChunkNeighborsIter {
-    pub fn new(center: ChunkPos) -> Self {
+    pub fn new(center: ChunkPos, neighbors: ChunkNeighbors) -> Self {
         Self {
             center,
+            neighbors,
             index: 0,
         }
     }
@@ -23,14 +49,36 @@ impl Iterator for ChunkNeighborsIter {
     type Item = ChunkPos;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match self.index {
-            0 => { Some(self.get_delta(ivec3(1, 0, 0))) }
-            1 => { Some(self.get_delta(ivec3(-1, 0, 0))) }
-            2 => { Some(self.get_delta(ivec3(0, 1, 0))) }
-            3 => { Some(self.get_delta(ivec3(0, -1, 0))) }
-            4 => { Some(self.get_delta(ivec3(0, 0, 1))) }
-            5 => { Some(self.get_delta(ivec3(0, 0, -1))) }
-            _ => { None }
+        // Перебираем куб 3x3x3 вокруг чанка, пропуская сам чанк и лишних соседей
+        while self.index < 27 {
+            let delta = ivec3(self.index % 3 - 1, self.index / 3 % 3 - 1, self.index / 9 - 1);
+            self.index += 1;
+            let shifted_axes = delta.abs().dot(IVec3::ONE);
+            if shifted_axes > 0 && shifted_axes <= self.neighbors.max_shifted_axes() {
+                return Some(self.get_delta(delta));
+            }
         }
+        None
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::ivec3;
+    use crate::{ChunkNeighbors, ChunkPos};
+
+    #[test]
+    fn neighbors_count() {
+        let center: ChunkPos = ivec3(3, -2, 7).into();
+        for (neighbors, count) in [(ChunkNeighbors::Faces, 6), (ChunkNeighbors::Edges, 18), (ChunkNeighbors::All, 26)] {
+            let positions: Vec<ChunkPos> = center.get_neighbors(neighbors).collect();
+            assert_eq!(positions.len(), count);
+            assert!(positions.iter().all(|pos| (**pos - *center).abs().max_element() == 1));
+        }
+        let faces: Vec<ChunkPos> = center.get_neighbors(ChunkNeighbors::Faces).collect();
+        assert!(faces.contains(&ivec3(3, -2, 8).into()));
+        assert!(!faces.contains(&ivec3(4, -1, 7).into()));
+        assert!(center.get_neighbors(ChunkNeighbors::Edges).any(|pos| pos == ivec3(4, -1, 7).into()));
+        assert!(!center.get_neighbors(ChunkNeighbors::Edges).any(|pos| pos == ivec3(4, -1, 8).into()));
+    }
+}
@@ -3,7 +3,8 @@ use bevy_derive::{Deref, DerefMut};
 use bevy_math::{IVec3, ivec3};
 use crate::absolute_block_pos::AbsoluteBlockPos;
 use crate::chunk::CHUNK_SIZE;
-use crate::chunk_neighbors_iter::ChunkNeighborsIter;
+use crate::chunk_area_iter::{ChunkAabbIter, ChunkSpiralIter};
+use crate::chunk_neighbors_iter::{ChunkNeighbors, ChunkNeighborsIter};
 use crate::ChunkBlockPos;
 
 /// Координаты чанка в сетке чанков.
@@ -33,9 +34,20 @@ impl ChunkPos {
         local_pos.try_into()
     }
 
-    /// Возвращает итератор из координат чанков окружающих этот (6 штук)
-    pub fn get_neighbors(&self) -> ChunkNeighborsIter {
-        ChunkNeighborsIter::new(*self)
+    /// Возвращает итератор из координат чанков окружающих этот, 6, 18 или 26 штук
+    pub fn get_neighbors(&self, neighbors: ChunkNeighbors) -> ChunkNeighborsIter {
+        ChunkNeighborsIter::new(*self, neighbors)
+    }
+
+    /// Возвращает чанки области вокруг этого чанка от ближних к дальним: смещение чанка по горизонтали меньше
+    /// `radius`, по вертикали меньше `vertical_radius` и `contains` для смещения возвращает true
+    pub fn spiral<F: Fn(IVec3) -> bool>(&self, radius: u32, vertical_radius: u32, contains: F) -> ChunkSpiralIter<F> {
+        ChunkSpiralIter::new(*self, radius, vertical_radius, contains)
+    }
+
+    /// Возвращает чанки пересекающиеся с областью блоков от `min` до `max` включительно
+    pub fn intersecting(min: AbsoluteBlockPos, max: AbsoluteBlockPos) -> ChunkAabbIter {
+        ChunkAabbIter::new(min, max)
     }
 
     /// Возвращает абсолютные координаты чанка в мире
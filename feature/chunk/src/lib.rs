@@ -6,10 +6,22 @@ mod chunk_map;
 mod absolute_block_pos;
 mod chunk_neighbors_iter;
 mod chunk_neighbor_dir;
+mod chunk_area_iter;
+mod palette_storage;
+mod binary_codec;
+mod region_file;
+mod region_storage;
+mod raycast;
 
-pub use chunk::{Chunk, CHUNK_SIZE};
+pub use chunk::{Chunk, CHUNK_SIZE, CHUNK_FORMAT_VERSION};
 pub use chunk_block_pos::ChunkBlockPos;
 pub use chunk_pos::ChunkPos;
-pub use chunk_map::ChunkMap;
+pub use chunk_map::{ChunkMap, ChunkNeighbourhood, ShardedChunkMap};
 pub use absolute_block_pos::AbsoluteBlockPos;
 pub use chunk_neighbor_dir::ChunkNeighborDir;
+pub use chunk_neighbors_iter::ChunkNeighbors;
+pub use palette_storage::PaletteStorage;
+pub use binary_codec::BinaryCodec;
+pub use region_file::{RegionFile, RegionPos, REGION_SIZE, REGION_HEIGHT, REGION_FORMAT_VERSION};
+pub use region_storage::RegionStorage;
+pub use raycast::{raycast, RaycastHit};
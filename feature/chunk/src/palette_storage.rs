@@ -0,0 +1,511 @@
+use std::io;
+use std::io::{Read, Write};
+use crate::binary_codec::{BinaryCodec, invalid_data};
+use crate::chunk::CHUNK_SIZE;
+
+/// Количество элементов в хранилище (по одному на каждый блок чанка)
+pub const PALETTE_STORAGE_SIZE: usize = CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE;
+
+/// Количество бит в одном слове упакованных индексов
+const WORD_BITS: usize = u64::BITS as usize;
+
+/// Элемент палитры
+#[derive(Clone)]
+struct PaletteEntry<T> {
+    value: T,
+
+    /// Количество элементов хранилища ссылающихся на эту запись палитры
+    count: u32,
+}
+
+/// Хранилище значений с палитрой.
+///
+/// Вместо плотного массива значений хранит палитру из различных значений и упакованные в [u64] индексы на эту
+/// палитру, ширина индекса в битах подбирается по размеру палитры. Если все значения одинаковы (например чанк
+/// целиком состоит из воздуха), то индексы не хранятся вовсе.
+///
+/// Палитра автоматически перепаковывается при росте и уменьшении количества различных значений.
+#[derive(Clone)]
+pub struct PaletteStorage<T> {
+    /// Палитра, [None] означает свободный слот
+    palette: Vec<Option<PaletteEntry<T>>>,
+
+    /// Ширина одного индекса в битах, 0 означает что все значения хранилища одинаковы
+    bits: usize,
+
+    /// Упакованные индексы на элементы [Self::palette]
+    data: Vec<u64>,
+
+    /// Элемент палитры выданный через [Self::get_mut] и позиция которая на него ссылается.
+    ///
+    /// Значение по ссылке могло поменяться, поэтому перед следующим изменением хранилища этот элемент нужно
+    /// объединить с совпадающим элементом палитры, если такой есть, см [Self::commit_dirty]
+    dirty: Option<(usize, usize)>,
+}
+
+impl<T> PaletteStorage<T> {
+    /// Создает хранилище заполненное одинаковыми значениями
+    pub fn new(value: T) -> Self {
+        Self {
+            palette: vec![Some(PaletteEntry { value, count: PALETTE_STORAGE_SIZE as u32 })],
+            bits: 0,
+            data: Vec::new(),
+            dirty: None,
+        }
+    }
+
+    /// Возвращает значение по индексу
+    pub fn get(&self, index: usize) -> &T {
+        &self.entry(self.read_index(index)).value
+    }
+
+    /// Возвращает количество различных значений в хранилище
+    pub fn palette_len(&self) -> usize {
+        self.palette.iter().filter(|entry| entry.is_some()).count()
+    }
+
+    /// Возвращает ширину индекса в битах
+    pub fn bits_per_index(&self) -> usize {
+        self.bits
+    }
+
+    /// Возвращает true если все значения в хранилище одинаковы
+    pub fn is_uniform(&self) -> bool {
+        self.bits == 0
+    }
+
+    fn entry(&self, palette_index: usize) -> &PaletteEntry<T> {
+        self.palette[palette_index].as_ref().unwrap()
+    }
+
+    fn entry_mut(&mut self, palette_index: usize) -> &mut PaletteEntry<T> {
+        self.palette[palette_index].as_mut().unwrap()
+    }
+
+    fn read_index(&self, index: usize) -> usize {
+        assert!(index < PALETTE_STORAGE_SIZE);
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = WORD_BITS / self.bits;
+        let word = self.data[index / per_word];
+        let shift = (index % per_word) * self.bits;
+        ((word >> shift) & ((1u64 << self.bits) - 1)) as usize
+    }
+
+    fn write_index(&mut self, index: usize, palette_index: usize) {
+        let per_word = WORD_BITS / self.bits;
+        let shift = (index % per_word) * self.bits;
+        let mask = ((1u64 << self.bits) - 1) << shift;
+        let word = &mut self.data[index / per_word];
+        *word = (*word & !mask) | ((palette_index as u64) << shift);
+    }
+
+    /// Выделяет новый элемент палитры, при необходимости перепаковывает индексы в более широкие
+    fn allocate(&mut self, value: T) -> usize {
+        let entry = Some(PaletteEntry { value, count: 0 });
+        let palette_index = match self.palette.iter().position(|entry| entry.is_none()) {
+            None => {
+                self.palette.push(entry);
+                self.palette.len() - 1
+            }
+            Some(palette_index) => {
+                self.palette[palette_index] = entry;
+                palette_index
+            }
+        };
+        if palette_index >= 1 << self.bits {
+            self.repack(bits_for(palette_index + 1), None);
+        }
+        palette_index
+    }
+
+    /// Уменьшает счетчик ссылок на элемент палитры, освобождает его если ссылок больше нет и при необходимости
+    /// перепаковывает индексы в более узкие
+    fn release(&mut self, palette_index: usize) {
+        let entry = self.entry_mut(palette_index);
+        entry.count -= 1;
+        if entry.count != 0 {
+            return;
+        }
+        self.palette[palette_index] = None;
+
+        // Не сжимаем сразу при переходе через границу, иначе чередование записей будет постоянно перепаковывать
+        // хранилище туда и обратно
+        let len = self.palette_len();
+        let bits = bits_for(len);
+        if bits == 0 || bits + 1 < self.bits {
+            let remap = self.compact();
+            self.repack(bits, Some(&remap));
+        }
+    }
+
+    /// Удаляет свободные слоты из палитры, возвращает отображение старых индексов палитры на новые
+    fn compact(&mut self) -> Vec<usize> {
+        let mut remap = vec![0; self.palette.len()];
+        let mut new_index = 0;
+        for (old_index, entry) in self.palette.iter().enumerate() {
+            if entry.is_some() {
+                remap[old_index] = new_index;
+                new_index += 1;
+            }
+        }
+        self.palette.retain(|entry| entry.is_some());
+        remap
+    }
+
+    /// Перепаковывает индексы с новой шириной, опционально перенумеровывая их
+    fn repack(&mut self, bits: usize, remap: Option<&[usize]>) {
+        let indexes: Vec<usize> = (0..PALETTE_STORAGE_SIZE)
+            .map(|index| {
+                let palette_index = self.read_index(index);
+                remap.map(|remap| remap[palette_index]).unwrap_or(palette_index)
+            })
+            .collect();
+
+        self.bits = bits;
+        self.data.clear();
+        if bits == 0 {
+            self.data.shrink_to_fit();
+            return;
+        }
+
+        let per_word = WORD_BITS / bits;
+        self.data.resize(PALETTE_STORAGE_SIZE.div_ceil(per_word), 0);
+        for (index, palette_index) in indexes.into_iter().enumerate() {
+            self.write_index(index, palette_index);
+        }
+    }
+}
+
+impl<T: Clone + PartialEq> PaletteStorage<T> {
+    /// Устанавливает значение по индексу
+    pub fn set(&mut self, index: usize, value: T) {
+        self.commit_dirty();
+
+        let old_palette_index = self.read_index(index);
+        if self.entry(old_palette_index).value == value {
+            return;
+        }
+
+        let palette_index = match self.find(&value, None) {
+            None => { self.allocate(value) }
+            Some(palette_index) => { palette_index }
+        };
+        self.assign(index, palette_index);
+    }
+
+    /// Возвращает изменяемую ссылку на значение по индексу.
+    ///
+    /// Если значение разделяется с другими элементами хранилища, то для этого индекса заводится отдельный элемент
+    /// палитры, после изменения он будет объединен с совпадающим при следующей операции записи
+    pub fn get_mut(&mut self, index: usize) -> &mut T {
+        self.commit_dirty();
+
+        let mut palette_index = self.read_index(index);
+        if self.entry(palette_index).count != 1 {
+            let value = self.entry(palette_index).value.clone();
+            palette_index = self.allocate(value);
+            self.assign(index, palette_index);
+        }
+        self.dirty = Some((palette_index, index));
+        &mut self.entry_mut(palette_index).value
+    }
+
+    /// Объединяет элемент палитры выданный через [Self::get_mut] с совпадающим элементом, если такой есть
+    fn commit_dirty(&mut self) {
+        if let Some((dirty_palette_index, index)) = self.dirty.take() {
+            let value = &self.entry(dirty_palette_index).value;
+            if let Some(palette_index) = self.find(value, Some(dirty_palette_index)) {
+                self.assign(index, palette_index);
+            }
+        }
+    }
+
+    /// Ищет элемент палитры с переданным значением
+    fn find(&self, value: &T, exclude: Option<usize>) -> Option<usize> {
+        self.palette.iter().enumerate().position(|(palette_index, entry)| {
+            Some(palette_index) != exclude && entry.as_ref().map(|entry| entry.value == *value).unwrap_or(false)
+        })
+    }
+
+    /// Переводит элемент хранилища на другой элемент палитры
+    fn assign(&mut self, index: usize, palette_index: usize) {
+        let old_palette_index = self.read_index(index);
+        self.entry_mut(palette_index).count += 1;
+        self.write_index(index, palette_index);
+        self.release(old_palette_index);
+    }
+}
+
+/// Формат: размер палитры (u16), элементы палитры, ширина индекса (u8), упакованные индексы.
+/// Свободные слоты палитры не записываются, индексы перенумеровываются
+impl<T: BinaryCodec> BinaryCodec for PaletteStorage<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut remap = vec![0; self.palette.len()];
+        let mut len = 0;
+        for (palette_index, entry) in self.palette.iter().enumerate() {
+            if entry.is_some() {
+                remap[palette_index] = len;
+                len += 1;
+            }
+        }
+
+        (len as u16).encode(writer)?;
+        for entry in self.palette.iter().flatten() {
+            entry.value.encode(writer)?;
+        }
+
+        let bits = bits_for(len);
+        (bits as u8).encode(writer)?;
+        if bits == 0 {
+            return Ok(());
+        }
+
+        let per_word = WORD_BITS / bits;
+        let mut word = 0u64;
+        for index in 0..PALETTE_STORAGE_SIZE {
+            word |= (remap[self.read_index(index)] as u64) << ((index % per_word) * bits);
+            if index % per_word == per_word - 1 || index == PALETTE_STORAGE_SIZE - 1 {
+                word.encode(writer)?;
+                word = 0;
+            }
+        }
+        Ok(())
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Self::decode_with(reader, T::decode)
+    }
+
+    fn decode_legacy<R: Read>(reader: &mut R, version: u8) -> io::Result<Self> {
+        Self::decode_with(reader, |reader| T::decode_legacy(reader, version))
+    }
+}
+
+impl<T: BinaryCodec> PaletteStorage<T> {
+    /// Читает хранилище, элементы палитры читаются через `decode_value`
+    fn decode_with<R: Read>(reader: &mut R, decode_value: impl Fn(&mut R) -> io::Result<T>) -> io::Result<Self> {
+        let len = u16::decode(reader)? as usize;
+        if len == 0 || len > PALETTE_STORAGE_SIZE {
+            return Err(invalid_data("incorrect palette length"));
+        }
+
+        let mut palette = Vec::with_capacity(len);
+        for _ in 0..len {
+            palette.push(Some(PaletteEntry { value: decode_value(reader)?, count: 0 }));
+        }
+
+        let bits = u8::decode(reader)? as usize;
+        if bits != bits_for(len) {
+            return Err(invalid_data("incorrect palette index width"));
+        }
+
+        let mut storage = PaletteStorage { palette, bits, data: Vec::new(), dirty: None };
+        if bits == 0 {
+            storage.entry_mut(0).count = PALETTE_STORAGE_SIZE as u32;
+            return Ok(storage);
+        }
+
+        for _ in 0..PALETTE_STORAGE_SIZE.div_ceil(WORD_BITS / bits) {
+            storage.data.push(u64::decode(reader)?);
+        }
+        for index in 0..PALETTE_STORAGE_SIZE {
+            let palette_index = storage.read_index(index);
+            if palette_index >= len {
+                return Err(invalid_data("palette index out of bounds"));
+            }
+            storage.entry_mut(palette_index).count += 1;
+        }
+
+        // Элементы палитры на которые никто не ссылается сразу освобождаем
+        for entry in storage.palette.iter_mut() {
+            if entry.as_ref().map(|entry| entry.count == 0).unwrap_or(false) {
+                *entry = None;
+            }
+        }
+        Ok(storage)
+    }
+}
+
+/// Возвращает минимальную ширину индекса в битах для палитры из [len] элементов
+fn bits_for(len: usize) -> usize {
+    if len <= 1 {
+        0
+    } else {
+        (usize::BITS - (len - 1).leading_zeros()) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::BinaryCodec;
+    use crate::palette_storage::{bits_for, PALETTE_STORAGE_SIZE, PaletteStorage};
+
+    #[test]
+    fn bits_for_palette_len() {
+        assert_eq!(bits_for(1), 0);
+        assert_eq!(bits_for(2), 1);
+        assert_eq!(bits_for(3), 2);
+        assert_eq!(bits_for(4), 2);
+        assert_eq!(bits_for(5), 3);
+        assert_eq!(bits_for(256), 8);
+    }
+
+    #[test]
+    fn new_storage_is_uniform() {
+        let storage = PaletteStorage::new(7);
+        assert!(storage.is_uniform());
+        assert_eq!(storage.palette_len(), 1);
+        assert!((0..PALETTE_STORAGE_SIZE).all(|index| *storage.get(index) == 7));
+    }
+
+    #[test]
+    fn set_grows_palette() {
+        let mut storage = PaletteStorage::new(0);
+        storage.set(10, 1);
+        assert_eq!(storage.bits_per_index(), 1);
+        storage.set(11, 2);
+        storage.set(12, 3);
+        assert_eq!(storage.bits_per_index(), 2);
+        storage.set(13, 4);
+        assert_eq!(storage.bits_per_index(), 3);
+
+        assert_eq!(*storage.get(9), 0);
+        assert_eq!(*storage.get(10), 1);
+        assert_eq!(*storage.get(11), 2);
+        assert_eq!(*storage.get(12), 3);
+        assert_eq!(*storage.get(13), 4);
+        assert_eq!(storage.palette_len(), 5);
+    }
+
+    #[test]
+    fn set_same_value_reuses_palette_entry() {
+        let mut storage = PaletteStorage::new(0);
+        for index in 0..100 {
+            storage.set(index, 1);
+        }
+        assert_eq!(storage.palette_len(), 2);
+        assert_eq!(storage.bits_per_index(), 1);
+    }
+
+    #[test]
+    fn overwrite_all_returns_to_uniform() {
+        let mut storage = PaletteStorage::new(0);
+        for index in 0..PALETTE_STORAGE_SIZE {
+            storage.set(index, (index % 20) as u32);
+        }
+        assert_eq!(storage.palette_len(), 20);
+        assert_eq!(storage.bits_per_index(), 5);
+
+        for index in 0..PALETTE_STORAGE_SIZE {
+            storage.set(index, 5);
+        }
+        assert!(storage.is_uniform());
+        assert_eq!(storage.palette_len(), 1);
+        assert!((0..PALETTE_STORAGE_SIZE).all(|index| *storage.get(index) == 5));
+    }
+
+    #[test]
+    fn shrink_keeps_values() {
+        let mut storage = PaletteStorage::new(0);
+        for index in 0..PALETTE_STORAGE_SIZE {
+            storage.set(index, (index % 17) as u32);
+        }
+        assert_eq!(storage.bits_per_index(), 5);
+
+        // Оставляем только значения 0, 1 и 2
+        for index in 0..PALETTE_STORAGE_SIZE {
+            if index % 17 > 2 {
+                storage.set(index, 0);
+            }
+        }
+        // Из-за гистерезиса ширина индекса уменьшается не до минимально возможной
+        assert_eq!(storage.palette_len(), 3);
+        assert_eq!(storage.bits_per_index(), 3);
+        for index in 0..PALETTE_STORAGE_SIZE {
+            let expected = if index % 17 > 2 { 0 } else { (index % 17) as u32 };
+            assert_eq!(*storage.get(index), expected);
+        }
+    }
+
+    #[test]
+    fn get_mut_changes_only_one_value() {
+        let mut storage = PaletteStorage::new(0);
+        *storage.get_mut(42) = 3;
+        assert_eq!(*storage.get(42), 3);
+        assert_eq!(*storage.get(41), 0);
+        assert_eq!(*storage.get(43), 0);
+    }
+
+    #[test]
+    fn get_mut_merges_equal_entries() {
+        let mut storage = PaletteStorage::new(0);
+        for index in 0..64 {
+            *storage.get_mut(index) = 1;
+        }
+        storage.set(64, 1);
+        assert_eq!(storage.palette_len(), 2);
+        assert!((0..=64).all(|index| *storage.get(index) == 1));
+        assert_eq!(*storage.get(65), 0);
+    }
+
+    #[test]
+    fn get_mut_back_to_same_value_returns_to_uniform() {
+        let mut storage = PaletteStorage::new(0);
+        *storage.get_mut(1) = 2;
+        *storage.get_mut(1) = 0;
+        storage.set(2, 0);
+        assert!(storage.is_uniform());
+    }
+
+    #[test]
+    fn codec_round_trip() {
+        let mut storage = PaletteStorage::new(0u32);
+        for index in 0..PALETTE_STORAGE_SIZE {
+            storage.set(index, (index % 5) as u32);
+        }
+        // Освобождаем слот в середине палитры
+        for index in 0..PALETTE_STORAGE_SIZE {
+            if index % 5 == 2 {
+                storage.set(index, 0);
+            }
+        }
+
+        let mut bytes = Vec::new();
+        storage.encode(&mut bytes).unwrap();
+        let decoded = PaletteStorage::<u32>::decode(&mut bytes.as_slice()).unwrap();
+
+        assert_eq!(decoded.palette_len(), 4);
+        assert_eq!(decoded.bits_per_index(), 2);
+        assert!((0..PALETTE_STORAGE_SIZE).all(|index| decoded.get(index) == storage.get(index)));
+    }
+
+    #[test]
+    fn codec_uniform_storage_is_compact() {
+        let storage = PaletteStorage::new(9u8);
+        let mut bytes = Vec::new();
+        storage.encode(&mut bytes).unwrap();
+        // Размер палитры, одно значение и ширина индекса
+        assert_eq!(bytes.len(), 4);
+
+        let decoded = PaletteStorage::<u8>::decode(&mut bytes.as_slice()).unwrap();
+        assert!(decoded.is_uniform());
+        assert_eq!(*decoded.get(100), 9);
+    }
+
+    #[test]
+    fn codec_rejects_index_out_of_palette() {
+        let mut bytes = Vec::new();
+        3u16.encode(&mut bytes).unwrap();
+        for value in 0..3u8 {
+            value.encode(&mut bytes).unwrap();
+        }
+        2u8.encode(&mut bytes).unwrap();
+        // Все индексы равны 3, а в палитре только 3 элемента
+        for _ in 0..PALETTE_STORAGE_SIZE / 32 {
+            u64::MAX.encode(&mut bytes).unwrap();
+        }
+        assert!(PaletteStorage::<u8>::decode(&mut bytes.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,195 @@
+use std::sync::{Arc, RwLock};
+use bevy_math::{IVec3, Vec3};
+use crate::{AbsoluteBlockPos, Chunk, ChunkMap, ChunkNeighborDir, ChunkPos};
+
+/// Результат [raycast]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct RaycastHit {
+    /// Блок в который попал луч
+    pub pos: AbsoluteBlockPos,
+
+    /// Грань блока через которую вошел луч, направлена навстречу лучу. [None] если луч начинается внутри блока
+    pub face: Option<ChunkNeighborDir>,
+
+    /// Расстояние от начала луча до точки входа в блок
+    pub distance: f32,
+}
+
+/// Проходит по блокам вдоль луча (алгоритм Amanatides & Woo) и возвращает первый блок для которого `is_hit`
+/// вернул true.
+///
+/// Луч проходит через границы чанков, блоки незагруженных чанков считаются промахом. Поиск прекращается на
+/// расстоянии `max_distance` от начала луча. Для бесконечных или NaN `origin` и `max_distance` возвращает [None],
+/// иначе обход мог бы не закончиться
+pub fn raycast<BLOCK, METADATA>(
+    chunk_map: &ChunkMap<BLOCK, METADATA>,
+    origin: Vec3,
+    direction: Vec3,
+    max_distance: f32,
+    mut is_hit: impl FnMut(&BLOCK) -> bool,
+) -> Option<RaycastHit> {
+    if !origin.is_finite() || !max_distance.is_finite() {
+        return None;
+    }
+    let direction = direction.normalize_or_zero();
+    if direction == Vec3::ZERO {
+        return None;
+    }
+
+    let mut cell = origin.floor().as_ivec3();
+    let step = IVec3::select(direction.cmpeq(Vec3::ZERO), IVec3::ZERO, direction.signum().as_ivec3());
+    // Расстояние вдоль луча между соседними границами блоков по каждой оси
+    let t_delta = direction.abs().recip();
+    // Расстояние вдоль луча до ближайшей границы блока по каждой оси
+    let mut t_max = Vec3::ZERO;
+    for axis in 0..3 {
+        t_max[axis] = match step[axis] {
+            1 => { (cell[axis] as f32 + 1. - origin[axis]) * t_delta[axis] }
+            -1 => { (origin[axis] - cell[axis] as f32) * t_delta[axis] }
+            _ => { f32::INFINITY }
+        };
+    }
+
+    let mut chunks = CachedChunk::new(chunk_map);
+    let mut distance = 0.;
+    let mut face = None;
+    loop {
+        if chunks.get_block(cell, &mut is_hit) {
+            return Some(RaycastHit { pos: cell.into(), face, distance });
+        }
+
+        let axis = if t_max.x < t_max.y && t_max.x < t_max.z {
+            0
+        } else if t_max.y < t_max.z {
+            1
+        } else {
+            2
+        };
+        distance = t_max[axis];
+        if distance > max_distance {
+            return None;
+        }
+        cell[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+        face = Some(entered_face(axis, step[axis]));
+    }
+}
+
+/// Грань через которую луч входит в блок при шаге `step` вдоль оси `axis`
+fn entered_face(axis: usize, step: i32) -> ChunkNeighborDir {
+    match (axis, step > 0) {
+        (0, true) => { ChunkNeighborDir::NegX }
+        (0, false) => { ChunkNeighborDir::PosX }
+        (1, true) => { ChunkNeighborDir::NegY }
+        (1, false) => { ChunkNeighborDir::PosY }
+        (_, true) => { ChunkNeighborDir::NegZ }
+        (_, false) => { ChunkNeighborDir::PosZ }
+    }
+}
+
+type SharedChunk<BLOCK, METADATA> = Arc<RwLock<Chunk<BLOCK, METADATA>>>;
+
+/// Запоминает последний чанк, соседние блоки луча обычно лежат в одном чанке
+struct CachedChunk<'a, BLOCK, METADATA> {
+    chunk_map: &'a ChunkMap<BLOCK, METADATA>,
+    chunk: Option<(ChunkPos, Option<SharedChunk<BLOCK, METADATA>>)>,
+}
+
+impl<'a, BLOCK, METADATA> CachedChunk<'a, BLOCK, METADATA> {
+    fn new(chunk_map: &'a ChunkMap<BLOCK, METADATA>) -> Self {
+        Self { chunk_map, chunk: None }
+    }
+
+    /// Возвращает результат `is_hit` для блока, пустые блоки и блоки незагруженных чанков считаются промахом
+    fn get_block(&mut self, pos: IVec3, is_hit: &mut impl FnMut(&BLOCK) -> bool) -> bool {
+        let chunk_pos = ChunkPos::from_global_coord(pos);
+        if !matches!(self.chunk, Some((cached_pos, _)) if cached_pos == chunk_pos) {
+            let chunk = self.chunk_map.get(&chunk_pos);
+            self.chunk = Some((chunk_pos, chunk));
+        }
+        let Some((_, Some(chunk))) = &self.chunk else {
+            return false;
+        };
+        let block_pos = chunk_pos.try_global_pos_into_chunk_pos(pos.into()).unwrap();
+        let chunk = chunk.read().unwrap();
+        chunk[&block_pos].as_ref().is_some_and(is_hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+    use bevy_math::{ivec3, IVec3, vec3, Vec3};
+    use crate::{Chunk, ChunkMap, ChunkNeighborDir, ChunkPos};
+    use crate::raycast::raycast;
+
+    /// Карта чанков в которой блоки заданы абсолютными координатами
+    fn chunk_map(blocks: &[IVec3], loaded: &[IVec3]) -> ChunkMap<u8, ()> {
+        let chunk_map = ChunkMap::<u8, ()>::default();
+        for pos in loaded {
+            chunk_map.insert((*pos).into(), Arc::new(RwLock::new(Chunk::new(()))));
+        }
+        for pos in blocks {
+            let chunk_pos = ChunkPos::from_global_coord(*pos);
+            let chunk = chunk_map.get(&chunk_pos).unwrap();
+            let block_pos = chunk_pos.try_global_pos_into_chunk_pos((*pos).into()).unwrap();
+            chunk.write().unwrap().set(&block_pos, Some(1));
+        }
+        chunk_map
+    }
+
+    #[test]
+    fn hit_along_axis() {
+        let chunk_map = chunk_map(&[ivec3(5, 0, 0)], &[IVec3::ZERO]);
+        let hit = raycast(&chunk_map, vec3(0.5, 0.5, 0.5), Vec3::X, 10., |_| true).unwrap();
+        assert_eq!(*hit.pos, ivec3(5, 0, 0));
+        assert_eq!(hit.face, Some(ChunkNeighborDir::NegX));
+        assert_eq!(hit.distance, 4.5);
+
+        assert!(raycast(&chunk_map, vec3(0.5, 0.5, 0.5), Vec3::X, 4., |_| true).is_none());
+        assert!(raycast(&chunk_map, vec3(0.5, 0.5, 0.5), Vec3::NEG_X, 10., |_| true).is_none());
+        assert!(raycast(&chunk_map, vec3(0.5, 0.5, 0.5), Vec3::X, 10., |_| false).is_none());
+    }
+
+    #[test]
+    fn hit_across_chunk_borders() {
+        let chunk_map = chunk_map(&[ivec3(-3, 2, 1)], &[IVec3::ZERO, ivec3(-1, 0, 0)]);
+        let hit = raycast(&chunk_map, vec3(2.5, 2.5, 1.5), Vec3::NEG_X, 10., |_| true).unwrap();
+        assert_eq!(*hit.pos, ivec3(-3, 2, 1));
+        assert_eq!(hit.face, Some(ChunkNeighborDir::PosX));
+        assert_eq!(hit.distance, 4.5);
+    }
+
+    #[test]
+    fn unloaded_chunks_are_misses() {
+        let chunk_map = chunk_map(&[ivec3(40, 3, 3)], &[IVec3::ZERO, ivec3(2, 0, 0)]);
+        let hit = raycast(&chunk_map, vec3(1., 3.5, 3.5), Vec3::X, 100., |_| true).unwrap();
+        assert_eq!(*hit.pos, ivec3(40, 3, 3));
+        assert_eq!(hit.distance, 39.);
+    }
+
+    #[test]
+    fn diagonal_ray_enters_through_top_face() {
+        let chunk_map = chunk_map(&[ivec3(3, 3, 0)], &[IVec3::ZERO]);
+        // Луч идет вниз под углом и проходит над блоком (2, 2, 0), не задевая его
+        let hit = raycast(&chunk_map, vec3(0.5, 0.5, 4.), vec3(1., 1., -1.), 20., |_| true).unwrap();
+        assert_eq!(*hit.pos, ivec3(3, 3, 0));
+        assert_eq!(hit.face, Some(ChunkNeighborDir::PosZ));
+        assert!((hit.distance - 3. * 3f32.sqrt()).abs() < 1e-4);
+
+        // Луч начинающийся внутри блока попадает в него сразу
+        let hit = raycast(&chunk_map, vec3(3.5, 3.5, 0.5), Vec3::Z, 20., |_| true).unwrap();
+        assert_eq!(hit.face, None);
+        assert_eq!(hit.distance, 0.);
+    }
+
+    #[test]
+    fn non_finite_rays_are_misses() {
+        let chunk_map = chunk_map(&[ivec3(5, 0, 0)], &[IVec3::ZERO]);
+        // Без проверки такие лучи никогда не достигли бы max_distance
+        assert_eq!(raycast(&chunk_map, vec3(0.5, 0.5, 0.5), Vec3::X, f32::INFINITY, |_| true), None);
+        assert_eq!(raycast(&chunk_map, vec3(0.5, 0.5, 0.5), Vec3::X, f32::NAN, |_| true), None);
+        assert_eq!(raycast(&chunk_map, vec3(f32::NAN, 0.5, 0.5), Vec3::X, 10., |_| true), None);
+        assert_eq!(raycast(&chunk_map, vec3(f32::NEG_INFINITY, 0.5, 0.5), Vec3::X, 10., |_| true), None);
+    }
+}
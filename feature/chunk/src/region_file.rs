@@ -0,0 +1,427 @@
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use bevy_derive::Deref;
+use bevy_math::{IVec3, ivec3};
+use crate::binary_codec::{BinaryCodec, invalid_data};
+use crate::ChunkPos;
+
+/// Размер региона в чанках по осям X и Y
+pub const REGION_SIZE: i32 = 32;
+
+/// Размер региона в чанках по оси Z
+pub const REGION_HEIGHT: i32 = 16;
+
+/// Количество чанков в одном регионе
+const CHUNKS_PER_REGION: usize = (REGION_SIZE * REGION_SIZE * REGION_HEIGHT) as usize;
+
+/// Файл выделяется блоками по [SECTOR_SIZE] байт
+const SECTOR_SIZE: u64 = 4096;
+
+const REGION_MAGIC: [u8; 4] = *b"VSRG";
+
+/// Версия формата файла региона
+pub const REGION_FORMAT_VERSION: u32 = 1;
+
+/// Размер записи в таблице смещений: номер первого сектора (u32) + размер данных в байтах (u32)
+const TABLE_ENTRY_SIZE: u64 = 8;
+
+/// Количество секторов занимаемых заголовком и таблицей смещений
+const HEADER_SECTORS: u32 =
+    ((REGION_MAGIC.len() as u64 + 4 + CHUNKS_PER_REGION as u64 * TABLE_ENTRY_SIZE).div_ceil(SECTOR_SIZE)) as u32;
+
+/// Координаты региона в сетке регионов
+#[derive(Default, Hash, Eq, PartialEq, Clone, Copy, Debug, Deref)]
+pub struct RegionPos {
+    pos: IVec3,
+}
+
+impl From<ChunkPos> for RegionPos {
+    fn from(value: ChunkPos) -> Self {
+        RegionPos {
+            pos: ivec3(
+                value.x.div_euclid(REGION_SIZE),
+                value.y.div_euclid(REGION_SIZE),
+                value.z.div_euclid(REGION_HEIGHT),
+            )
+        }
+    }
+}
+
+/// Запись в таблице смещений, нулевой размер означает отсутствие чанка
+#[derive(Default, Copy, Clone)]
+struct RegionEntry {
+    sector: u32,
+    len: u32,
+}
+
+impl RegionEntry {
+    fn sectors(&self) -> u32 {
+        (self.len as u64).div_ceil(SECTOR_SIZE) as u32
+    }
+}
+
+/// Файл региона, хранит бинарные данные группы чанков [REGION_SIZE]x[REGION_SIZE]x[REGION_HEIGHT].
+///
+/// Формат: заголовок (magic + версия), таблица смещений на каждый чанк региона, данные чанков выровненные по
+/// секторам. При перезаписи чанк записывается в свободные сектора, а старые сектора освобождаются только после
+/// обновления таблицы и могут быть переиспользованы, для возврата места файловой системе используется
+/// [RegionFile::compact]
+pub struct RegionFile {
+    file: File,
+    table: Vec<RegionEntry>,
+
+    /// Занятость секторов файла
+    used_sectors: Vec<bool>,
+}
+
+impl RegionFile {
+    /// Открывает файл региона, если файла нет создает пустой
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+
+        if file.metadata()?.len() == 0 {
+            file.write_all(&REGION_MAGIC)?;
+            REGION_FORMAT_VERSION.encode(&mut file)?;
+            file.set_len(HEADER_SECTORS as u64 * SECTOR_SIZE)?;
+            return Ok(Self {
+                file,
+                table: vec![RegionEntry::default(); CHUNKS_PER_REGION],
+                used_sectors: vec![true; HEADER_SECTORS as usize],
+            });
+        }
+
+        let mut header = vec![0u8; (HEADER_SECTORS as u64 * SECTOR_SIZE) as usize];
+        file.read_exact(&mut header)?;
+        let mut reader = header.as_slice();
+
+        let mut magic = [0u8; 4];
+        reader.read_exact(&mut magic)?;
+        if magic != REGION_MAGIC {
+            return Err(invalid_data("not a region file"));
+        }
+        let version = u32::decode(&mut reader)?;
+        if version != REGION_FORMAT_VERSION {
+            return Err(invalid_data(&format!("unsupported region format version {}", version)));
+        }
+
+        let sectors_in_file = file.metadata()?.len().div_ceil(SECTOR_SIZE) as usize;
+        let mut used_sectors = vec![false; sectors_in_file];
+        used_sectors[..HEADER_SECTORS as usize].fill(true);
+
+        let mut table = Vec::with_capacity(CHUNKS_PER_REGION);
+        for _ in 0..CHUNKS_PER_REGION {
+            let entry = RegionEntry {
+                sector: u32::decode(&mut reader)?,
+                len: u32::decode(&mut reader)?,
+            };
+            if entry.len != 0 {
+                let end = entry.sector.checked_add(entry.sectors())
+                    .ok_or_else(|| invalid_data("chunk data out of region file bounds"))?;
+                let range = entry.sector as usize..end as usize;
+                if range.start < HEADER_SECTORS as usize || range.end > sectors_in_file {
+                    return Err(invalid_data("chunk data out of region file bounds"));
+                }
+                // Иначе запись одного чанка может перезаписать данные другого
+                if used_sectors[range.clone()].contains(&true) {
+                    return Err(invalid_data("chunk data overlaps in region file"));
+                }
+                used_sectors[range].fill(true);
+            }
+            table.push(entry);
+        }
+
+        Ok(Self { file, table, used_sectors })
+    }
+
+    /// Читает данные чанка, если чанк не записан в регион возвращает [None]
+    pub fn read_chunk(&mut self, pos: ChunkPos) -> io::Result<Option<Vec<u8>>> {
+        let entry = self.table[Self::entry_index(pos)];
+        if entry.len == 0 {
+            return Ok(None);
+        }
+        let mut data = vec![0u8; entry.len as usize];
+        self.file.seek(SeekFrom::Start(entry.sector as u64 * SECTOR_SIZE))?;
+        self.file.read_exact(&mut data)?;
+        Ok(Some(data))
+    }
+
+    /// Записывает данные чанка. Старые данные чанка не перезаписываются, поэтому при сбое во время записи в файле
+    /// остается предыдущая версия чанка
+    pub fn write_chunk(&mut self, pos: ChunkPos, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() {
+            return self.remove_chunk(pos);
+        }
+
+        let index = Self::entry_index(pos);
+        let old_entry = self.table[index];
+
+        let len = data.len() as u32;
+        let sectors = RegionEntry { sector: 0, len }.sectors();
+        let sector = self.find_free_sectors(sectors);
+
+        self.file.seek(SeekFrom::Start(sector as u64 * SECTOR_SIZE))?;
+        self.file.write_all(data)?;
+        // Дописываем последний сектор до конца, чтобы длина файла всегда была кратна размеру сектора
+        let end = (sector + sectors) as u64 * SECTOR_SIZE;
+        if self.file.metadata()?.len() < end {
+            self.file.set_len(end)?;
+        }
+
+        let entry = RegionEntry { sector, len };
+        self.set_used(entry, true);
+        self.write_entry(index, entry)?;
+        self.set_used(old_entry, false);
+        Ok(())
+    }
+
+    /// Удаляет чанк из региона, занимаемое им место освобождается
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> io::Result<()> {
+        let index = Self::entry_index(pos);
+        let entry = self.table[index];
+        if entry.len == 0 {
+            return Ok(());
+        }
+        self.set_used(entry, false);
+        self.write_entry(index, RegionEntry::default())
+    }
+
+    /// Возвращает true если в регионе нет ни одного чанка
+    pub fn is_empty(&self) -> bool {
+        self.table.iter().all(|entry| entry.len == 0)
+    }
+
+    /// Возвращает количество свободных секторов внутри файла
+    pub fn free_sectors(&self) -> usize {
+        self.used_sectors.iter().filter(|used| !**used).count()
+    }
+
+    /// Возвращает размер файла в байтах
+    pub fn file_len(&self) -> u64 {
+        self.used_sectors.len() as u64 * SECTOR_SIZE
+    }
+
+    /// Сдвигает данные чанков к началу файла убирая свободные сектора и обрезает файл
+    pub fn compact(&mut self) -> io::Result<()> {
+        let mut indexes: Vec<usize> = (0..CHUNKS_PER_REGION).filter(|index| self.table[*index].len != 0).collect();
+        indexes.sort_by_key(|index| self.table[*index].sector);
+
+        let mut next_sector = HEADER_SECTORS;
+        for index in indexes {
+            let entry = self.table[index];
+            // Данные всегда переносятся только ближе к началу файла, поэтому не затирают еще не перенесенные
+            if entry.sector != next_sector {
+                let mut data = vec![0u8; entry.len as usize];
+                self.file.seek(SeekFrom::Start(entry.sector as u64 * SECTOR_SIZE))?;
+                self.file.read_exact(&mut data)?;
+                self.file.seek(SeekFrom::Start(next_sector as u64 * SECTOR_SIZE))?;
+                self.file.write_all(&data)?;
+                self.write_entry(index, RegionEntry { sector: next_sector, len: entry.len })?;
+            }
+            next_sector += entry.sectors();
+        }
+
+        self.file.set_len(next_sector as u64 * SECTOR_SIZE)?;
+        self.used_sectors.clear();
+        self.used_sectors.resize(next_sector as usize, true);
+        Ok(())
+    }
+
+    /// Сбрасывает записанные данные на диск
+    pub fn sync(&mut self) -> io::Result<()> {
+        self.file.sync_data()
+    }
+
+    fn entry_index(pos: ChunkPos) -> usize {
+        let x = pos.x.rem_euclid(REGION_SIZE);
+        let y = pos.y.rem_euclid(REGION_SIZE);
+        let z = pos.z.rem_euclid(REGION_HEIGHT);
+        ((z * REGION_SIZE + y) * REGION_SIZE + x) as usize
+    }
+
+    fn write_entry(&mut self, index: usize, entry: RegionEntry) -> io::Result<()> {
+        self.table[index] = entry;
+        let offset = REGION_MAGIC.len() as u64 + 4 + index as u64 * TABLE_ENTRY_SIZE;
+        let mut bytes = Vec::with_capacity(TABLE_ENTRY_SIZE as usize);
+        entry.sector.encode(&mut bytes)?;
+        entry.len.encode(&mut bytes)?;
+        self.file.seek(SeekFrom::Start(offset))?;
+        self.file.write_all(&bytes)
+    }
+
+    fn set_used(&mut self, entry: RegionEntry, used: bool) {
+        if entry.len == 0 {
+            return;
+        }
+        let end = (entry.sector + entry.sectors()) as usize;
+        if self.used_sectors.len() < end {
+            self.used_sectors.resize(end, false);
+        }
+        self.used_sectors[entry.sector as usize..end].fill(used);
+    }
+
+    /// Ищет первый подходящий непрерывный участок свободных секторов, если такого нет возвращает конец файла
+    fn find_free_sectors(&self, count: u32) -> u32 {
+        let mut run_start = 0;
+        let mut run_len = 0;
+        for (sector, used) in self.used_sectors.iter().enumerate() {
+            if *used {
+                run_len = 0;
+                continue;
+            }
+            if run_len == 0 {
+                run_start = sector;
+            }
+            run_len += 1;
+            if run_len == count {
+                return run_start as u32;
+            }
+        }
+        // Свободный участок в конце файла можно дополнить новыми секторами
+        if run_len != 0 && run_start + run_len as usize == self.used_sectors.len() {
+            return run_start as u32;
+        }
+        self.used_sectors.len() as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::ivec3;
+    use crate::{ChunkPos, RegionFile, RegionPos};
+    use crate::region_file::{HEADER_SECTORS, RegionEntry, SECTOR_SIZE};
+
+    fn chunk_pos(x: i32, y: i32, z: i32) -> ChunkPos {
+        ivec3(x, y, z).into()
+    }
+
+    #[test]
+    fn region_pos_from_chunk_pos() {
+        assert_eq!(*RegionPos::from(chunk_pos(0, 31, 15)), ivec3(0, 0, 0));
+        assert_eq!(*RegionPos::from(chunk_pos(32, -1, 16)), ivec3(1, -1, 1));
+        assert_eq!(*RegionPos::from(chunk_pos(-33, -32, -17)), ivec3(-2, -1, -2));
+    }
+
+    #[test]
+    fn write_and_read_chunks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut region = RegionFile::open(&dir.path().join("region")).unwrap();
+
+        assert!(region.is_empty());
+        assert_eq!(region.read_chunk(chunk_pos(1, 2, 3)).unwrap(), None);
+
+        region.write_chunk(chunk_pos(1, 2, 3), &[1, 2, 3]).unwrap();
+        region.write_chunk(chunk_pos(-1, -2, -3), &vec![7; 5000]).unwrap();
+
+        assert_eq!(region.read_chunk(chunk_pos(1, 2, 3)).unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(region.read_chunk(chunk_pos(-1, -2, -3)).unwrap(), Some(vec![7; 5000]));
+        assert_eq!(region.file_len(), (HEADER_SECTORS as u64 + 3) * SECTOR_SIZE);
+    }
+
+    #[test]
+    fn data_survives_reopen() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("region");
+        {
+            let mut region = RegionFile::open(&path).unwrap();
+            region.write_chunk(chunk_pos(5, 5, 5), &[42; 100]).unwrap();
+        }
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read_chunk(chunk_pos(5, 5, 5)).unwrap(), Some(vec![42; 100]));
+        assert_eq!(region.free_sectors(), 0);
+    }
+
+    #[test]
+    fn rewrite_reuses_freed_sectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut region = RegionFile::open(&dir.path().join("region")).unwrap();
+
+        region.write_chunk(chunk_pos(0, 0, 0), &[1; 100]).unwrap();
+        region.write_chunk(chunk_pos(1, 0, 0), &[2; 100]).unwrap();
+        // Чанк вырос и больше не помещается на старое место
+        region.write_chunk(chunk_pos(0, 0, 0), &vec![3; 5000]).unwrap();
+        assert_eq!(region.free_sectors(), 1);
+
+        // Новый маленький чанк занимает освободившийся сектор
+        region.write_chunk(chunk_pos(2, 0, 0), &[4; 100]).unwrap();
+        assert_eq!(region.free_sectors(), 0);
+
+        assert_eq!(region.read_chunk(chunk_pos(0, 0, 0)).unwrap(), Some(vec![3; 5000]));
+        assert_eq!(region.read_chunk(chunk_pos(1, 0, 0)).unwrap(), Some(vec![2; 100]));
+        assert_eq!(region.read_chunk(chunk_pos(2, 0, 0)).unwrap(), Some(vec![4; 100]));
+    }
+
+    #[test]
+    fn rewrite_keeps_old_data_until_table_update() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("region");
+        let mut region = RegionFile::open(&path).unwrap();
+
+        region.write_chunk(chunk_pos(0, 0, 0), &[1; 100]).unwrap();
+        region.write_chunk(chunk_pos(0, 0, 0), &[2; 100]).unwrap();
+        // Новая версия записана в другой сектор, старый освобожден только после обновления таблицы
+        assert_eq!(region.free_sectors(), 1);
+        let file = std::fs::read(&path).unwrap();
+        let old_data = HEADER_SECTORS as usize * SECTOR_SIZE as usize;
+        assert_eq!(file[old_data..old_data + 100], [1; 100]);
+        assert_eq!(region.read_chunk(chunk_pos(0, 0, 0)).unwrap(), Some(vec![2; 100]));
+    }
+
+    #[test]
+    fn compact_removes_free_sectors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("region");
+        let mut region = RegionFile::open(&path).unwrap();
+
+        for x in 0..4 {
+            region.write_chunk(chunk_pos(x, 0, 0), &vec![x as u8; 5000]).unwrap();
+        }
+        region.remove_chunk(chunk_pos(0, 0, 0)).unwrap();
+        region.remove_chunk(chunk_pos(2, 0, 0)).unwrap();
+        assert_eq!(region.free_sectors(), 4);
+
+        region.compact().unwrap();
+        assert_eq!(region.free_sectors(), 0);
+        assert_eq!(region.file_len(), (HEADER_SECTORS as u64 + 4) * SECTOR_SIZE);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), region.file_len());
+
+        let mut region = RegionFile::open(&path).unwrap();
+        assert_eq!(region.read_chunk(chunk_pos(0, 0, 0)).unwrap(), None);
+        assert_eq!(region.read_chunk(chunk_pos(1, 0, 0)).unwrap(), Some(vec![1; 5000]));
+        assert_eq!(region.read_chunk(chunk_pos(2, 0, 0)).unwrap(), None);
+        assert_eq!(region.read_chunk(chunk_pos(3, 0, 0)).unwrap(), Some(vec![3; 5000]));
+    }
+
+    #[test]
+    fn open_rejects_foreign_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("region");
+        std::fs::write(&path, vec![0; SECTOR_SIZE as usize * HEADER_SECTORS as usize]).unwrap();
+        assert!(RegionFile::open(&path).is_err());
+    }
+
+    #[test]
+    fn open_rejects_corrupted_table() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("region");
+        let corrupt = |entry: RegionEntry| {
+            let mut region = RegionFile::open(&path).unwrap();
+            region.write_chunk(chunk_pos(0, 0, 0), &[1; 100]).unwrap();
+            region.write_chunk(chunk_pos(1, 0, 0), &[2; 100]).unwrap();
+            region.write_entry(RegionFile::entry_index(chunk_pos(1, 0, 0)), entry).unwrap();
+            drop(region);
+            let result = RegionFile::open(&path);
+            std::fs::remove_file(&path).unwrap();
+            result
+        };
+
+        // Конец данных не помещается в u32
+        assert!(corrupt(RegionEntry { sector: u32::MAX, len: 5000 }).is_err());
+        // Данные за концом файла
+        assert!(corrupt(RegionEntry { sector: HEADER_SECTORS + 2, len: 100 }).is_err());
+        // Данные пересекаются с данными другого чанка
+        assert!(corrupt(RegionEntry { sector: HEADER_SECTORS, len: 100 }).is_err());
+        assert!(corrupt(RegionEntry { sector: HEADER_SECTORS + 1, len: 100 }).is_ok());
+    }
+}
@@ -0,0 +1,176 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::{BinaryCodec, Chunk, ChunkPos, RegionFile, RegionPos};
+
+const REGION_FILE_EXTENSION: &str = "region";
+
+/// Сколько файлов регионов держится открытыми по умолчанию
+const MAX_OPEN_REGIONS: usize = 64;
+
+/// Хранилище чанков в директории.
+///
+/// Чанки группируются по регионам, на каждый [RegionPos] создается отдельный [RegionFile]. Файлы регионов
+/// создаются только при записи в них первого чанка. Открытыми держатся только недавно использованные файлы
+pub struct RegionStorage {
+    dir: PathBuf,
+
+    /// Открытые файлы регионов
+    regions: HashMap<RegionPos, RegionFile>,
+
+    /// Открытые регионы от давно использованных к недавно использованным
+    recently_used: VecDeque<RegionPos>,
+
+    max_open_regions: usize,
+}
+
+impl RegionStorage {
+    /// Открывает хранилище в переданной директории, если директории нет создает ее
+    pub fn open(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = dir.into();
+        std::fs::create_dir_all(&dir)?;
+        Ok(Self { dir, regions: HashMap::new(), recently_used: VecDeque::new(), max_open_regions: MAX_OPEN_REGIONS })
+    }
+
+    /// Задает сколько файлов регионов держится открытыми, давно не использованные файлы закрываются
+    pub fn set_max_open_regions(&mut self, max_open_regions: usize) {
+        self.max_open_regions = max_open_regions.max(1);
+    }
+
+    /// Возвращает количество открытых файлов регионов
+    pub fn open_regions(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// Возвращает директорию хранилища
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Читает чанк, если чанк не сохранен возвращает [None]
+    pub fn read_chunk<BLOCK, METADATA>(&mut self, pos: ChunkPos) -> io::Result<Option<Chunk<BLOCK, METADATA>>>
+        where BLOCK: BinaryCodec, METADATA: BinaryCodec
+    {
+        let data = match self.region(pos.into(), false)? {
+            None => { return Ok(None); }
+            Some(region) => { region.read_chunk(pos)? }
+        };
+        data.map(|data| Chunk::decode(&mut data.as_slice())).transpose()
+    }
+
+    /// Сохраняет чанк, перезаписывая предыдущую версию если она была
+    pub fn write_chunk<BLOCK, METADATA>(&mut self, pos: ChunkPos, chunk: &Chunk<BLOCK, METADATA>) -> io::Result<()>
+        where BLOCK: BinaryCodec, METADATA: BinaryCodec
+    {
+        let mut data = Vec::new();
+        chunk.encode(&mut data)?;
+        self.region(pos.into(), true)?.unwrap().write_chunk(pos, &data)
+    }
+
+    /// Удаляет сохраненный чанк
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> io::Result<()> {
+        match self.region(pos.into(), false)? {
+            None => { Ok(()) }
+            Some(region) => { region.remove_chunk(pos) }
+        }
+    }
+
+    /// Сжимает все открытые файлы регионов, см [RegionFile::compact]
+    pub fn compact(&mut self) -> io::Result<()> {
+        for region in self.regions.values_mut() {
+            region.compact()?;
+        }
+        Ok(())
+    }
+
+    /// Сбрасывает данные всех открытых файлов регионов на диск
+    pub fn sync(&mut self) -> io::Result<()> {
+        for region in self.regions.values_mut() {
+            region.sync()?;
+        }
+        Ok(())
+    }
+
+    fn region_path(&self, pos: RegionPos) -> PathBuf {
+        self.dir.join(format!("r.{}.{}.{}.{}", pos.x, pos.y, pos.z, REGION_FILE_EXTENSION))
+    }
+
+    /// Возвращает файл региона, если файла нет то создает его только при [create] = true
+    fn region(&mut self, pos: RegionPos, create: bool) -> io::Result<Option<&mut RegionFile>> {
+        if self.regions.contains_key(&pos) {
+            self.recently_used.retain(|used| *used != pos);
+        } else {
+            let path = self.region_path(pos);
+            if !create && !path.exists() {
+                return Ok(None);
+            }
+            let region = RegionFile::open(&path)?;
+            while self.regions.len() >= self.max_open_regions {
+                let Some(evicted) = self.recently_used.pop_front() else { break; };
+                // Перед закрытием данные сбрасываются на диск, как при [RegionStorage::sync]
+                self.regions.remove(&evicted).unwrap().sync()?;
+            }
+            self.regions.insert(pos, region);
+        }
+        self.recently_used.push_back(pos);
+        Ok(self.regions.get_mut(&pos))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy_math::{ivec3, uvec3};
+    use crate::{Chunk, ChunkPos, RegionStorage};
+
+    fn chunk_pos(x: i32, y: i32, z: i32) -> ChunkPos {
+        ivec3(x, y, z).into()
+    }
+
+    #[test]
+    fn read_missing_chunk_does_not_create_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = RegionStorage::open(dir.path()).unwrap();
+        assert!(storage.read_chunk::<u8, ()>(chunk_pos(0, 0, 0)).unwrap().is_none());
+        storage.remove_chunk(chunk_pos(0, 0, 0)).unwrap();
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn chunks_are_grouped_by_region() {
+        let dir = tempfile::tempdir().unwrap();
+        {
+            let mut storage = RegionStorage::open(dir.path()).unwrap();
+            let mut chunk = Chunk::<u8, ()>::new(());
+            chunk.set(&uvec3(1, 1, 1).try_into().unwrap(), Some(5));
+
+            storage.write_chunk(chunk_pos(0, 0, 0), &chunk).unwrap();
+            storage.write_chunk(chunk_pos(31, 31, 15), &chunk).unwrap();
+            storage.write_chunk(chunk_pos(-1, 0, 0), &chunk).unwrap();
+        }
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        let mut storage = RegionStorage::open(dir.path()).unwrap();
+        let chunk = storage.read_chunk::<u8, ()>(chunk_pos(31, 31, 15)).unwrap().unwrap();
+        assert_eq!(chunk[&uvec3(1, 1, 1).try_into().unwrap()], Some(5));
+        assert!(storage.read_chunk::<u8, ()>(chunk_pos(1, 0, 0)).unwrap().is_none());
+    }
+
+    #[test]
+    fn least_recently_used_regions_are_closed() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = RegionStorage::open(dir.path()).unwrap();
+        storage.set_max_open_regions(2);
+        let mut chunk = Chunk::<u8, ()>::new(());
+        chunk.set(&uvec3(1, 1, 1).try_into().unwrap(), Some(5));
+
+        for x in 0..4 {
+            storage.write_chunk(chunk_pos(x * 32, 0, 0), &chunk).unwrap();
+            assert!(storage.open_regions() <= 2);
+        }
+        for x in 0..4 {
+            let chunk = storage.read_chunk::<u8, ()>(chunk_pos(x * 32, 0, 0)).unwrap().unwrap();
+            assert_eq!(chunk[&uvec3(1, 1, 1).try_into().unwrap()], Some(5));
+        }
+        assert_eq!(storage.open_regions(), 2);
+    }
+}
@@ -1,5 +1,7 @@
 mod world_anchor_plugin;
 mod world_anchor;
+mod load_shape;
 
 pub use world_anchor::{WorldAnchor, WorldAnchorInChunkPos};
-pub use world_anchor_plugin::WorldAnchorPlugin;
\ No newline at end of file
+pub use world_anchor_plugin::WorldAnchorPlugin;
+pub use load_shape::{LoadShape, load_area_chunks};
\ No newline at end of file
@@ -0,0 +1,108 @@
+use std::sync::Arc;
+use bevy::math::IVec3;
+use chunk::ChunkPos;
+use crate::WorldAnchor;
+
+/// Форма области загрузки мира вокруг [WorldAnchor].
+///
+/// Область задается горизонтальным радиусом `r` и вертикальным радиусом `vr` якоря, в нее попадают только чанки
+/// смещение которых от чанка якоря по горизонтали меньше `r`, а по вертикали меньше `vr`
+#[derive(Clone, Default)]
+pub enum LoadShape {
+    /// Все чанки в пределах радиусов, квадрат колонн чанков
+    #[default]
+    Square,
+
+    /// Круг колонн чанков вписанный в квадрат [LoadShape::Square]
+    Cylinder,
+
+    /// Эллипсоид вписанный в область [LoadShape::Square], чанк входит в него если в него входит центр чанка
+    Sphere,
+
+    /// Пользовательская форма, функция получает смещение чанка от чанка якоря
+    Custom(Arc<dyn Fn(IVec3) -> bool + Send + Sync>),
+}
+
+impl LoadShape {
+    /// Возвращает входит ли в область чанк со смещением `offset` от чанка якоря
+    pub fn contains(&self, offset: IVec3, radius: u32, vertical_radius: u32) -> bool {
+        let (r, vr) = (radius as i64, vertical_radius as i64);
+        let (x, y, z) = (offset.x as i64, offset.y as i64, offset.z as i64);
+        if x.abs() >= r || y.abs() >= r || z.abs() >= vr {
+            return false;
+        }
+        // Полуоси вписанных фигур равны r - 1/2 и vr - 1/2, поэтому все расстояния удвоены
+        let (a, b) = (2 * r - 1, 2 * vr - 1);
+        match self {
+            LoadShape::Square => { true }
+            LoadShape::Cylinder => { 4 * (x * x + y * y) < a * a }
+            LoadShape::Sphere => { 4 * (x * x + y * y) * b * b + 4 * z * z * a * a < a * a * b * b }
+            LoadShape::Custom(contains) => { contains(offset) }
+        }
+    }
+}
+
+impl WorldAnchor {
+    /// Возвращает входит ли чанк `pos` в область загрузки якоря находящегося в чанке `center`
+    pub fn contains(&self, center: ChunkPos, pos: ChunkPos) -> bool {
+        self.shape.contains(*pos - *center, self.load_radius, self.vertical_load_radius)
+    }
+}
+
+/// Возвращает чанки области загрузки якоря находящегося в чанке `center`, отсортированные по расстоянию до него
+pub fn load_area_chunks(center: ChunkPos, anchor: &WorldAnchor) -> Vec<ChunkPos> {
+    let (radius, vertical_radius) = (anchor.load_radius, anchor.vertical_load_radius);
+    center.spiral(radius, vertical_radius, |offset| anchor.shape.contains(offset, radius, vertical_radius))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use bevy::math::{ivec3, IVec3};
+    use chunk::ChunkPos;
+    use crate::{load_area_chunks, LoadShape, WorldAnchor};
+
+    fn anchor(shape: LoadShape, load_radius: u32, vertical_load_radius: u32) -> WorldAnchor {
+        WorldAnchor { load_radius, vertical_load_radius, shape, ..Default::default() }
+    }
+
+    fn count(shape: LoadShape, load_radius: u32, vertical_load_radius: u32) -> usize {
+        load_area_chunks(ivec3(0, 0, 0).into(), &anchor(shape, load_radius, vertical_load_radius)).len()
+    }
+
+    #[test]
+    fn shapes_contain_expected_chunks() {
+        assert_eq!(count(LoadShape::Square, 3, 2), 5 * 5 * 3);
+        assert_eq!(count(LoadShape::Square, 0, 2), 0);
+        assert_eq!(count(LoadShape::Square, 2, 0), 0);
+        assert_eq!(count(LoadShape::Square, 1, 1), 1);
+
+        // Круг вписанный в квадрат 5x5 не содержит угловых чанков
+        assert_eq!(count(LoadShape::Cylinder, 3, 1), 21);
+        assert_eq!(count(LoadShape::Cylinder, 3, 2), 21 * 3);
+
+        let sphere = anchor(LoadShape::Sphere, 4, 2);
+        assert!(sphere.contains(ivec3(0, 0, 0).into(), ivec3(3, 0, 0).into()));
+        assert!(sphere.contains(ivec3(0, 0, 0).into(), ivec3(0, 0, 1).into()));
+        assert!(!sphere.contains(ivec3(0, 0, 0).into(), ivec3(3, 0, 1).into()));
+        assert!(!sphere.contains(ivec3(0, 0, 0).into(), ivec3(2, 2, 1).into()));
+        assert!(count(LoadShape::Sphere, 4, 4) < count(LoadShape::Cylinder, 4, 4));
+
+        // Пользовательская форма ограничена радиусами якоря
+        let plane = LoadShape::Custom(Arc::new(|offset: IVec3| offset.z == 0));
+        assert_eq!(count(plane, 2, 5), 9);
+    }
+
+    #[test]
+    fn chunks_are_ordered_by_distance() {
+        let center: ChunkPos = ivec3(10, -4, 3).into();
+        let chunks = load_area_chunks(center, &anchor(LoadShape::Sphere, 5, 3));
+        assert_eq!(chunks[0], center);
+        let distances: Vec<i32> = chunks.iter().map(|pos| center.distance_squared(**pos)).collect();
+        assert!(distances.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let anchor = anchor(LoadShape::Sphere, 5, 3);
+        assert!(chunks.iter().all(|pos| anchor.contains(center, *pos)));
+    }
+}
@@ -1,20 +1,60 @@
 use bevy::prelude::Component;
 use chunk::ChunkPos;
+use crate::LoadShape;
 
 const DEFAULT_LOAD_RADIUS: u32 = 2;
+const DEFAULT_VERTICAL_LOAD_RADIUS: u32 = 2;
+const DEFAULT_IMPORTANCE: f32 = 1.;
+const MIN_IMPORTANCE: f32 = 1e-3;
+const MAX_IMPORTANCE: f32 = 1e3;
 
 /// Маркерный интерфейс, для маркировки сущностей вокруг которых должен грузиться мир
 /// Такие сущности обязательно должны так же включать элемент [Transform]
-#[derive(Component)]
+#[derive(Component, Clone)]
 pub struct WorldAnchor {
     /// Радиус в пределах которого будет загружаться мир
     pub load_radius: u32,
+
+    /// Радиус в пределах которого будет загружаться мир по вертикали
+    pub vertical_load_radius: u32,
+
+    /// Форма области загрузки в пределах радиусов
+    pub shape: LoadShape,
+
+    /// Важность якоря, должна быть больше нуля. Расстояние до чанков якоря делится на важность при расчете
+    /// приоритета загрузки, поэтому чанки вокруг более важных якорей загружаются раньше.
+    /// Некорректные значения ограничиваются, см [WorldAnchor::clamped_importance]
+    pub importance: f32,
 }
 
 impl Default for WorldAnchor {
     fn default() -> Self {
         WorldAnchor {
-            load_radius: DEFAULT_LOAD_RADIUS
+            load_radius: DEFAULT_LOAD_RADIUS,
+            vertical_load_radius: DEFAULT_VERTICAL_LOAD_RADIUS,
+            shape: LoadShape::default(),
+            importance: DEFAULT_IMPORTANCE,
+        }
+    }
+}
+
+impl WorldAnchor {
+    /// Якорь той же формы с радиусами увеличенными на `margin`
+    pub fn expanded(&self, margin: u32) -> Self {
+        Self {
+            load_radius: self.load_radius + margin,
+            vertical_load_radius: self.vertical_load_radius + margin,
+            ..self.clone()
+        }
+    }
+
+    /// Важность ограниченная диапазоном от [MIN_IMPORTANCE] до [MAX_IMPORTANCE], для NaN используется важность по
+    /// умолчанию. Так приоритет загрузки всегда конечен и не отрицателен
+    pub fn clamped_importance(&self) -> f32 {
+        if self.importance.is_nan() {
+            DEFAULT_IMPORTANCE
+        } else {
+            self.importance.clamp(MIN_IMPORTANCE, MAX_IMPORTANCE)
         }
     }
 }
@@ -24,3 +64,18 @@ impl Default for WorldAnchor {
 pub struct WorldAnchorInChunkPos {
     pub pos: ChunkPos,
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::WorldAnchor;
+
+    #[test]
+    fn importance_is_clamped() {
+        let importance = |importance: f32| WorldAnchor { importance, ..Default::default() }.clamped_importance();
+        assert_eq!(importance(2.), 2.);
+        assert_eq!(importance(f32::NAN), 1.);
+        for invalid in [0., -1., f32::INFINITY, f32::NEG_INFINITY] {
+            assert!(importance(invalid).is_finite() && importance(invalid) > 0.);
+        }
+    }
+}
@@ -0,0 +1,80 @@
+// Шейдер блоков мира, см BlockMaterial
+
+#import bevy_pbr::mesh_functions as mesh_functions
+#import bevy_pbr::mesh_bindings mesh
+#import bevy_pbr::mesh_view_bindings view
+#import bevy_pbr::pbr_functions as pbr_functions
+#import bevy_core_pipeline::tonemapping tone_mapping
+
+@group(1) @binding(0) var atlas_texture: texture_2d<f32>;
+@group(1) @binding(1) var atlas_sampler: sampler;
+
+struct Vertex {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    // Координаты текстуры в блоках
+    @location(2) uv: vec2<f32>,
+    // Тайл атласа: min_u, min_v, width, height
+    @location(3) tile: vec4<f32>,
+    // Множитель яркости от затенения соседними блоками
+    @location(4) ao: f32,
+    // Освещенность: r - свет неба, g - свет блоков
+    @location(5) light: vec4<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world_position: vec4<f32>,
+    @location(1) world_normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+    @location(3) tile: vec4<f32>,
+    @location(4) ao: f32,
+    @location(5) light: vec4<f32>,
+};
+
+// Яркость от уровня освещенности, каждый уровень темнее предыдущего на 20%
+fn light_brightness(light: f32) -> f32 {
+    return pow(0.8, (1.0 - light) * 15.0);
+}
+
+@vertex
+fn vertex(vertex: Vertex) -> VertexOutput {
+    var out: VertexOutput;
+    out.world_position = mesh_functions::mesh_position_local_to_world(mesh.model, vec4<f32>(vertex.position, 1.0));
+    out.position = mesh_functions::mesh_position_world_to_clip(out.world_position);
+    out.world_normal = mesh_functions::mesh_normal_local_to_world(vertex.normal);
+    out.uv = vertex.uv;
+    out.tile = vertex.tile;
+    out.ao = vertex.ao;
+    out.light = vertex.light;
+    return out;
+}
+
+@fragment
+fn fragment(
+    in: VertexOutput,
+    @builtin(front_facing) is_front: bool,
+) -> @location(0) vec4<f32> {
+    // Грань может состоять из нескольких блоков, тайл атласа повторяется для каждого блока
+    let uv = in.tile.xy + fract(in.uv) * in.tile.zw;
+
+    var pbr_input: pbr_functions::PbrInput = pbr_functions::pbr_input_new();
+    let color = textureSample(atlas_texture, atlas_sampler, uv);
+    let brightness = max(light_brightness(in.light.r), light_brightness(in.light.g));
+    pbr_input.material.base_color = vec4<f32>(color.rgb * in.ao * brightness, color.a);
+    pbr_input.material.perceptual_roughness = 1.0;
+    pbr_input.material.metallic = 0.0;
+    pbr_input.material.reflectance = 0.0;
+    pbr_input.frag_coord = in.position;
+    pbr_input.world_position = in.world_position;
+    pbr_input.world_normal = pbr_functions::prepare_world_normal(in.world_normal, false, is_front);
+    pbr_input.is_orthographic = view.projection[3].w == 1.0;
+    pbr_input.N = pbr_input.world_normal;
+    pbr_input.V = pbr_functions::calculate_view(in.world_position, pbr_input.is_orthographic);
+
+    var output_color = pbr_functions::pbr(pbr_input);
+#ifdef TONEMAP_IN_SHADER
+    output_color = tone_mapping(output_color, view.color_grading);
+#endif
+    return output_color;
+}
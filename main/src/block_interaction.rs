@@ -0,0 +1,182 @@
+use bevy::math::IVec3;
+use bevy::prelude::*;
+use bevy::window::{CursorGrabMode, PrimaryWindow};
+use chunk::{AbsoluteBlockPos, raycast, RaycastHit};
+use crate::camera::{PlayerBody, PlayerCamera};
+use crate::key_binding::KeyBindings;
+use crate::logic::block::{Block, BlockId, BlockRegistry, SharedBlockRegistry};
+use crate::logic::collision::Aabb;
+use crate::logic::world::World;
+
+/// Плагин взаимодействия игрока с блоками: разрушение и установка блоков, подсветка блока под прицелом.
+/// Требует [SharedBlockRegistry] и [crate::logic::world::WorldPlugin]
+pub struct BlockInteractionPlugin;
+
+impl Plugin for BlockInteractionPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<SelectedBlock>()
+            .init_resource::<TargetBlock>()
+            .add_systems(Update, (update_target_block, break_block, place_block, draw_target_outline).chain())
+        ;
+    }
+}
+
+/// Блок который игрок ставит, по умолчанию камень или первый блок реестра
+#[derive(Resource, Deref, DerefMut)]
+pub struct SelectedBlock(pub Block);
+
+impl FromWorld for SelectedBlock {
+    fn from_world(world: &mut bevy::prelude::World) -> Self {
+        let registry = world.resource::<SharedBlockRegistry>();
+        let id = registry.get_id("stone")
+            .or_else(|| registry.iter().next().map(|definition| BlockId(definition.id)))
+            .expect("Block registry is empty");
+        SelectedBlock(Block::new(id))
+    }
+}
+
+/// Блок под прицелом камеры игрока
+#[derive(Resource, Default, Deref, DerefMut)]
+pub struct TargetBlock(pub Option<RaycastHit>);
+
+/// Ищет блок на который смотрит камера игрока
+fn update_target_block(
+    world: Res<World>,
+    key_bindings: Res<KeyBindings>,
+    mut target_block: ResMut<TargetBlock>,
+    camera: Query<&GlobalTransform, With<PlayerCamera>>,
+) {
+    let Ok(transform) = camera.get_single() else {
+        **target_block = None;
+        return;
+    };
+    let hit = raycast(
+        &world.chunk_map,
+        transform.translation(),
+        transform.forward(),
+        key_bindings.reach_distance,
+        |_| true,
+    );
+    **target_block = hit;
+}
+
+fn break_block(
+    world: Res<World>,
+    block_registry: Res<SharedBlockRegistry>,
+    mouse: Res<Input<MouseButton>>,
+    key_bindings: Res<KeyBindings>,
+    target_block: Res<TargetBlock>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+) {
+    if !mouse.just_pressed(key_bindings.break_block) || !is_cursor_grabbed(&primary_window) {
+        return;
+    }
+    let Some(hit) = **target_block else {
+        return;
+    };
+    // Блоки с отрицательной прочностью разрушить нельзя
+    let block = world.get_block(hit.pos).flatten();
+    let hardness = block.and_then(|block| block_registry.get(block.id())).map(|definition| definition.hardness);
+    if hardness.is_some_and(|hardness| hardness < 0.) {
+        return;
+    }
+    world.set_block(hit.pos, None);
+}
+
+fn place_block(
+    world: Res<World>,
+    block_registry: Res<SharedBlockRegistry>,
+    mouse: Res<Input<MouseButton>>,
+    key_bindings: Res<KeyBindings>,
+    target_block: Res<TargetBlock>,
+    selected_block: Res<SelectedBlock>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    player: Query<(&GlobalTransform, &PlayerBody), With<PlayerCamera>>,
+) {
+    if !mouse.just_pressed(key_bindings.place_block) || !is_cursor_grabbed(&primary_window) {
+        return;
+    }
+    let (Some(hit), Ok((transform, body))) = (**target_block, player.get_single()) else {
+        return;
+    };
+    // Если камера находится внутри блока, то грани к которой можно приставить блок нет
+    let Some(face) = hit.face else {
+        return;
+    };
+    let pos: AbsoluteBlockPos = (*hit.pos + IVec3::from(face)).into();
+    if can_place_block(&world, &block_registry, pos, **selected_block, body.aabb(transform.translation())) {
+        world.set_block(pos, Some(**selected_block));
+    }
+}
+
+/// Рисует контур блока под прицелом
+fn draw_target_outline(
+    target_block: Res<TargetBlock>,
+    mut gizmos: Gizmos,
+) {
+    if let Some(hit) = **target_block {
+        // Контур немного больше блока, иначе он пропадает в гранях блока
+        let transform = Transform::from_translation(hit.pos.as_vec3() + 0.5).with_scale(Vec3::splat(1.005));
+        gizmos.cuboid(transform, Color::BLACK);
+    }
+}
+
+fn is_cursor_grabbed(primary_window: &Query<&Window, With<PrimaryWindow>>) -> bool {
+    primary_window.get_single().is_ok_and(|window| window.cursor.grab_mode != CursorGrabMode::None)
+}
+
+/// Можно ли поставить блок: место должно быть загружено и свободно, а твердый блок не должен пересекаться с игроком
+fn can_place_block(
+    world: &World,
+    registry: &BlockRegistry,
+    pos: AbsoluteBlockPos,
+    block: Block,
+    player: Aabb,
+) -> bool {
+    if world.get_block(pos) != Some(None) {
+        return false;
+    }
+    let block_min = pos.as_vec3();
+    let block_max = block_min + Vec3::ONE;
+    let overlaps = block_min.cmplt(player.max).all() && player.min.cmplt(block_max).all();
+    !(registry.is_solid(&Some(block)) && overlaps)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{ivec3, vec3};
+    use chunk::ChunkPos;
+    use crate::block_interaction::can_place_block;
+    use crate::camera::PlayerBody;
+    use crate::logic::block::{Block, BlockDefinition, BlockId, BlockRegistry};
+    use crate::logic::chunk::Chunk;
+    use crate::logic::light::ChunkLight;
+    use crate::logic::world::World;
+
+    #[test]
+    fn placing_is_refused_inside_player() {
+        let definitions = [
+            BlockDefinition { id: 1, name: "stone".to_string(), ..Default::default() },
+            BlockDefinition { id: 2, name: "flower".to_string(), solid: false, ..Default::default() },
+        ];
+        let registry = BlockRegistry::new(definitions).unwrap();
+        let world = World::default();
+        world.add_chunk(ChunkPos::default(), Chunk::new(()), ChunkLight::new());
+        world.set_block(ivec3(5, 5, 0).into(), Some(Block::new(BlockId(1))));
+
+        // Игрок стоит на блоке (5, 5, 0), его тело занимает блоки (5, 5, 1) и (5, 5, 2)
+        let player = PlayerBody::default().aabb(vec3(5.5, 5.5, 2.6));
+        let stone = Block::new(BlockId(1));
+        let flower = Block::new(BlockId(2));
+        assert!(!can_place_block(&world, &registry, ivec3(5, 5, 1).into(), stone, player));
+        assert!(!can_place_block(&world, &registry, ivec3(5, 5, 2).into(), stone, player));
+        assert!(can_place_block(&world, &registry, ivec3(5, 5, 3).into(), stone, player));
+        assert!(can_place_block(&world, &registry, ivec3(6, 5, 1).into(), stone, player));
+        assert!(can_place_block(&world, &registry, ivec3(5, 5, 1).into(), flower, player));
+
+        // Место занято или чанк не загружен
+        assert!(!can_place_block(&world, &registry, ivec3(5, 5, 0).into(), stone, player));
+        assert!(!can_place_block(&world, &registry, ivec3(-1, 5, 1).into(), stone, player));
+    }
+}
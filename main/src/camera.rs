@@ -6,6 +6,9 @@ use bevy::prelude::*;
 use bevy::window::{CursorGrabMode, PrimaryWindow};
 use world_anchor::WorldAnchor;
 use crate::key_binding::KeyBindings;
+use crate::logic::block::SharedBlockRegistry;
+use crate::logic::collision::{Aabb, move_aabb};
+use crate::logic::world::World;
 
 pub struct CameraPlugin;
 
@@ -20,8 +23,16 @@ struct InputState {
 pub struct MovementSettings {
     /// Чувствительность мыши
     pub sensitivity: f32,
-    /// Скорость перемещения камеры
+    /// Скорость перемещения камеры в режиме полета
     pub speed: f32,
+    /// Скорость ходьбы
+    pub walk_speed: f32,
+    /// Начальная вертикальная скорость прыжка
+    pub jump_speed: f32,
+    /// Ускорение свободного падения
+    pub gravity: f32,
+    /// Максимальная высота уступа на который игрок поднимается без прыжка
+    pub step_height: f32,
 }
 
 impl Default for MovementSettings {
@@ -29,21 +40,76 @@ impl Default for MovementSettings {
         Self {
             sensitivity: 0.0002,
             speed: 128.,
+            walk_speed: 4.3,
+            jump_speed: 8.5,
+            gravity: 28.,
+            step_height: 0.6,
         }
     }
 }
 
+/// Режим перемещения игрока
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum MovementMode {
+    /// Свободный полет сквозь блоки
+    #[default]
+    Flying,
+
+    /// Ходьба с гравитацией и столкновениями с блоками
+    Walking,
+}
+
+/// Состояние перемещения игрока
+#[derive(Component, Default)]
+pub struct PlayerMovement {
+    pub mode: MovementMode,
+    pub velocity: Vec3,
+    pub on_ground: bool,
+}
+
 /// Маркер для дефолтной камеры игрока
 #[derive(Component)]
 pub struct PlayerCamera;
 
+/// Размеры тела игрока, камера находится на уровне его глаз
+#[derive(Component, Clone, Copy)]
+pub struct PlayerBody {
+    /// Размер ограничивающего параллелепипеда
+    pub size: Vec3,
+
+    /// Высота глаз над нижней гранью
+    pub eye_height: f32,
+}
+
+impl Default for PlayerBody {
+    fn default() -> Self {
+        Self {
+            size: vec3(0.6, 0.6, 1.8),
+            eye_height: 1.6,
+        }
+    }
+}
+
+impl PlayerBody {
+    /// Возвращает ограничивающий параллелепипед при переданной позиции глаз
+    pub fn aabb(&self, eye: Vec3) -> Aabb {
+        let min = eye - self.eye_offset();
+        Aabb::new(min, min + self.size)
+    }
+
+    /// Смещение глаз относительно минимального угла параллелепипеда
+    fn eye_offset(&self) -> Vec3 {
+        vec3(self.size.x / 2., self.size.y / 2., self.eye_height)
+    }
+}
+
 impl Plugin for CameraPlugin {
     fn build(&self, app: &mut App) {
         app
             .init_resource::<InputState>()
             .init_resource::<MovementSettings>()
             .add_systems(Startup, setup_player_camera)
-            .add_systems(Update, (player_move, player_look, cursor_grab));
+            .add_systems(Update, (toggle_movement_mode, player_move, player_walk, player_look, cursor_grab));
     }
 }
 
@@ -57,7 +123,9 @@ fn setup_player_camera(mut commands: Commands) {
             ..default()
         },
         PlayerCamera,
-        WorldAnchor { load_radius: 16 },
+        PlayerBody::default(),
+        PlayerMovement::default(),
+        WorldAnchor { load_radius: 16, vertical_load_radius: 8, ..default() },
     ));
 }
 
@@ -68,10 +136,13 @@ fn player_move(
     primary_window: Query<&Window, With<PrimaryWindow>>,
     settings: Res<MovementSettings>,
     key_bindings: Res<KeyBindings>,
-    mut query: Query<(&PlayerCamera, &mut Transform)>, //    mut query: Query<&mut Transform, With<FlyCam>>,
+    mut query: Query<(&PlayerMovement, &mut Transform), With<PlayerCamera>>,
 ) {
     let window = primary_window.single();
-    for (_camera, mut transform) in query.iter_mut() {
+    for (movement, mut transform) in query.iter_mut() {
+        if movement.mode != MovementMode::Flying {
+            continue;
+        }
         let mut velocity = Vec3::ZERO;
 
         let local_y = transform.local_x();
@@ -106,6 +177,77 @@ fn player_move(
     }
 }
 
+/// Перемещает идущего игрока: горизонтальная скорость задается клавишами, вертикальная - гравитацией и прыжком.
+/// Незагруженные блоки считаются твердыми, чтобы игрок не проваливался сквозь еще не загруженный мир
+fn player_walk(
+    keys: Res<Input<KeyCode>>,
+    time: Res<Time>,
+    primary_window: Query<&Window, With<PrimaryWindow>>,
+    settings: Res<MovementSettings>,
+    key_bindings: Res<KeyBindings>,
+    world: Res<World>,
+    block_registry: Res<SharedBlockRegistry>,
+    mut query: Query<(&mut PlayerMovement, &PlayerBody, &mut Transform), With<PlayerCamera>>,
+) {
+    let is_grabbed = primary_window.single().cursor.grab_mode != CursorGrabMode::None;
+    // При долгом кадре тело могло бы пролететь сквозь блоки за один шаг
+    let dt = time.delta_seconds().min(0.05);
+    for (mut movement, body, mut transform) in query.iter_mut() {
+        if movement.mode != MovementMode::Walking {
+            continue;
+        }
+
+        let local_x = transform.local_x();
+        let forward = -Vec3::new(local_x.y, -local_x.x, 0.);
+        let right = Vec3::new(local_x.x, local_x.y, 0.);
+        let mut direction = Vec3::ZERO;
+        if is_grabbed {
+            let axis = |positive: KeyCode, negative: KeyCode| {
+                keys.pressed(positive) as i32 as f32 - keys.pressed(negative) as i32 as f32
+            };
+            direction += forward * axis(key_bindings.move_forward, key_bindings.move_backward);
+            direction += right * axis(key_bindings.move_right, key_bindings.move_left);
+        }
+        let horizontal = direction.normalize_or_zero() * settings.walk_speed;
+        movement.velocity.x = horizontal.x;
+        movement.velocity.y = horizontal.y;
+        movement.velocity.z -= settings.gravity * dt;
+        if movement.on_ground && is_grabbed && keys.pressed(key_bindings.move_up) {
+            movement.velocity.z = settings.jump_speed;
+        }
+
+        let is_solid = |pos: IVec3| {
+            world.get_block(pos.into()).map(|block| block_registry.is_solid(&block)).unwrap_or(true)
+        };
+        let offset = movement.velocity * dt;
+        let result = move_aabb(body.aabb(transform.translation), offset, settings.step_height, is_solid);
+        transform.translation = result.aabb.min + body.eye_offset();
+        if result.collided.z {
+            movement.velocity.z = 0.;
+        }
+        movement.on_ground = result.on_ground;
+    }
+}
+
+/// Переключает режим перемещения между полетом и ходьбой
+fn toggle_movement_mode(
+    keys: Res<Input<KeyCode>>,
+    key_bindings: Res<KeyBindings>,
+    mut query: Query<&mut PlayerMovement, With<PlayerCamera>>,
+) {
+    if !keys.just_pressed(key_bindings.toggle_movement_mode) {
+        return;
+    }
+    for mut movement in query.iter_mut() {
+        movement.mode = match movement.mode {
+            MovementMode::Flying => { MovementMode::Walking }
+            MovementMode::Walking => { MovementMode::Flying }
+        };
+        movement.velocity = Vec3::ZERO;
+        movement.on_ground = false;
+    }
+}
+
 /// Handles looking around if cursor is locked
 fn player_look(
     settings: Res<MovementSettings>,
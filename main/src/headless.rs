@@ -0,0 +1,123 @@
+use bevy::app::AppExit;
+use bevy::prelude::*;
+use world_anchor::WorldAnchor;
+use crate::launch_config::LaunchConfig;
+
+/// Плагин режима без окна: создает [WorldAnchor] в заданных позициях и при необходимости завершает работу
+/// после заданного числа тиков. Рендер, камера и ввод в этом режиме не используются
+pub struct HeadlessPlugin {
+    /// Позиции вокруг которых грузится мир
+    pub anchors: Vec<Vec3>,
+
+    /// Радиус загрузки мира вокруг каждой позиции
+    pub anchor_radius: u32,
+
+    /// Радиус загрузки мира вокруг каждой позиции по вертикали
+    pub anchor_vertical_radius: u32,
+
+    /// Через сколько тиков завершить работу, [None] - работать бесконечно
+    pub ticks: Option<u64>,
+}
+
+impl HeadlessPlugin {
+    pub fn from_config(config: &LaunchConfig) -> Self {
+        Self {
+            anchors: config.anchors.clone(),
+            anchor_radius: config.anchor_radius,
+            anchor_vertical_radius: config.anchor_vertical_radius,
+            ticks: config.ticks,
+        }
+    }
+}
+
+impl Plugin for HeadlessPlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .insert_resource(HeadlessAnchors {
+                anchors: self.anchors.clone(),
+                radius: self.anchor_radius,
+                vertical_radius: self.anchor_vertical_radius,
+            })
+            .insert_resource(TickLimit(self.ticks))
+            .add_event::<AppExit>()
+            .add_systems(Startup, spawn_anchors)
+            // Выход отправляется до Last, чтобы мир успел сохраниться в этом же тике
+            .add_systems(PostUpdate, exit_after_ticks)
+        ;
+    }
+}
+
+#[derive(Resource)]
+struct HeadlessAnchors {
+    anchors: Vec<Vec3>,
+    radius: u32,
+    vertical_radius: u32,
+}
+
+/// Оставшееся число тиков
+#[derive(Resource, Deref, DerefMut)]
+struct TickLimit(Option<u64>);
+
+fn spawn_anchors(
+    mut commands: Commands,
+    anchors: Res<HeadlessAnchors>,
+) {
+    for pos in anchors.anchors.iter() {
+        commands.spawn((
+            TransformBundle::from_transform(Transform::from_translation(*pos)),
+            WorldAnchor { load_radius: anchors.radius, vertical_load_radius: anchors.vertical_radius, ..default() },
+        ));
+    }
+    info!("Spawned {} world anchors", anchors.anchors.len());
+}
+
+fn exit_after_ticks(
+    mut tick_limit: ResMut<TickLimit>,
+    mut app_exit_events: EventWriter<AppExit>,
+) {
+    let Some(ticks) = &mut **tick_limit else {
+        return;
+    };
+    *ticks = ticks.saturating_sub(1);
+    if *ticks == 0 {
+        info!("Tick limit reached, exiting");
+        app_exit_events.send(AppExit);
+        **tick_limit = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::app::AppExit;
+    use bevy::math::vec3;
+    use bevy::prelude::*;
+    use world_anchor::WorldAnchor;
+    use crate::headless::HeadlessPlugin;
+
+    #[test]
+    fn spawns_anchors_and_exits_after_ticks() {
+        let mut app = App::new();
+        app.add_plugins(HeadlessPlugin {
+            anchors: vec![Vec3::ZERO, vec3(100., 0., 64.)],
+            anchor_radius: 3,
+            anchor_vertical_radius: 2,
+            ticks: Some(3),
+        });
+
+        let exit_count = |app: &App| app.world.resource::<Events<AppExit>>().len();
+        app.update();
+        app.update();
+        assert_eq!(exit_count(&app), 0);
+        app.update();
+        assert_eq!(exit_count(&app), 1);
+
+        let mut anchors = app.world.query::<(&WorldAnchor, &Transform)>();
+        let positions: Vec<Vec3> = anchors.iter(&app.world).map(|(anchor, transform)| {
+            assert_eq!(anchor.load_radius, 3);
+            assert_eq!(anchor.vertical_load_radius, 2);
+            transform.translation
+        }).collect();
+        assert_eq!(positions.len(), 2);
+        assert!(positions.contains(&vec3(100., 0., 64.)));
+    }
+}
@@ -10,6 +10,12 @@ pub struct KeyBindings {
     pub move_up: KeyCode,
     pub move_down: KeyCode,
     pub toggle_grab_cursor: KeyCode,
+    pub toggle_movement_mode: KeyCode,
+    pub break_block: MouseButton,
+    pub place_block: MouseButton,
+
+    /// Максимальное расстояние до блока который игрок может сломать или к которому может поставить блок
+    pub reach_distance: f32,
 }
 
 impl Default for KeyBindings {
@@ -22,6 +28,10 @@ impl Default for KeyBindings {
             move_up: KeyCode::Space,
             move_down: KeyCode::ShiftLeft,
             toggle_grab_cursor: KeyCode::Escape,
+            toggle_movement_mode: KeyCode::F,
+            break_block: MouseButton::Left,
+            place_block: MouseButton::Right,
+            reach_distance: 6.,
         }
     }
 }
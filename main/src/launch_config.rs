@@ -0,0 +1,287 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+use bevy::math::Vec3;
+use bevy::prelude::default;
+use crate::logic::frame_budget::FrameBudget;
+use crate::logic::world::{ChunkIntegrationBudget, WorldMetadata, WorldMetadataError, WorldSeed, WorldStorageSettings};
+use crate::render::{MeshingMode, MeshUploadBudget};
+
+/// Параметры запуска игры.
+///
+/// Читаются из файла конфигурации и аргументов командной строки. Файл состоит из строк вида `key = value`, строки
+/// начинающиеся с `#` игнорируются. Аргументы имеют вид `--key=value` и приоритетнее файла, путь к файлу задается
+/// аргументом `--config=<path>`.
+///
+/// Поддерживаемые ключи: `seed`, `generator` (`noise`, `flat`, `superflat`, `void`), `world_dir`,
+/// `meshing` (`greedy`, `naive`). Seed и генератор сохраняются в директории мира при ее создании, см [WorldMetadata].
+///
+/// Бюджеты кадра: `chunks_per_frame` и `chunks_frame_time` (в мс) для добавления загруженных чанков в мир,
+/// `meshes_per_frame` и `meshes_frame_time` (в мс) для загрузки мешей чанков.
+///
+/// Ключи режима без окна: `headless` (`true`, `false`), `anchors` (позиции вида `x,y,z` через `;`),
+/// `anchor_radius`, `anchor_vertical_radius`, `tick_rate` (тиков в секунду), `ticks` (завершить работу после
+/// заданного числа тиков).
+///
+/// Сетевая игра: `server` (адрес на котором сервер принимает клиентов, сервер всегда запускается без окна),
+/// `connect` (адрес сервера к которому подключается клиент)
+pub struct LaunchConfig {
+    pub seed: Option<WorldSeed>,
+    pub generator: Option<String>,
+    pub world_dir: Option<PathBuf>,
+    pub meshing: MeshingMode,
+    pub chunk_integration_budget: ChunkIntegrationBudget,
+    pub mesh_upload_budget: MeshUploadBudget,
+    pub headless: bool,
+    pub anchors: Vec<Vec3>,
+    pub anchor_radius: u32,
+    pub anchor_vertical_radius: u32,
+    pub tick_rate: f64,
+    pub ticks: Option<u64>,
+    pub server: Option<SocketAddr>,
+    pub connect: Option<SocketAddr>,
+}
+
+impl Default for LaunchConfig {
+    fn default() -> Self {
+        Self {
+            seed: None,
+            generator: None,
+            world_dir: None,
+            meshing: default(),
+            chunk_integration_budget: default(),
+            mesh_upload_budget: default(),
+            headless: false,
+            anchors: vec![],
+            anchor_radius: 8,
+            anchor_vertical_radius: 4,
+            tick_rate: 20.,
+            ticks: None,
+            server: None,
+            connect: None,
+        }
+    }
+}
+
+impl LaunchConfig {
+    /// Читает параметры запуска из аргументов командной строки текущего процесса
+    pub fn load() -> Self {
+        Self::from_args(std::env::args().skip(1))
+    }
+
+    pub fn from_args(args: impl Iterator<Item=String>) -> Self {
+        let args: Vec<(String, String)> = args
+            .filter_map(|arg| {
+                let arg = arg.strip_prefix("--")?;
+                let (key, value) = arg.split_once('=')?;
+                Some((key.to_string(), value.to_string()))
+            })
+            .collect();
+
+        let mut config = LaunchConfig::default();
+        if let Some((_, path)) = args.iter().find(|(key, _)| key == "config") {
+            match std::fs::read_to_string(path) {
+                Ok(content) => { config.apply_file(&content) }
+                Err(err) => { eprintln!("Can't read config file {}: {}", path, err) }
+            }
+        }
+        for (key, value) in args.iter() {
+            config.apply(key, value);
+        }
+        config
+    }
+
+    fn apply_file(&mut self, content: &str) {
+        for line in content.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            match line.split_once('=') {
+                None => { eprintln!("Incorrect config line: {}", line) }
+                Some((key, value)) => { self.apply(key.trim(), value.trim()) }
+            }
+        }
+    }
+
+    fn apply(&mut self, key: &str, value: &str) {
+        match key {
+            "config" => {}
+            "seed" => {
+                match value.parse() {
+                    Ok(seed) => { self.seed = Some(seed) }
+                    Err(_) => { eprintln!("Incorrect seed: {}", value) }
+                }
+            }
+            "generator" => { self.generator = Some(value.to_string()) }
+            "world_dir" => { self.world_dir = Some(PathBuf::from(value)) }
+            "meshing" => {
+                match value {
+                    "greedy" => { self.meshing = MeshingMode::Greedy }
+                    "naive" => { self.meshing = MeshingMode::Naive }
+                    _ => { eprintln!("Incorrect meshing mode: {}", value) }
+                }
+            }
+            "chunks_per_frame" => { apply_max_items(&mut self.chunk_integration_budget, key, value) }
+            "chunks_frame_time" => { apply_max_time(&mut self.chunk_integration_budget, key, value) }
+            "meshes_per_frame" => { apply_max_items(&mut self.mesh_upload_budget, key, value) }
+            "meshes_frame_time" => { apply_max_time(&mut self.mesh_upload_budget, key, value) }
+            "headless" => {
+                match value.parse() {
+                    Ok(headless) => { self.headless = headless }
+                    Err(_) => { eprintln!("Incorrect headless flag: {}", value) }
+                }
+            }
+            "anchors" => {
+                match parse_positions(value) {
+                    Some(anchors) => { self.anchors = anchors }
+                    None => { eprintln!("Incorrect anchors: {}", value) }
+                }
+            }
+            "anchor_radius" => {
+                match value.parse() {
+                    Ok(radius) => { self.anchor_radius = radius }
+                    Err(_) => { eprintln!("Incorrect anchor radius: {}", value) }
+                }
+            }
+            "anchor_vertical_radius" => {
+                match value.parse() {
+                    Ok(radius) => { self.anchor_vertical_radius = radius }
+                    Err(_) => { eprintln!("Incorrect anchor vertical radius: {}", value) }
+                }
+            }
+            "tick_rate" => {
+                match value.parse() {
+                    Ok(tick_rate) if tick_rate > 0. => { self.tick_rate = tick_rate }
+                    _ => { eprintln!("Incorrect tick rate: {}", value) }
+                }
+            }
+            "ticks" => {
+                match value.parse() {
+                    Ok(ticks) => { self.ticks = Some(ticks) }
+                    Err(_) => { eprintln!("Incorrect ticks count: {}", value) }
+                }
+            }
+            "server" => {
+                match value.parse() {
+                    Ok(addr) => {
+                        self.server = Some(addr);
+                        self.headless = true;
+                    }
+                    Err(_) => { eprintln!("Incorrect server address: {}", value) }
+                }
+            }
+            "connect" => {
+                match value.parse() {
+                    Ok(addr) => { self.connect = Some(addr) }
+                    Err(_) => { eprintln!("Incorrect server address: {}", value) }
+                }
+            }
+            _ => { eprintln!("Unknown config key: {}", key) }
+        }
+    }
+
+    /// Возвращает seed и генератор мира, для уже созданного мира они берутся из его директории
+    pub fn world_metadata(&self) -> Result<WorldMetadata, WorldMetadataError> {
+        WorldMetadata::resolve(&self.world_storage_settings().world_dir, self.seed, self.generator.as_deref())
+    }
+
+    pub fn world_storage_settings(&self) -> WorldStorageSettings {
+        let mut settings = WorldStorageSettings::default();
+        if let Some(world_dir) = &self.world_dir {
+            settings.world_dir = world_dir.clone();
+        }
+        settings
+    }
+}
+
+fn apply_max_items(budget: &mut FrameBudget, key: &str, value: &str) {
+    match value.parse() {
+        Ok(max_items) => { budget.max_items = max_items }
+        Err(_) => { eprintln!("Incorrect {}: {}", key, value) }
+    }
+}
+
+/// Время задается в миллисекундах
+fn apply_max_time(budget: &mut FrameBudget, key: &str, value: &str) {
+    match value.parse::<f64>() {
+        Ok(ms) if ms >= 0. => { budget.max_time = Duration::from_secs_f64(ms / 1000.) }
+        _ => { eprintln!("Incorrect {}: {}", key, value) }
+    }
+}
+
+/// Разбирает список позиций вида `x,y,z;x,y,z`
+fn parse_positions(value: &str) -> Option<Vec<Vec3>> {
+    value
+        .split(';')
+        .map(|pos| pos.trim())
+        .filter(|pos| !pos.is_empty())
+        .map(|pos| {
+            let coords: Vec<f32> = pos.split(',').map(|coord| coord.trim().parse().ok()).collect::<Option<_>>()?;
+            <[f32; 3]>::try_from(coords).ok().map(Vec3::from)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::time::Duration;
+    use bevy::math::vec3;
+    use crate::launch_config::LaunchConfig;
+    use crate::logic::world::{ChunkIntegrationBudget, WorldSeed};
+    use crate::render::MeshingMode;
+
+    fn args(args: &[&str]) -> impl Iterator<Item=String> {
+        args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn parse_args() {
+        let config = LaunchConfig::from_args(args(&[
+            "--seed=42", "--generator=flat", "--world_dir=saves/a", "--meshing=naive", "junk",
+            "--chunks_per_frame=8", "--meshes_frame_time=2.5", "--chunks_frame_time=-1",
+        ]));
+        assert_eq!(config.chunk_integration_budget.max_items, 8);
+        assert_eq!(config.chunk_integration_budget.max_time, ChunkIntegrationBudget::default().max_time);
+        assert_eq!(config.mesh_upload_budget.max_time, Duration::from_micros(2500));
+        assert_eq!(config.seed, Some(WorldSeed(42)));
+        assert_eq!(config.meshing, MeshingMode::Naive);
+        assert_eq!(config.generator.as_deref(), Some("flat"));
+        assert_eq!(config.world_dir, Some(PathBuf::from("saves/a")));
+        assert!(!config.headless);
+    }
+
+    #[test]
+    fn parse_headless_args() {
+        let config = LaunchConfig::from_args(args(&[
+            "--headless=true", "--anchors=0,0,64; -100.5,20,70", "--anchor_radius=4", "--ticks=100", "--tick_rate=0",
+            "--anchor_vertical_radius=3",
+        ]));
+        assert!(config.headless);
+        assert_eq!(config.anchors, vec![vec3(0., 0., 64.), vec3(-100.5, 20., 70.)]);
+        assert_eq!(config.anchor_radius, 4);
+        assert_eq!(config.anchor_vertical_radius, 3);
+        assert_eq!(config.ticks, Some(100));
+        assert_eq!(config.tick_rate, 20.);
+
+        let config = LaunchConfig::from_args(args(&["--anchors=1,2"]));
+        assert!(config.anchors.is_empty());
+
+        let config = LaunchConfig::from_args(args(&["--server=127.0.0.1:7777", "--connect=localhost"]));
+        assert!(config.headless);
+        assert_eq!(config.server, Some("127.0.0.1:7777".parse().unwrap()));
+        assert_eq!(config.connect, None);
+    }
+
+    #[test]
+    fn args_override_config_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("game.cfg");
+        std::fs::write(&path, "# comment\nseed = 1\n\ngenerator = void\n").unwrap();
+
+        let config_arg = format!("--config={}", path.display());
+        let config = LaunchConfig::from_args(args(&[&config_arg, "--seed=2"]));
+        assert_eq!(config.seed, Some(WorldSeed(2)));
+        assert_eq!(config.generator.as_deref(), Some("void"));
+    }
+}
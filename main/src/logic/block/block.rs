@@ -1,12 +1,85 @@
-use crate::logic::block::BlockType;
+use std::io;
+use std::io::{Read, Write};
+use chunk::{BinaryCodec, CHUNK_FORMAT_VERSION};
 
-#[derive(Default)]
+/// Идентификатор типа блока в [crate::logic::block::BlockRegistry]
+#[derive(Default, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct BlockId(pub u16);
+
+/// Блок мира, в чанках хранится только идентификатор типа блока, все свойства блока запрашиваются через
+/// [crate::logic::block::BlockRegistry]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Block {
-    block_type: BlockType,
+    id: BlockId,
 }
 
 impl Block {
-    pub fn new(block_type: BlockType) -> Self {
-        Self { block_type }
+    pub fn new(id: BlockId) -> Self {
+        Self { id }
+    }
+
+    pub fn id(&self) -> BlockId {
+        self.id
+    }
+}
+
+/// Формат: идентификатор типа блока (u16).
+///
+/// В версии 1 формата чанка хранился порядковый номер типа блока (u8) из фиксированного списка: воздух, бедрок, трава.
+/// Бедрок и трава получили в реестре те же идентификаторы, а воздух хранился только как отсутствие блока
+impl BinaryCodec for Block {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.id.0.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Ok(Self::new(BlockId(u16::decode(reader)?)))
+    }
+
+    fn decode_legacy<R: Read>(reader: &mut R, version: u8) -> io::Result<Self> {
+        match (version, u8::decode(reader)?) {
+            (1, ordinal @ (1 | 2)) => { Ok(Self::new(BlockId(ordinal as u16))) }
+            (1, ordinal) => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, format!("unknown block type {}", ordinal)))
+            }
+            _ => {
+                Err(io::Error::new(io::ErrorKind::InvalidData, format!(
+                    "unsupported chunk format version {}, current is {}", version, CHUNK_FORMAT_VERSION,
+                )))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::uvec3;
+    use chunk::BinaryCodec;
+    use crate::logic::block::{Block, BlockId};
+    use crate::logic::chunk::Chunk;
+
+    #[test]
+    fn version_1_chunk_is_migrated() {
+        // Чанк версии 1: версия, палитра из отсутствия блока, бедрока и травы, ширина индекса 2 бита, индексы
+        let mut bytes = vec![1, 3, 0, 0, 1, 1, 1, 2, 2];
+        let grass_index = 2 * 16 * 16 + 3 * 16 + 1;
+        for word in 0..128 {
+            let mut value = 0u64;
+            for index in word * 32..(word + 1) * 32 {
+                let palette_index = if index == 0 { 1 } else if index == grass_index { 2 } else { 0 };
+                value |= palette_index << ((index % 32) * 2);
+            }
+            bytes.extend(value.to_le_bytes());
+        }
+
+        let chunk = Chunk::decode(&mut bytes.as_slice()).unwrap();
+        let block_at = |x, y, z| chunk[&uvec3(x, y, z).try_into().unwrap()];
+        assert_eq!(block_at(0, 0, 0), Some(Block::new(BlockId(1))));
+        assert_eq!(block_at(2, 3, 1), Some(Block::new(BlockId(2))));
+        assert_eq!(block_at(5, 5, 5), None);
+
+        // Неизвестный тип блока старого формата
+        bytes[7] = 7;
+        assert!(Chunk::decode(&mut bytes.as_slice()).is_err());
     }
 }
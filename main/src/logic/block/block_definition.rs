@@ -0,0 +1,110 @@
+use bevy::math::IVec3;
+use serde::Deserialize;
+
+/// Описание типа блока, загружается из файлов определений, см [crate::logic::block::BlockRegistry]
+#[derive(Deserialize, Clone, Debug)]
+pub struct BlockDefinition {
+    /// Числовой идентификатор, именно он хранится в чанках и сохранениях, поэтому не должен меняться
+    pub id: u16,
+
+    /// Уникальное имя блока
+    pub name: String,
+
+    /// Твердый ли блок, через твердые блоки нельзя пройти
+    #[serde(default = "default_solid")]
+    pub solid: bool,
+
+    /// Прозрачный ли блок, грани соседних блоков за прозрачным блоком рендерятся
+    #[serde(default)]
+    pub transparent: bool,
+
+    /// Яркость света излучаемого блоком, от 0 до 15
+    #[serde(default)]
+    pub light_emission: u8,
+
+    /// Текстуры граней блока
+    #[serde(default)]
+    pub textures: BlockTextures,
+
+    /// Прочность блока, отрицательное значение означает что блок нельзя разрушить
+    #[serde(default = "default_hardness")]
+    pub hardness: f32,
+}
+
+impl Default for BlockDefinition {
+    fn default() -> Self {
+        Self {
+            id: 0,
+            name: String::new(),
+            solid: default_solid(),
+            transparent: false,
+            light_emission: 0,
+            textures: BlockTextures::default(),
+            hardness: default_hardness(),
+        }
+    }
+}
+
+fn default_solid() -> bool {
+    true
+}
+
+fn default_hardness() -> f32 {
+    1.
+}
+
+/// Имена текстур граней блока.
+///
+/// Для каждой грани берется первая заданная текстура из цепочки: конкретная грань, `top`/`bottom`/`side`, `all`.
+/// Ось Z направлена вверх
+#[derive(Deserialize, Clone, Debug, Default)]
+#[serde(default)]
+pub struct BlockTextures {
+    pub all: Option<String>,
+    pub side: Option<String>,
+    pub top: Option<String>,
+    pub bottom: Option<String>,
+    pub pos_x: Option<String>,
+    pub neg_x: Option<String>,
+    pub pos_y: Option<String>,
+    pub neg_y: Option<String>,
+}
+
+impl BlockTextures {
+    /// Возвращает имя текстуры для грани с переданной нормалью
+    pub fn get(&self, normal: IVec3) -> Option<&str> {
+        let (face, group) = match normal.to_array() {
+            [1, 0, 0] => { (&self.pos_x, &self.side) }
+            [-1, 0, 0] => { (&self.neg_x, &self.side) }
+            [0, 1, 0] => { (&self.pos_y, &self.side) }
+            [0, -1, 0] => { (&self.neg_y, &self.side) }
+            [0, 0, 1] => { (&None, &self.top) }
+            [0, 0, -1] => { (&None, &self.bottom) }
+            _ => { panic!("Incorrect face normal {}", normal) }
+        };
+        face.as_deref().or(group.as_deref()).or(self.all.as_deref())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::IVec3;
+    use crate::logic::block::block_definition::BlockTextures;
+
+    #[test]
+    fn textures_fallback() {
+        let textures = BlockTextures {
+            all: Some("all".to_string()),
+            side: Some("side".to_string()),
+            top: Some("top".to_string()),
+            pos_x: Some("pos_x".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(textures.get(IVec3::X), Some("pos_x"));
+        assert_eq!(textures.get(IVec3::NEG_X), Some("side"));
+        assert_eq!(textures.get(IVec3::Y), Some("side"));
+        assert_eq!(textures.get(IVec3::Z), Some("top"));
+        assert_eq!(textures.get(IVec3::NEG_Z), Some("all"));
+        assert_eq!(BlockTextures::default().get(IVec3::Z), None);
+    }
+}
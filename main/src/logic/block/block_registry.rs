@@ -0,0 +1,210 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::{fs, io};
+use bevy::prelude::{Deref, Resource};
+use bevy::utils::HashMap;
+use crate::logic::block::{Block, BlockDefinition, BlockId};
+
+/// Максимальная яркость света излучаемого блоком
+pub const MAX_LIGHT_EMISSION: u8 = 15;
+
+/// Реестр всех типов блоков.
+///
+/// Определения блоков загружаются из директории с файлами `.ron` или `.json`, каждый файл содержит список
+/// [BlockDefinition]. Воздух не является блоком, в чанках он хранится как [None]
+#[derive(Default)]
+pub struct BlockRegistry {
+    definitions: Vec<Option<BlockDefinition>>,
+    by_name: HashMap<String, BlockId>,
+}
+
+#[derive(Debug)]
+pub enum BlockRegistryError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, String),
+    DuplicateId(u16),
+    DuplicateName(String),
+    IncorrectLightEmission(String),
+    UnknownBlock(String),
+}
+
+impl Display for BlockRegistryError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlockRegistryError::Io(path, err) => { write!(f, "Can't read {}: {}", path.display(), err) }
+            BlockRegistryError::Parse(path, err) => { write!(f, "Can't parse {}: {}", path.display(), err) }
+            BlockRegistryError::DuplicateId(id) => { write!(f, "Duplicate block id {}", id) }
+            BlockRegistryError::DuplicateName(name) => { write!(f, "Duplicate block name {}", name) }
+            BlockRegistryError::IncorrectLightEmission(name) => {
+                write!(f, "Light emission of block {} is greater than {}", name, MAX_LIGHT_EMISSION)
+            }
+            BlockRegistryError::UnknownBlock(name) => { write!(f, "Unknown block {}", name) }
+        }
+    }
+}
+
+impl std::error::Error for BlockRegistryError {}
+
+impl BlockRegistry {
+    pub fn new(definitions: impl IntoIterator<Item=BlockDefinition>) -> Result<Self, BlockRegistryError> {
+        let mut registry = Self::default();
+        for definition in definitions {
+            registry.register(definition)?;
+        }
+        Ok(registry)
+    }
+
+    /// Загружает определения блоков из всех файлов `.ron` и `.json` в переданной директории, остальные файлы
+    /// игнорируются
+    pub fn load_dir(dir: &Path) -> Result<Self, BlockRegistryError> {
+        let mut paths = fs::read_dir(dir)
+            .and_then(|entries| entries.map(|entry| entry.map(|entry| entry.path())).collect::<io::Result<Vec<_>>>())
+            .map_err(|err| BlockRegistryError::Io(dir.to_path_buf(), err))?;
+        // Порядок файлов не должен влиять на результат, в том числе на текст ошибок
+        paths.sort();
+
+        let mut definitions = Vec::new();
+        for path in paths {
+            if !matches!(path.extension().and_then(|ext| ext.to_str()), Some("ron") | Some("json")) {
+                continue;
+            }
+            let content = fs::read_to_string(&path).map_err(|err| BlockRegistryError::Io(path.clone(), err))?;
+            definitions.extend(parse_definitions(&path, &content)?);
+        }
+        Self::new(definitions)
+    }
+
+    fn register(&mut self, definition: BlockDefinition) -> Result<(), BlockRegistryError> {
+        if definition.light_emission > MAX_LIGHT_EMISSION {
+            return Err(BlockRegistryError::IncorrectLightEmission(definition.name));
+        }
+        if self.by_name.contains_key(&definition.name) {
+            return Err(BlockRegistryError::DuplicateName(definition.name));
+        }
+        let index = definition.id as usize;
+        if self.definitions.len() <= index {
+            self.definitions.resize(index + 1, None);
+        }
+        if self.definitions[index].is_some() {
+            return Err(BlockRegistryError::DuplicateId(definition.id));
+        }
+        self.by_name.insert(definition.name.clone(), BlockId(definition.id));
+        self.definitions[index] = Some(definition);
+        Ok(())
+    }
+
+    pub fn get(&self, id: BlockId) -> Option<&BlockDefinition> {
+        self.definitions.get(id.0 as usize)?.as_ref()
+    }
+
+    pub fn get_id(&self, name: &str) -> Option<BlockId> {
+        self.by_name.get(name).copied()
+    }
+
+    /// Возвращает блок по имени, если такого блока нет в реестре возвращает [BlockRegistryError::UnknownBlock]
+    pub fn block(&self, name: &str) -> Result<Block, BlockRegistryError> {
+        self.get_id(name).map(Block::new).ok_or_else(|| BlockRegistryError::UnknownBlock(name.to_string()))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&BlockDefinition> {
+        self.definitions.iter().flatten()
+    }
+
+    /// Возвращает закрывает ли блок соседние грани. Блоки отсутствующие в реестре считаются непрозрачными
+    pub fn is_opaque(&self, block: &Option<Block>) -> bool {
+        match block {
+            None => { false }
+            Some(block) => { self.get(block.id()).map(|definition| !definition.transparent).unwrap_or(true) }
+        }
+    }
+
+    /// Возвращает является ли блок препятствием для движения. Блоки отсутствующие в реестре считаются твердыми
+    pub fn is_solid(&self, block: &Option<Block>) -> bool {
+        match block {
+            None => { false }
+            Some(block) => { self.get(block.id()).map(|definition| definition.solid).unwrap_or(true) }
+        }
+    }
+}
+
+fn parse_definitions(path: &Path, content: &str) -> Result<Vec<BlockDefinition>, BlockRegistryError> {
+    let result = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") => { serde_json::from_str(content).map_err(|err| err.to_string()) }
+        _ => { ron::from_str(content).map_err(|err| err.to_string()) }
+    };
+    result.map_err(|err| BlockRegistryError::Parse(path.to_path_buf(), err))
+}
+
+/// Реестр блоков используемый игрой, может свободно клонироваться для передачи в асинхронные задачи
+#[derive(Resource, Clone, Deref)]
+pub struct SharedBlockRegistry(Arc<BlockRegistry>);
+
+impl SharedBlockRegistry {
+    pub fn new(registry: BlockRegistry) -> Self {
+        Self(Arc::new(registry))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::IVec3;
+    use crate::logic::block::{Block, BlockDefinition, BlockId, BlockRegistry};
+    use crate::logic::block::block_registry::BlockRegistryError;
+
+    #[test]
+    fn load_ron_and_json() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.ron"), r#"
+            #![enable(implicit_some)]
+            [
+                (id: 1, name: "stone", hardness: 1.5, textures: (all: "stone.png")),
+                (id: 3, name: "glass", transparent: true),
+            ]
+        "#).unwrap();
+        std::fs::write(dir.path().join("b.json"), r#"
+            [{ "id": 2, "name": "lamp", "light_emission": 15, "textures": { "top": "lamp_top.png" } }]
+        "#).unwrap();
+        std::fs::write(dir.path().join("readme.txt"), "not a definition").unwrap();
+
+        let registry = BlockRegistry::load_dir(dir.path()).unwrap();
+        assert_eq!(registry.iter().count(), 3);
+
+        let stone = registry.get(registry.get_id("stone").unwrap()).unwrap();
+        assert_eq!(stone.hardness, 1.5);
+        assert!(stone.solid);
+        assert_eq!(stone.textures.get(IVec3::Z), Some("stone.png"));
+
+        let lamp = registry.get(BlockId(2)).unwrap();
+        assert_eq!(lamp.light_emission, 15);
+        assert_eq!(lamp.textures.get(IVec3::Z), Some("lamp_top.png"));
+
+        assert!(registry.is_opaque(&Some(registry.block("stone").unwrap())));
+        assert!(!registry.is_opaque(&Some(registry.block("glass").unwrap())));
+        assert!(!registry.is_opaque(&None));
+        assert!(registry.is_opaque(&Some(Block::new(BlockId(100)))));
+        assert!(registry.is_solid(&Some(registry.block("glass").unwrap())));
+        assert!(!registry.is_solid(&None));
+        assert!(matches!(registry.block("dirt"), Err(BlockRegistryError::UnknownBlock(_))));
+    }
+
+    #[test]
+    fn reject_duplicates() {
+        let definition = |id: u16, name: &str| BlockDefinition { id, name: name.to_string(), ..Default::default() };
+        assert!(matches!(
+            BlockRegistry::new([definition(1, "a"), definition(1, "b")]),
+            Err(BlockRegistryError::DuplicateId(1))
+        ));
+        assert!(matches!(
+            BlockRegistry::new([definition(1, "a"), definition(2, "a")]),
+            Err(BlockRegistryError::DuplicateName(_))
+        ));
+    }
+
+    #[test]
+    fn reject_incorrect_file() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.ron"), "[(name: \"no_id\")]").unwrap();
+        assert!(matches!(BlockRegistry::load_dir(dir.path()), Err(BlockRegistryError::Parse(_, _))));
+    }
+}
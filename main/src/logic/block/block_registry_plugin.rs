@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+use bevy::app::{App, Plugin};
+use bevy::asset::FileAssetIo;
+use bevy::log::info;
+use crate::logic::block::{BlockRegistry, SharedBlockRegistry};
+
+/// Загружает [BlockRegistry] при сборке плагина, должен быть добавлен до плагинов использующих блоки
+pub struct BlockRegistryPlugin {
+    /// Директория с файлами определений блоков
+    pub dir: PathBuf,
+}
+
+impl Default for BlockRegistryPlugin {
+    fn default() -> Self {
+        Self {
+            dir: FileAssetIo::get_base_path().join("assets").join("blocks"),
+        }
+    }
+}
+
+impl Plugin for BlockRegistryPlugin {
+    fn build(&self, app: &mut App) {
+        let registry = match BlockRegistry::load_dir(&self.dir) {
+            Ok(registry) => { registry }
+            Err(err) => { panic!("Can't load block registry: {}", err) }
+        };
+        info!("Loaded {} block definitions", registry.iter().count());
+        app.insert_resource(SharedBlockRegistry::new(registry));
+    }
+}
@@ -1,7 +0,0 @@
-#[derive(Default)]
-pub enum BlockType {
-    #[default]
-    AIR,
-    BEDROCK,
-    GRASS,
-}
\ No newline at end of file
@@ -1,5 +1,9 @@
-mod block_type;
 mod block;
+mod block_definition;
+mod block_registry;
+mod block_registry_plugin;
 
-pub use block_type::BlockType;
-pub use block::Block;
+pub use block::{Block, BlockId};
+pub use block_definition::BlockDefinition;
+pub use block_registry::{BlockRegistry, BlockRegistryError, SharedBlockRegistry};
+pub use block_registry_plugin::BlockRegistryPlugin;
@@ -0,0 +1,176 @@
+use bevy::math::{BVec3, IVec3, Vec3};
+
+/// Допуск при сравнении координат, без него тело стоящее вплотную к блоку считалось бы пересекающим его
+const EPSILON: f32 = 1e-4;
+
+/// Ограничивающий параллелепипед со сторонами параллельными осям
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn new(min: Vec3, max: Vec3) -> Self {
+        Self { min, max }
+    }
+
+    pub fn translate(&self, offset: Vec3) -> Self {
+        Self::new(self.min + offset, self.max + offset)
+    }
+}
+
+/// Результат [move_aabb]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Movement {
+    /// Параллелепипед после перемещения
+    pub aabb: Aabb,
+
+    /// По каким осям перемещение было остановлено блоками
+    pub collided: BVec3,
+
+    /// Остановлено ли перемещение вниз, то есть стоит ли тело на блоке
+    pub on_ground: bool,
+}
+
+/// Перемещает параллелепипед на `offset` не давая ему войти в блоки для которых `is_solid` возвращает true.
+///
+/// Перемещение выполняется по очереди вдоль каждой оси, начиная с вертикальной, поэтому тело скользит вдоль стен.
+/// Если горизонтальное перемещение стоящего на блоках тела остановлено, тело пробует подняться на уступ высотой
+/// не больше `step_height`
+pub fn move_aabb(aabb: Aabb, offset: Vec3, step_height: f32, is_solid: impl Fn(IVec3) -> bool) -> Movement {
+    let movement = move_by_axes(aabb, offset, &is_solid);
+    let is_blocked_horizontally = movement.collided.x || movement.collided.y;
+    if step_height <= 0. || !is_blocked_horizontally || !movement.on_ground {
+        return movement;
+    }
+
+    // Поднимаемся, двигаемся по горизонтали и опускаемся обратно, подъем засчитывается только если так удалось
+    // продвинуться дальше
+    let up = sweep_axis(aabb, 2, step_height, &is_solid);
+    let raised = move_by_axes(aabb.translate(Vec3::Z * up), Vec3::new(offset.x, offset.y, 0.), &is_solid);
+    let down = sweep_axis(raised.aabb, 2, offset.z.min(0.) - up, &is_solid);
+    let stepped = raised.aabb.translate(Vec3::Z * down);
+    let horizontal_distance = |moved: &Aabb| (moved.min - aabb.min).truncate().length_squared();
+    if horizontal_distance(&stepped) <= horizontal_distance(&movement.aabb) + EPSILON {
+        return movement;
+    }
+    Movement { aabb: stepped, collided: raised.collided, on_ground: true }
+}
+
+fn move_by_axes(aabb: Aabb, offset: Vec3, is_solid: &impl Fn(IVec3) -> bool) -> Movement {
+    let mut aabb = aabb;
+    let mut collided = BVec3::FALSE;
+    for axis in [2, 0, 1] {
+        let delta = sweep_axis(aabb, axis, offset[axis], is_solid);
+        collided.set(axis, (delta - offset[axis]).abs() > EPSILON);
+        let mut shift = Vec3::ZERO;
+        shift[axis] = delta;
+        aabb = aabb.translate(shift);
+    }
+    Movement { aabb, collided, on_ground: collided.z && offset.z < 0. }
+}
+
+/// Возвращает на сколько можно сдвинуть параллелепипед вдоль оси `axis`, не больше `delta`
+fn sweep_axis(aabb: Aabb, axis: usize, delta: f32, is_solid: &impl Fn(IVec3) -> bool) -> f32 {
+    if delta == 0. {
+        return 0.;
+    }
+    let (a, b) = match axis {
+        0 => { (1, 2) }
+        1 => { (0, 2) }
+        _ => { (0, 1) }
+    };
+    // Блоки по двум другим осям, которые пересекает параллелепипед
+    let cells = |axis: usize| (aabb.min[axis] + EPSILON).floor() as i32..(aabb.max[axis] - EPSILON).ceil() as i32;
+    let is_layer_solid = |layer: i32| {
+        cells(a).any(|i| cells(b).any(|j| {
+            let mut pos = IVec3::ZERO;
+            pos[axis] = layer;
+            pos[a] = i;
+            pos[b] = j;
+            is_solid(pos)
+        }))
+    };
+
+    // Проверяем слои блоков по ходу движения начиная с ближайшего, блоки которые тело уже пересекает пропускаются
+    if delta > 0. {
+        let start = (aabb.max[axis] - EPSILON).ceil() as i32;
+        let end = (aabb.max[axis] + delta).ceil() as i32;
+        for layer in start..end {
+            if is_layer_solid(layer) {
+                return (layer as f32 - aabb.max[axis]).clamp(0., delta);
+            }
+        }
+    } else {
+        let start = (aabb.min[axis] + EPSILON).floor() as i32 - 1;
+        let end = (aabb.min[axis] + delta).floor() as i32;
+        for layer in (end..=start).rev() {
+            if is_layer_solid(layer) {
+                return (layer as f32 + 1. - aabb.min[axis]).clamp(delta, 0.);
+            }
+        }
+    }
+    delta
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{IVec3, ivec3, vec3, Vec3};
+    use crate::logic::collision::{Aabb, move_aabb};
+
+    /// Пол на высоте 0, стена высотой в один блок при x = 3 и стена высотой в два блока при y = 3
+    fn is_solid(pos: IVec3) -> bool {
+        pos.z == 0 || (pos.x == 3 && pos.z == 1) || (pos.y == 3 && (1..=2).contains(&pos.z))
+    }
+
+    /// Тело размером 0.6 x 0.6 x 1.8 стоящее ногами в точке `feet`
+    fn body(feet: Vec3) -> Aabb {
+        Aabb::new(feet - vec3(0.3, 0.3, 0.), feet + vec3(0.3, 0.3, 1.8))
+    }
+
+    #[test]
+    fn falling_body_lands_on_floor() {
+        let movement = move_aabb(body(vec3(0.5, 0.5, 3.)), vec3(0., 0., -5.), 0., is_solid);
+        assert!((movement.aabb.min - body(vec3(0.5, 0.5, 1.)).min).length() < 1e-4);
+        assert!(movement.on_ground);
+        assert!(movement.collided.z);
+
+        let movement = move_aabb(body(vec3(0.5, 0.5, 3.)), vec3(0., 0., -1.), 0., is_solid);
+        assert!(!movement.on_ground);
+    }
+
+    #[test]
+    fn wall_stops_and_slides() {
+        // Без подъема на уступ стена в один блок останавливает движение по x, движение по y продолжается
+        let movement = move_aabb(body(vec3(1.5, 0.5, 1.)), vec3(2., 0.5, -0.1), 0., is_solid);
+        assert!(movement.collided.x && !movement.collided.y);
+        assert!((movement.aabb.max.x - 3.).abs() < 1e-4);
+        assert!((movement.aabb.min.y - 0.7).abs() < 1e-4);
+        assert!(movement.on_ground);
+    }
+
+    #[test]
+    fn step_up_on_low_wall_only() {
+        let movement = move_aabb(body(vec3(2.5, 0.5, 1.)), vec3(1., 0., -0.1), 1., is_solid);
+        assert!((movement.aabb.min - body(vec3(3.5, 0.5, 2.)).min).length() < 1e-4);
+        assert!(movement.on_ground);
+
+        // Стена в два блока выше уступа
+        let movement = move_aabb(body(vec3(0.5, 2.5, 1.)), vec3(0., 1., -0.1), 1., is_solid);
+        assert!((movement.aabb.max.y - 3.).abs() < 1e-4);
+        assert!((movement.aabb.min.z - 1.).abs() < 1e-4);
+
+        // В воздухе на уступ не поднимаемся
+        let movement = move_aabb(body(vec3(2.5, 0.5, 1.5)), vec3(1., 0., 0.1), 1., is_solid);
+        assert!((movement.aabb.max.x - 3.).abs() < 1e-4);
+    }
+
+    #[test]
+    fn ceiling_stops_jump() {
+        let is_solid = |pos: IVec3| pos == ivec3(0, 0, 3) || pos.z == 0;
+        let movement = move_aabb(body(vec3(0.5, 0.5, 1.)), vec3(0., 0., 2.), 0., is_solid);
+        assert!(movement.collided.z && !movement.on_ground);
+        assert!((movement.aabb.max.z - 3.).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+/// Ограничение работы выполняемой за один кадр, остальная работа откладывается на следующие кадры
+#[derive(Clone, Copy, Debug)]
+pub struct FrameBudget {
+    /// Наибольшее число элементов обрабатываемых за кадр
+    pub max_items: usize,
+
+    /// Наибольшее время работы за кадр. Время проверяется перед каждым элементом, поэтому последний начатый элемент
+    /// может превысить бюджет на время своей обработки
+    pub max_time: Duration,
+}
+
+impl FrameBudget {
+    /// Начинает отсчет бюджета текущего кадра
+    pub fn start(&self) -> FrameBudgetTracker {
+        FrameBudgetTracker { budget: *self, started: Instant::now(), items: 0 }
+    }
+}
+
+/// Расход [FrameBudget] в текущем кадре
+pub struct FrameBudgetTracker {
+    budget: FrameBudget,
+    started: Instant,
+    items: usize,
+}
+
+impl FrameBudgetTracker {
+    /// Возвращает можно ли обработать еще один элемент и если можно учитывает его. Первый элемент кадра разрешается
+    /// всегда если `max_items` больше нуля, поэтому работа не останавливается даже при очень медленных элементах
+    pub fn try_take(&mut self) -> bool {
+        if self.items >= self.budget.max_items {
+            return false;
+        }
+        if self.items > 0 && self.started.elapsed() >= self.budget.max_time {
+            return false;
+        }
+        self.items += 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::logic::frame_budget::FrameBudget;
+
+    #[test]
+    fn budget_limits_items_and_time() {
+        let mut tracker = FrameBudget { max_items: 2, max_time: Duration::from_secs(60) }.start();
+        assert!(tracker.try_take());
+        assert!(tracker.try_take());
+        assert!(!tracker.try_take());
+
+        let mut tracker = FrameBudget { max_items: 100, max_time: Duration::from_millis(1) }.start();
+        assert!(tracker.try_take());
+        std::thread::sleep(Duration::from_millis(2));
+        assert!(!tracker.try_take());
+
+        // Даже при нулевом времени за кадр обрабатывается один элемент
+        let mut tracker = FrameBudget { max_items: 100, max_time: Duration::ZERO }.start();
+        assert!(tracker.try_take());
+        assert!(!tracker.try_take());
+        let mut tracker = FrameBudget { max_items: 0, max_time: Duration::from_secs(60) }.start();
+        assert!(!tracker.try_take());
+    }
+}
@@ -0,0 +1,115 @@
+use std::sync::{Arc, RwLock};
+use chunk::{CHUNK_SIZE, ChunkBlockPos, ShardedChunkMap};
+
+/// Максимальный уровень освещенности
+pub const MAX_LIGHT: u8 = 15;
+
+/// Тип освещения
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LightKind {
+    /// Свет неба, без потерь распространяется вниз от открытого неба, см [SkyExposure]
+    Sky,
+
+    /// Свет излучаемый блоками, см [crate::logic::block::BlockDefinition::light_emission]
+    Block,
+}
+
+/// Колонны блоков чанка над которыми открытое небо, то есть выше чанка в этой колонне нет сгенерированных блоков.
+///
+/// Используется пока чанк над этим чанком не загружен,
+/// см [crate::logic::world::generator::WorldGenerator::surface_height]
+#[derive(Clone, PartialEq, Debug)]
+pub struct SkyExposure(Vec<bool>);
+
+impl Default for SkyExposure {
+    fn default() -> Self {
+        Self(vec![false; CHUNK_SIZE * CHUNK_SIZE])
+    }
+}
+
+impl SkyExposure {
+    /// Заполняет колонны по функции от локальных координат x и y
+    pub fn from_fn(is_exposed: impl Fn(u32, u32) -> bool) -> Self {
+        let size = CHUNK_SIZE as u32;
+        Self((0..size).flat_map(|x| (0..size).map(move |y| (x, y))).map(|(x, y)| is_exposed(x, y)).collect())
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> bool {
+        self.0[x as usize * CHUNK_SIZE + y as usize]
+    }
+}
+
+/// Освещенность блоков одного чанка, хранится рядом с [crate::logic::chunk::Chunk].
+///
+/// Для каждого блока хранится один байт, старшие 4 бита - свет неба, младшие - свет блоков
+#[derive(Clone)]
+pub struct ChunkLight {
+    data: Vec<u8>,
+    sky_exposure: SkyExposure,
+}
+
+impl Default for ChunkLight {
+    fn default() -> Self {
+        Self {
+            data: vec![0; CHUNK_SIZE * CHUNK_SIZE * CHUNK_SIZE],
+            sky_exposure: SkyExposure::default(),
+        }
+    }
+}
+
+impl ChunkLight {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_sky_exposure(sky_exposure: SkyExposure) -> Self {
+        Self { sky_exposure, ..Self::new() }
+    }
+
+    pub fn sky_exposure(&self) -> &SkyExposure {
+        &self.sky_exposure
+    }
+
+    pub fn get(&self, kind: LightKind, pos: &ChunkBlockPos) -> u8 {
+        let value = self.data[to_index(pos)];
+        match kind {
+            LightKind::Sky => { value >> 4 }
+            LightKind::Block => { value & 0x0f }
+        }
+    }
+
+    pub fn set(&mut self, kind: LightKind, pos: &ChunkBlockPos, light: u8) {
+        debug_assert!(light <= MAX_LIGHT);
+        let value = &mut self.data[to_index(pos)];
+        *value = match kind {
+            LightKind::Sky => { (*value & 0x0f) | (light << 4) }
+            LightKind::Block => { (*value & 0xf0) | light }
+        };
+    }
+}
+
+/// Порядок совпадает с порядком блоков в [crate::logic::chunk::Chunk]
+fn to_index(pos: &ChunkBlockPos) -> usize {
+    (pos.x as usize * CHUNK_SIZE + pos.y as usize) * CHUNK_SIZE + pos.z as usize
+}
+
+/// Освещенность загруженных чанков
+pub type LightMap = Arc<ShardedChunkMap<Arc<RwLock<ChunkLight>>>>;
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::uvec3;
+    use crate::logic::light::{ChunkLight, LightKind};
+
+    #[test]
+    fn sky_and_block_light_are_independent() {
+        let mut light = ChunkLight::new();
+        let pos = uvec3(3, 15, 7).try_into().unwrap();
+        light.set(LightKind::Sky, &pos, 15);
+        light.set(LightKind::Block, &pos, 4);
+        light.set(LightKind::Sky, &pos, 9);
+        assert_eq!(light.get(LightKind::Sky, &pos), 9);
+        assert_eq!(light.get(LightKind::Block, &pos), 4);
+        assert_eq!(light.get(LightKind::Sky, &uvec3(3, 15, 6).try_into().unwrap()), 0);
+    }
+}
@@ -0,0 +1,541 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, RwLock};
+use bevy::math::IVec3;
+use bevy::utils::{HashMap, HashSet};
+use chunk::{CHUNK_SIZE, ChunkBlockPos, ChunkPos};
+use crate::logic::block::{Block, BlockRegistry};
+use crate::logic::chunk::{Chunk, ChunkMap};
+use crate::logic::light::{ChunkLight, LightKind, LightMap, MAX_LIGHT, SkyExposure};
+use crate::logic::world::World;
+
+/// Направления распространения света
+const DIRECTIONS: [IVec3; 6] = [IVec3::X, IVec3::NEG_X, IVec3::Y, IVec3::NEG_Y, IVec3::Z, IVec3::NEG_Z];
+
+/// Доступ к блокам и освещенности по абсолютным координатам блоков
+trait LightStorage {
+    /// Возвращает блок, [None] если чанк не загружен
+    fn get_block(&mut self, pos: IVec3) -> Option<Option<Block>>;
+
+    /// Возвращает освещенность блока, [None] если чанк не загружен
+    fn get_light(&mut self, kind: LightKind, pos: IVec3) -> Option<u8>;
+
+    /// Устанавливает освещенность блока, для незагруженных чанков ничего не делает
+    fn set_light(&mut self, kind: LightKind, pos: IVec3, light: u8);
+
+    /// Возвращает освещается ли блок открытым небом: блок лежит в верхнем слое чанка, чанк над ним не загружен, а
+    /// колонна открыта по [SkyExposure]
+    fn is_open_to_sky(&mut self, pos: IVec3) -> bool;
+}
+
+/// Рассчитывает освещенность чанка без учета соседних чанков.
+///
+/// Свет соседей добавляется после загрузки чанка в мир, см [LightUpdater::on_chunk_loaded]. Пока чанк над этим
+/// не загружен свет неба приходит сверху в колонны открытые по `sky_exposure`
+pub fn compute_chunk_light(
+    registry: &BlockRegistry,
+    chunk: &Chunk,
+    chunk_pos: ChunkPos,
+    sky_exposure: SkyExposure,
+) -> ChunkLight {
+    let mut light = ChunkLight::with_sky_exposure(sky_exposure);
+    let mut storage = SingleChunkStorage { chunk, light: &mut light, chunk_pos };
+    for kind in [LightKind::Sky, LightKind::Block] {
+        let mut queue = VecDeque::new();
+        for (block_pos, block) in chunk.into_iter() {
+            let pos = chunk_pos.get_absolute_coord() + block_pos.as_ivec3();
+            let source = source_light(registry, &mut storage, kind, pos, block);
+            if source > 0 {
+                storage.set_light(kind, pos, source);
+                queue.push_back(pos);
+            }
+        }
+        propagate_increase(registry, &mut storage, kind, queue);
+    }
+    light
+}
+
+/// Инкрементально обновляет освещенность загруженных чанков мира.
+///
+/// Запоминает чанки меши которых нужно перестроить из-за изменения освещенности, в том числе соседние чанки если
+/// свет изменился на их границе
+pub struct LightUpdater<'a> {
+    registry: &'a BlockRegistry,
+    storage: WorldStorage<'a>,
+}
+
+impl<'a> LightUpdater<'a> {
+    pub fn new(registry: &'a BlockRegistry, world: &'a World) -> Self {
+        Self::from_maps(registry, &world.chunk_map, &world.light_map)
+    }
+
+    pub fn from_maps(registry: &'a BlockRegistry, chunk_map: &'a ChunkMap, light_map: &'a LightMap) -> Self {
+        Self {
+            registry,
+            storage: WorldStorage { chunk_map, light_map, chunks: HashMap::new(), changed: HashSet::new() },
+        }
+    }
+
+    /// Распространяет свет через границы только что загруженного чанка в обе стороны.
+    ///
+    /// Свет незагруженных чанков не учитывается, поэтому до загрузки всех соседей освещенность может быть ниже
+    /// настоящей, но никогда не выше
+    pub fn on_chunk_loaded(&mut self, chunk_pos: ChunkPos) {
+        // Чанк мог быть закеширован как не загруженный при обработке соседнего чанка
+        self.storage.chunks.remove(&chunk_pos);
+        // Верхний слой чанка снизу больше не освещается небом напрямую, теперь свет приходит из этого чанка
+        self.update_sky_below(chunk_pos);
+        let origin = chunk_pos.get_absolute_coord();
+        let max = CHUNK_SIZE as i32 - 1;
+        let mut queue = VecDeque::new();
+        for x in 0..=max {
+            for y in 0..=max {
+                for z in 0..=max {
+                    let local = IVec3::new(x, y, z);
+                    let mut is_border = false;
+                    for dir in DIRECTIONS {
+                        let outer = local + dir;
+                        if outer.min_element() < 0 || outer.max_element() > max {
+                            queue.push_back(origin + outer);
+                            is_border = true;
+                        }
+                    }
+                    if is_border {
+                        queue.push_back(origin + local);
+                    }
+                }
+            }
+        }
+        for kind in [LightKind::Sky, LightKind::Block] {
+            propagate_increase(self.registry, &mut self.storage, kind, queue.clone());
+        }
+    }
+
+    /// Пересчитывает освещенность после выгрузки чанка: верхний слой чанка снизу снова освещается открытым небом.
+    /// Свет пришедший из выгруженного чанка в остальных соседей остается
+    pub fn on_chunk_unloaded(&mut self, chunk_pos: ChunkPos) {
+        self.storage.chunks.remove(&chunk_pos);
+        self.update_sky_below(chunk_pos);
+    }
+
+    /// Пересчитывает свет неба в открытых колоннах верхнего слоя чанка под переданным
+    fn update_sky_below(&mut self, chunk_pos: ChunkPos) {
+        let below: ChunkPos = (*chunk_pos - IVec3::Z).into();
+        let Some(((_, light), _)) = self.storage.get_chunk(below.get_absolute_coord()) else {
+            return;
+        };
+        let sky_exposure = light.read().unwrap().sky_exposure().clone();
+        let top = below.get_absolute_coord() + IVec3::Z * (CHUNK_SIZE as i32 - 1);
+        let size = CHUNK_SIZE as u32;
+        let positions: Vec<IVec3> = (0..size)
+            .flat_map(|x| (0..size).map(move |y| (x, y)))
+            .filter(|&(x, y)| sky_exposure.get(x, y))
+            .map(|(x, y)| top + IVec3::new(x as i32, y as i32, 0))
+            .collect();
+        update_blocks_light(self.registry, &mut self.storage, LightKind::Sky, &positions);
+    }
+
+    /// Пересчитывает освещенность после изменения блока по переданным абсолютным координатам
+    pub fn on_block_changed(&mut self, pos: IVec3) {
+        for kind in [LightKind::Sky, LightKind::Block] {
+            update_blocks_light(self.registry, &mut self.storage, kind, &[pos]);
+        }
+    }
+
+    /// Возвращает чанки освещенность которых или освещенность на границе с которыми изменилась
+    pub fn into_changed_chunks(self) -> HashSet<ChunkPos> {
+        self.storage.changed
+    }
+}
+
+/// Собственный свет блока: свет открытого неба и излучение блока
+fn source_light(
+    registry: &BlockRegistry,
+    storage: &mut impl LightStorage,
+    kind: LightKind,
+    pos: IVec3,
+    block: &Option<Block>,
+) -> u8 {
+    match kind {
+        LightKind::Sky => {
+            if !registry.is_opaque(block) && storage.is_open_to_sky(pos) { MAX_LIGHT } else { 0 }
+        }
+        LightKind::Block => {
+            block.and_then(|block| registry.get(block.id())).map(|definition| definition.light_emission).unwrap_or(0)
+        }
+    }
+}
+
+/// Уровень света пришедшего в соседний блок в направлении `dir`. Полный свет неба распространяется вниз без потерь
+fn propagated_light(kind: LightKind, light: u8, dir: IVec3) -> u8 {
+    if kind == LightKind::Sky && light == MAX_LIGHT && dir == IVec3::NEG_Z {
+        MAX_LIGHT
+    } else {
+        light.saturating_sub(1)
+    }
+}
+
+/// Распространяет свет от блоков из очереди, освещенность соседей только увеличивается
+fn propagate_increase(
+    registry: &BlockRegistry,
+    storage: &mut impl LightStorage,
+    kind: LightKind,
+    mut queue: VecDeque<IVec3>,
+) {
+    while let Some(pos) = queue.pop_front() {
+        let light = match storage.get_light(kind, pos) {
+            Some(light) if light > 1 => { light }
+            _ => { continue; }
+        };
+        for dir in DIRECTIONS {
+            let next = pos + dir;
+            let next_light = propagated_light(kind, light, dir);
+            match storage.get_block(next) {
+                Some(block) if !registry.is_opaque(&block) => {}
+                _ => { continue; }
+            }
+            if storage.get_light(kind, next).is_some_and(|current| current < next_light) {
+                storage.set_light(kind, next, next_light);
+                queue.push_back(next);
+            }
+        }
+    }
+}
+
+/// Гасит свет который мог прийти от блоков из очереди. Соседи которые освещены из другого источника
+/// добавляются в `increase` и после распространения заново освещают погашенные блоки
+fn propagate_removal(
+    registry: &BlockRegistry,
+    storage: &mut impl LightStorage,
+    kind: LightKind,
+    mut removal: VecDeque<(IVec3, u8)>,
+    increase: &mut VecDeque<IVec3>,
+) {
+    while let Some((pos, light)) = removal.pop_front() {
+        for dir in DIRECTIONS {
+            let next = pos + dir;
+            let next_light = match storage.get_light(kind, next) {
+                Some(next_light) if next_light > 0 => { next_light }
+                _ => { continue; }
+            };
+            if next_light < light || next_light == propagated_light(kind, light, dir) {
+                storage.set_light(kind, next, 0);
+                removal.push_back((next, next_light));
+                let block = storage.get_block(next).unwrap_or_default();
+                let source = source_light(registry, storage, kind, next, &block);
+                if source > 0 {
+                    storage.set_light(kind, next, source);
+                    increase.push_back(next);
+                }
+            } else {
+                increase.push_back(next);
+            }
+        }
+    }
+}
+
+/// Пересчитывает освещенность блоков и всех блоков на которые они влияют
+fn update_blocks_light(
+    registry: &BlockRegistry,
+    storage: &mut impl LightStorage,
+    kind: LightKind,
+    positions: &[IVec3],
+) {
+    // Сначала гасим свет всех блоков сразу, чтобы они не освещали друг друга старым светом
+    let mut removal = VecDeque::new();
+    for &pos in positions {
+        let old_light = storage.get_light(kind, pos).unwrap_or(0);
+        if old_light > 0 {
+            storage.set_light(kind, pos, 0);
+            removal.push_back((pos, old_light));
+        }
+    }
+    let mut increase = VecDeque::new();
+    propagate_removal(registry, storage, kind, removal, &mut increase);
+
+    for &pos in positions {
+        let Some(block) = storage.get_block(pos) else {
+            continue;
+        };
+        let mut light = source_light(registry, storage, kind, pos, &block);
+        if !registry.is_opaque(&block) {
+            for dir in DIRECTIONS {
+                if let Some(neighbor_light) = storage.get_light(kind, pos - dir) {
+                    light = light.max(propagated_light(kind, neighbor_light, dir));
+                }
+            }
+        }
+        if light > 0 {
+            storage.set_light(kind, pos, light);
+            increase.push_back(pos);
+        }
+    }
+    propagate_increase(registry, storage, kind, increase);
+}
+
+/// Один чанк еще не добавленный в мир, блоки за его границами считаются не загруженными
+struct SingleChunkStorage<'a> {
+    chunk: &'a Chunk,
+    light: &'a mut ChunkLight,
+    chunk_pos: ChunkPos,
+}
+
+impl<'a> SingleChunkStorage<'a> {
+    fn to_local(&self, pos: IVec3) -> Option<ChunkBlockPos> {
+        (pos - self.chunk_pos.get_absolute_coord()).try_into().ok()
+    }
+}
+
+impl<'a> LightStorage for SingleChunkStorage<'a> {
+    fn get_block(&mut self, pos: IVec3) -> Option<Option<Block>> {
+        self.to_local(pos).map(|local| self.chunk[&local])
+    }
+
+    fn get_light(&mut self, kind: LightKind, pos: IVec3) -> Option<u8> {
+        self.to_local(pos).map(|local| self.light.get(kind, &local))
+    }
+
+    fn set_light(&mut self, kind: LightKind, pos: IVec3, light: u8) {
+        if let Some(local) = self.to_local(pos) {
+            self.light.set(kind, &local, light);
+        }
+    }
+
+    fn is_open_to_sky(&mut self, pos: IVec3) -> bool {
+        self.to_local(pos).is_some_and(|local| {
+            local.z as usize == CHUNK_SIZE - 1 && self.light.sky_exposure().get(local.x, local.y)
+        })
+    }
+}
+
+type LoadedChunk = (Arc<RwLock<Chunk>>, Arc<RwLock<ChunkLight>>);
+
+/// Загруженные чанки мира, ссылки на чанки кешируются на время обновления освещенности
+struct WorldStorage<'a> {
+    chunk_map: &'a ChunkMap,
+    light_map: &'a LightMap,
+    chunks: HashMap<ChunkPos, Option<LoadedChunk>>,
+    changed: HashSet<ChunkPos>,
+}
+
+impl<'a> WorldStorage<'a> {
+    fn get_chunk(&mut self, pos: IVec3) -> Option<(&LoadedChunk, ChunkBlockPos)> {
+        let chunk_pos = ChunkPos::from_global_coord(pos);
+        let local = (pos - chunk_pos.get_absolute_coord()).try_into().unwrap();
+        let chunk = self.chunks.entry(chunk_pos).or_insert_with(|| {
+            let chunk = self.chunk_map.get(&chunk_pos)?;
+            let light = self.light_map.get(&chunk_pos)?;
+            Some((chunk, light))
+        });
+        chunk.as_ref().map(|chunk| (chunk, local))
+    }
+}
+
+impl<'a> LightStorage for WorldStorage<'a> {
+    fn get_block(&mut self, pos: IVec3) -> Option<Option<Block>> {
+        let ((chunk, _), local) = self.get_chunk(pos)?;
+        let block = chunk.read().unwrap()[&local];
+        Some(block)
+    }
+
+    fn get_light(&mut self, kind: LightKind, pos: IVec3) -> Option<u8> {
+        let ((_, light), local) = self.get_chunk(pos)?;
+        let light = light.read().unwrap().get(kind, &local);
+        Some(light)
+    }
+
+    fn set_light(&mut self, kind: LightKind, pos: IVec3, light: u8) {
+        let Some(((_, chunk_light), local)) = self.get_chunk(pos) else {
+            return;
+        };
+        chunk_light.write().unwrap().set(kind, &local, light);
+
+        // Меши соседних чанков используют освещенность граничных блоков
+        let max = CHUNK_SIZE as u32 - 1;
+        let offsets = local.to_array().map(|value| match value {
+            0 => { [0, -1] }
+            value if value == max => { [0, 1] }
+            _ => { [0, 0] }
+        });
+        let chunk_pos = ChunkPos::from_global_coord(pos);
+        for x in offsets[0] {
+            for y in offsets[1] {
+                for z in offsets[2] {
+                    self.changed.insert((*chunk_pos + IVec3::new(x, y, z)).into());
+                }
+            }
+        }
+    }
+
+    fn is_open_to_sky(&mut self, pos: IVec3) -> bool {
+        let Some(((_, light), local)) = self.get_chunk(pos) else {
+            return false;
+        };
+        if local.z as usize != CHUNK_SIZE - 1 || !light.read().unwrap().sky_exposure().get(local.x, local.y) {
+            return false;
+        }
+        self.get_chunk(pos + IVec3::Z).is_none()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{ivec3, IVec3, uvec3};
+    use chunk::ChunkPos;
+    use crate::logic::block::{Block, BlockDefinition, BlockId, BlockRegistry};
+    use crate::logic::chunk::Chunk;
+    use crate::logic::light::{compute_chunk_light, LightKind, LightUpdater, SkyExposure};
+    use crate::logic::world::World;
+
+    fn stone() -> Block {
+        Block::new(BlockId(1))
+    }
+
+    fn torch() -> Block {
+        Block::new(BlockId(2))
+    }
+
+    fn registry() -> BlockRegistry {
+        let definitions = [
+            BlockDefinition { id: 1, name: "stone".to_string(), ..Default::default() },
+            BlockDefinition {
+                id: 2,
+                name: "torch".to_string(),
+                transparent: true,
+                light_emission: 14,
+                ..Default::default()
+            },
+        ];
+        BlockRegistry::new(definitions).unwrap()
+    }
+
+    /// Открытое небо над всеми колоннами чанка
+    fn open_sky() -> SkyExposure {
+        SkyExposure::from_fn(|_, _| true)
+    }
+
+    /// Чанк над которым открытое небо
+    fn sky_chunk_pos() -> ChunkPos {
+        ivec3(0, 0, 4).into()
+    }
+
+    /// Чанк со слоем камня на высоте 8
+    fn covered_chunk() -> Chunk {
+        let mut chunk = Chunk::new(());
+        for x in 0..16 {
+            for y in 0..16 {
+                chunk.set(&uvec3(x, y, 8).try_into().unwrap(), Some(stone()));
+            }
+        }
+        chunk
+    }
+
+    fn add_chunk(world: &World, registry: &BlockRegistry, pos: ChunkPos, chunk: Chunk, sky_exposure: SkyExposure) {
+        let light = compute_chunk_light(registry, &chunk, pos, sky_exposure);
+        world.add_chunk(pos, chunk, light);
+        LightUpdater::new(registry, world).on_chunk_loaded(pos);
+    }
+
+    fn light(world: &World, kind: LightKind, pos: IVec3) -> u8 {
+        let chunk_pos = ChunkPos::from_global_coord(pos);
+        let light = world.light_map.get(&chunk_pos).unwrap();
+        let light = light.read().unwrap().get(kind, &(pos - chunk_pos.get_absolute_coord()).try_into().unwrap());
+        light
+    }
+
+    fn set_block(world: &World, registry: &BlockRegistry, pos: IVec3, block: Option<Block>) {
+        assert!(world.set_block(pos.into(), block));
+        LightUpdater::new(registry, world).on_block_changed(pos);
+    }
+
+    #[test]
+    fn sky_light_does_not_reach_caves() {
+        let registry = registry();
+        let light = compute_chunk_light(&registry, &covered_chunk(), sky_chunk_pos(), open_sky());
+        let get = |z: u32| light.get(LightKind::Sky, &uvec3(5, 5, z).try_into().unwrap());
+        assert_eq!(get(15), 15);
+        assert_eq!(get(9), 15);
+        assert_eq!(get(8), 0);
+        assert_eq!(get(3), 0);
+    }
+
+    #[test]
+    fn block_light_decays_with_distance() {
+        let registry = registry();
+        let mut chunk = Chunk::new(());
+        chunk.set(&uvec3(8, 8, 8).try_into().unwrap(), Some(torch()));
+        let light = compute_chunk_light(&registry, &chunk, ivec3(0, 0, 0).into(), SkyExposure::default());
+        let get = |x: u32, y: u32| light.get(LightKind::Block, &uvec3(x, y, 8).try_into().unwrap());
+        assert_eq!(get(8, 8), 14);
+        assert_eq!(get(9, 8), 13);
+        assert_eq!(get(12, 10), 8);
+        assert_eq!(light.get(LightKind::Sky, &uvec3(8, 8, 8).try_into().unwrap()), 0);
+    }
+
+    #[test]
+    fn light_propagates_across_chunk_borders() {
+        let registry = registry();
+        let world = World::default();
+        let mut chunk = Chunk::new(());
+        chunk.set(&uvec3(14, 8, 8).try_into().unwrap(), Some(torch()));
+        add_chunk(&world, &registry, ivec3(0, 0, 0).into(), chunk, SkyExposure::default());
+        add_chunk(&world, &registry, ivec3(1, 0, 0).into(), Chunk::new(()), SkyExposure::default());
+
+        assert_eq!(light(&world, LightKind::Block, ivec3(16, 8, 8)), 12);
+        assert_eq!(light(&world, LightKind::Block, ivec3(20, 8, 8)), 8);
+
+        // Свет неба проходит вниз через пустой чанк под открытым небом
+        add_chunk(&world, &registry, sky_chunk_pos(), Chunk::new(()), open_sky());
+        let below = *sky_chunk_pos() - IVec3::Z;
+        add_chunk(&world, &registry, below.into(), Chunk::new(()), SkyExposure::default());
+        let bottom = below * 16 + ivec3(3, 3, 0);
+        assert_eq!(light(&world, LightKind::Sky, bottom), 15);
+    }
+
+    #[test]
+    fn block_changes_update_light() {
+        let registry = registry();
+        let world = World::default();
+        let mut chunk = covered_chunk();
+        chunk.set(&uvec3(5, 5, 8).try_into().unwrap(), None);
+        add_chunk(&world, &registry, sky_chunk_pos(), chunk, open_sky());
+        let origin = sky_chunk_pos().get_absolute_coord();
+
+        // Через отверстие в потолке свет неба проходит до дна без потерь и рассеивается в стороны
+        assert_eq!(light(&world, LightKind::Sky, origin + ivec3(5, 5, 0)), 15);
+        assert_eq!(light(&world, LightKind::Sky, origin + ivec3(7, 5, 0)), 13);
+
+        set_block(&world, &registry, origin + ivec3(5, 5, 8), Some(stone()));
+        assert_eq!(light(&world, LightKind::Sky, origin + ivec3(5, 5, 0)), 0);
+        assert_eq!(light(&world, LightKind::Sky, origin + ivec3(7, 5, 0)), 0);
+        assert_eq!(light(&world, LightKind::Sky, origin + ivec3(5, 5, 9)), 15);
+
+        set_block(&world, &registry, origin + ivec3(5, 5, 8), None);
+        assert_eq!(light(&world, LightKind::Sky, origin + ivec3(7, 5, 0)), 13);
+
+        set_block(&world, &registry, origin + ivec3(10, 10, 3), Some(torch()));
+        assert_eq!(light(&world, LightKind::Block, origin + ivec3(10, 12, 3)), 12);
+        set_block(&world, &registry, origin + ivec3(10, 10, 3), None);
+        assert_eq!(light(&world, LightKind::Block, origin + ivec3(10, 12, 3)), 0);
+    }
+
+    #[test]
+    fn sky_light_depends_on_loaded_chunk_above() {
+        let registry = registry();
+        let world = World::default();
+        let below = sky_chunk_pos();
+        let above: ChunkPos = (*below + IVec3::Z).into();
+        let bottom = below.get_absolute_coord() + ivec3(3, 3, 0);
+        add_chunk(&world, &registry, below, Chunk::new(()), open_sky());
+        assert_eq!(light(&world, LightKind::Sky, bottom), 15);
+
+        // Загруженный сверху чанк закрывает небо слоем камня
+        add_chunk(&world, &registry, above, covered_chunk(), open_sky());
+        assert_eq!(light(&world, LightKind::Sky, bottom), 0);
+        assert_eq!(light(&world, LightKind::Sky, above.get_absolute_coord() + ivec3(3, 3, 9)), 15);
+
+        // После выгрузки чанка сверху верхний слой снова освещается небом
+        world.remove_chunk(&above);
+        LightUpdater::new(&registry, &world).on_chunk_unloaded(above);
+        assert_eq!(light(&world, LightKind::Sky, bottom), 15);
+    }
+}
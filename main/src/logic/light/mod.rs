@@ -0,0 +1,5 @@
+mod chunk_light;
+mod light_engine;
+
+pub use chunk_light::{ChunkLight, LightKind, LightMap, MAX_LIGHT, SkyExposure};
+pub use light_engine::{compute_chunk_light, LightUpdater};
@@ -1,3 +1,6 @@
 pub mod chunk;
 pub mod block;
 pub mod world;
+pub mod light;
+pub mod frame_budget;
+pub mod collision;
@@ -0,0 +1,118 @@
+use std::time::Duration;
+use bevy::utils::HashMap;
+use chunk::ChunkPos;
+
+/// Уровень загрузки чанка, каждый следующий уровень включает предыдущие.
+///
+/// Чанки вокруг [world_anchor::WorldAnchor] имеют уровень [TicketKind::Render]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum TicketKind {
+    /// Чанк загружен в память
+    Load,
+
+    /// Чанк загружен и обновляется, см [crate::logic::world::World::ticking_chunks]
+    Tick,
+
+    /// Чанк загружен, обновляется и отображается
+    Render,
+}
+
+/// Дескриптор тикета загрузки, см [crate::logic::world::World::add_ticket]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TicketId(u64);
+
+/// Область чанков которая должна быть загружена без [world_anchor::WorldAnchor]
+#[derive(Clone, Copy, Debug)]
+pub struct ChunkTicket {
+    pub pos: ChunkPos,
+
+    /// Радиус области, по вертикали используется тот же радиус
+    pub radius: u32,
+
+    pub kind: TicketKind,
+
+    /// Время действия тикета, [None] - тикет действует пока его не удалят
+    pub duration: Option<Duration>,
+}
+
+/// Тикеты загрузки чанков мира
+#[derive(Default)]
+pub struct ChunkTickets {
+    tickets: HashMap<TicketId, ChunkTicket>,
+    next_id: u64,
+
+    /// Время [bevy::time::Time::elapsed] после которого тикет удаляется, отсчитывается от первого вызова
+    /// [Self::take_changed] после добавления тикета
+    expires_at: HashMap<TicketId, Duration>,
+
+    /// Тикеты добавлялись или удалялись с последнего вызова [Self::take_changed]
+    changed: bool,
+}
+
+impl ChunkTickets {
+    pub fn add(&mut self, pos: ChunkPos, radius: u32, kind: TicketKind, duration: Option<Duration>) -> TicketId {
+        let id = TicketId(self.next_id);
+        self.next_id += 1;
+        self.tickets.insert(id, ChunkTicket { pos, radius, kind, duration });
+        self.changed = true;
+        id
+    }
+
+    pub fn remove(&mut self, id: TicketId) -> bool {
+        let removed = self.tickets.remove(&id).is_some();
+        self.changed |= removed;
+        removed
+    }
+
+    /// Удаляет истекшие к `now` тикеты и возвращает изменился ли набор тикетов с прошлого вызова.
+    /// `now` - время [bevy::time::Time::elapsed]
+    pub fn take_changed(&mut self, now: Duration) -> bool {
+        for (id, ticket) in self.tickets.iter() {
+            if let Some(duration) = ticket.duration {
+                self.expires_at.entry(*id).or_insert(now + duration);
+            }
+        }
+        let count = self.tickets.len();
+        let expires_at = &mut self.expires_at;
+        self.tickets.retain(|id, _| match expires_at.get(id) {
+            Some(expires_at) => { *expires_at > now }
+            None => { true }
+        });
+        expires_at.retain(|id, _| self.tickets.contains_key(id));
+        let changed = self.changed || self.tickets.len() != count;
+        self.changed = false;
+        changed
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item=&ChunkTicket> {
+        self.tickets.values()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bevy::math::ivec3;
+    use crate::logic::world::chunk_tickets::{ChunkTickets, TicketKind};
+
+    #[test]
+    fn tickets_expire() {
+        let mut tickets = ChunkTickets::default();
+        assert!(!tickets.take_changed(Duration::ZERO));
+
+        let permanent = tickets.add(ivec3(0, 0, 0).into(), 2, TicketKind::Tick, None);
+        tickets.add(ivec3(5, 0, 0).into(), 1, TicketKind::Load, Some(Duration::from_secs(10)));
+        // Время действия отсчитывается от первого вызова после добавления тикета
+        assert!(tickets.take_changed(Duration::from_secs(5)));
+        assert!(!tickets.take_changed(Duration::from_secs(14)));
+        assert_eq!(tickets.iter().count(), 2);
+
+        assert!(tickets.take_changed(Duration::from_secs(15)));
+        assert_eq!(tickets.iter().map(|ticket| ticket.kind).collect::<Vec<_>>(), vec![TicketKind::Tick]);
+
+        assert!(tickets.remove(permanent));
+        assert!(!tickets.remove(permanent));
+        assert!(tickets.take_changed(Duration::from_secs(15)));
+        assert_eq!(tickets.iter().count(), 0);
+    }
+}
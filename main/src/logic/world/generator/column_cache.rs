@@ -0,0 +1,70 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use bevy::math::IVec2;
+use bevy::utils::HashMap;
+
+/// Кеш данных колонн чанков, общих для всех чанков колонны по вертикали, например карт высот.
+///
+/// Чанки генерируются параллельно, поэтому данные колонны могут быть рассчитаны несколькими потоками одновременно,
+/// в кеше остается первый результат. При превышении емкости вытесняются самые старые колонны
+pub struct ColumnCache<T> {
+    capacity: usize,
+    columns: Mutex<CachedColumns<T>>,
+}
+
+struct CachedColumns<T> {
+    values: HashMap<IVec2, Arc<T>>,
+
+    /// Колонны в порядке добавления в кеш
+    order: VecDeque<IVec2>,
+}
+
+impl<T> ColumnCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            columns: Mutex::new(CachedColumns { values: HashMap::new(), order: VecDeque::new() }),
+        }
+    }
+
+    /// Возвращает данные колонны чанков, если их нет в кеше рассчитывает их вне блокировки кеша
+    pub fn get_or_insert_with(&self, column: IVec2, compute: impl FnOnce() -> T) -> Arc<T> {
+        if let Some(value) = self.columns.lock().unwrap().values.get(&column) {
+            return Arc::clone(value);
+        }
+        let value = Arc::new(compute());
+
+        let mut columns = self.columns.lock().unwrap();
+        if let Some(value) = columns.values.get(&column) {
+            return Arc::clone(value);
+        }
+        while columns.order.len() >= self.capacity {
+            let Some(oldest) = columns.order.pop_front() else {
+                break;
+            };
+            columns.values.remove(&oldest);
+        }
+        columns.values.insert(column, Arc::clone(&value));
+        columns.order.push_back(column);
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use bevy::math::ivec2;
+    use crate::logic::world::generator::ColumnCache;
+
+    #[test]
+    fn oldest_columns_are_evicted() {
+        let cache = ColumnCache::new(2);
+        let first = cache.get_or_insert_with(ivec2(0, 0), || 1);
+        assert!(Arc::ptr_eq(&first, &cache.get_or_insert_with(ivec2(0, 0), || 2)));
+
+        cache.get_or_insert_with(ivec2(1, 0), || 3);
+        cache.get_or_insert_with(ivec2(2, 0), || 4);
+        assert_eq!(*cache.get_or_insert_with(ivec2(0, 0), || 5), 5);
+        assert_eq!(*cache.get_or_insert_with(ivec2(2, 0), || 6), 4);
+    }
+}
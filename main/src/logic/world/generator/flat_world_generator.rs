@@ -0,0 +1,87 @@
+use bevy::math::uvec3;
+use chunk::{CHUNK_SIZE, ChunkPos};
+use crate::logic::block::{Block, BlockRegistry, BlockRegistryError};
+use crate::logic::chunk::Chunk;
+use crate::logic::world::generator::WorldGenerator;
+use crate::logic::world::WorldSeed;
+
+/// Генератор плоского мира из горизонтальных слоев блоков
+pub struct FlatWorldGenerator {
+    /// Слои снизу вверх начиная с z = 0, для каждого слоя указано имя блока и толщина в блоках
+    pub layers: Vec<(String, u32)>,
+
+    /// Блоки слоев, заполняются в [WorldGenerator::set_block_registry]
+    blocks: Vec<(Block, u32)>,
+}
+
+impl FlatWorldGenerator {
+    /// Плоский мир из одного слоя травы заданной толщины
+    pub fn flat(height: u32) -> Self {
+        Self::new(vec![("grass".to_string(), height)])
+    }
+
+    /// Классический суперплоский мир: слой бедрока и три слоя травы
+    pub fn superflat() -> Self {
+        Self::new(vec![
+            ("bedrock".to_string(), 1),
+            ("grass".to_string(), 3),
+        ])
+    }
+
+    pub fn new(layers: Vec<(String, u32)>) -> Self {
+        Self { layers, blocks: Vec::new() }
+    }
+
+    /// Возвращает блок на переданной абсолютной высоте
+    fn get_block(&self, z: i32) -> Option<Block> {
+        if z < 0 {
+            return None;
+        }
+        let mut layer_start = 0;
+        for (block, thickness) in self.blocks.iter() {
+            layer_start += *thickness as i32;
+            if z < layer_start {
+                return Some(*block);
+            }
+        }
+        None
+    }
+}
+
+impl Default for FlatWorldGenerator {
+    fn default() -> Self {
+        Self::superflat()
+    }
+}
+
+impl WorldGenerator for FlatWorldGenerator {
+    fn set_seed(&mut self, _seed: &WorldSeed) {}
+
+    fn set_block_registry(&mut self, registry: &BlockRegistry) -> Result<(), BlockRegistryError> {
+        self.blocks = self.layers.iter()
+            .map(|(name, thickness)| Ok((registry.block(name)?, *thickness)))
+            .collect::<Result<_, _>>()?;
+        Ok(())
+    }
+
+    fn generate_chunk(&self, pos: ChunkPos) -> Chunk {
+        let mut chunk = Chunk::new(());
+        for z in 0..CHUNK_SIZE {
+            let block = self.get_block(pos.z * CHUNK_SIZE as i32 + z as i32);
+            if block.is_none() {
+                continue;
+            }
+            for x in 0..CHUNK_SIZE {
+                for y in 0..CHUNK_SIZE {
+                    let pos = uvec3(x as u32, y as u32, z as u32).try_into().unwrap();
+                    chunk.set(&pos, block);
+                }
+            }
+        }
+        chunk
+    }
+
+    fn surface_height(&self, _x: i32, _y: i32) -> i32 {
+        self.layers.iter().map(|(_, thickness)| *thickness as i32).sum()
+    }
+}
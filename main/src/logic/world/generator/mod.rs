@@ -0,0 +1,11 @@
+mod world_generator;
+mod noise_world_generator;
+mod flat_world_generator;
+mod void_world_generator;
+mod column_cache;
+
+pub use world_generator::{WorldGenerator, SharedWorldGenerator, world_generator_by_name};
+pub use noise_world_generator::NoiseWorldGenerator;
+pub use flat_world_generator::FlatWorldGenerator;
+pub use void_world_generator::VoidWorldGenerator;
+pub use column_cache::ColumnCache;
@@ -0,0 +1,261 @@
+use std::cmp::min;
+use std::sync::Arc;
+use bevy::math::{ivec2, IVec2, uvec3};
+use noise::{MultiFractal, NoiseFn};
+use chunk::{CHUNK_SIZE, ChunkPos};
+use crate::logic::block::{Block, BlockRegistry, BlockRegistryError};
+use crate::logic::chunk::Chunk;
+use crate::logic::world::generator::{ColumnCache, WorldGenerator};
+use crate::logic::world::{GenerationStage, WorldSeed};
+
+type Noise = noise::Fbm<noise::SuperSimplex>;
+
+/// Сколько колонн чанков хранит кеш карт высот
+const HEIGHTMAP_CACHE_CAPACITY: usize = 4096;
+
+/// Высоты поверхности колонны чанков, индекс `x * CHUNK_SIZE + y`
+type Heightmap = Vec<i32>;
+
+/// Генератор холмистого ландшафта на основе FBM шума
+pub struct NoiseWorldGenerator {
+    /// Количество октав шума
+    pub octaves: usize,
+
+    /// Частота первой октавы шума
+    pub frequency: f64,
+
+    /// Множитель частоты каждой следующей октавы
+    pub lacunarity: f64,
+
+    /// Множитель амплитуды каждой следующей октавы
+    pub persistence: f64,
+
+    /// Множитель абсолютных координат блока перед выборкой шума
+    pub scale: f64,
+
+    /// Минимальная высота поверхности в блоках
+    pub base_height: f64,
+
+    /// Разница между максимальной и минимальной высотой поверхности в блоках
+    pub height_amplitude: f64,
+
+    /// Имя блока из которого состоит ландшафт
+    pub block: String,
+
+    /// Множитель абсолютных координат блока перед выборкой шума пещер
+    pub caves_scale: f64,
+
+    /// Блоки для которых значение шума пещер больше этого порога вырезаются, значение больше 1 отключает пещеры.
+    /// По умолчанию пещеры отключены
+    pub caves_threshold: f64,
+
+    noise: Noise,
+    caves_noise: noise::SuperSimplex,
+
+    /// Карты высот общие для всех чанков колонны
+    heightmaps: ColumnCache<Heightmap>,
+
+    /// Блок ландшафта, заполняется в [WorldGenerator::set_block_registry]
+    terrain_block: Option<Block>,
+}
+
+impl Default for NoiseWorldGenerator {
+    fn default() -> Self {
+        let mut generator = Self {
+            octaves: Noise::DEFAULT_OCTAVE_COUNT,
+            frequency: 0.05,
+            lacunarity: Noise::DEFAULT_LACUNARITY,
+            persistence: 0.25,
+            scale: 0.14,
+            base_height: 25.6,
+            height_amplitude: 64.,
+            block: "grass".to_string(),
+            caves_scale: 0.06,
+            caves_threshold: 2.,
+            noise: Noise::default(),
+            caves_noise: noise::SuperSimplex::default(),
+            heightmaps: ColumnCache::new(HEIGHTMAP_CACHE_CAPACITY),
+            terrain_block: None,
+        };
+        generator.set_seed(&WorldSeed::default());
+        generator
+    }
+}
+
+impl WorldGenerator for NoiseWorldGenerator {
+    fn set_seed(&mut self, seed: &WorldSeed) {
+        // Параметры выставляются напрямую, без пересчета нормализации амплитуды шума
+        let mut noise = Noise::new(seed.derive_u32(GenerationStage::Height)).set_octaves(self.octaves);
+        noise.frequency = self.frequency;
+        noise.lacunarity = self.lacunarity;
+        noise.persistence = self.persistence;
+        self.noise = noise;
+
+        self.caves_noise = noise::SuperSimplex::new(seed.derive_u32(GenerationStage::Caves));
+        self.heightmaps = ColumnCache::new(HEIGHTMAP_CACHE_CAPACITY);
+    }
+
+    fn set_block_registry(&mut self, registry: &BlockRegistry) -> Result<(), BlockRegistryError> {
+        self.terrain_block = Some(registry.block(&self.block)?);
+        Ok(())
+    }
+
+    fn generate_chunk(&self, pos: ChunkPos) -> Chunk {
+        let block = self.terrain_block.expect("Block registry is not set");
+        let mut chunk = Chunk::new(());
+        let chunk_coord = pos.get_absolute_coord();
+        let heightmap = self.heightmap(pos.truncate());
+
+        for x in 0..CHUNK_SIZE {
+            for y in 0..CHUNK_SIZE {
+                let h = heightmap[x * CHUNK_SIZE + y] - chunk_coord.z;
+                if h <= 0 { continue; }
+
+                for z in 0..min(h as usize, CHUNK_SIZE) {
+                    if self.is_cave(chunk_coord.x + x as i32, chunk_coord.y + y as i32, chunk_coord.z + z as i32) {
+                        continue;
+                    }
+                    let pos = uvec3(x as u32, y as u32, z as u32).try_into().unwrap();
+                    chunk.set(&pos, Some(block));
+                }
+            }
+        }
+        chunk
+    }
+
+    fn surface_height(&self, x: i32, y: i32) -> i32 {
+        let size = CHUNK_SIZE as i32;
+        let heightmap = self.heightmap(ivec2(x.div_euclid(size), y.div_euclid(size)));
+        heightmap[x.rem_euclid(size) as usize * CHUNK_SIZE + y.rem_euclid(size) as usize]
+    }
+}
+
+impl NoiseWorldGenerator {
+    /// Возвращает карту высот колонны чанков по ее координатам x и y
+    fn heightmap(&self, column: IVec2) -> Arc<Heightmap> {
+        self.heightmaps.get_or_insert_with(column, || {
+            let origin = column * CHUNK_SIZE as i32;
+            (0..CHUNK_SIZE as i32)
+                .flat_map(|x| (0..CHUNK_SIZE as i32).map(move |y| origin + ivec2(x, y)))
+                .map(|pos| {
+                    let h = self.noise.get([pos.x as f64 * self.scale, pos.y as f64 * self.scale]);
+                    let h = (h + 1.0) / 2.0;
+                    (self.base_height + self.height_amplitude * h) as i32
+                })
+                .collect()
+        })
+    }
+
+    /// Возвращает нужно ли вырезать блок по переданным абсолютным координатам как часть пещеры
+    fn is_cave(&self, x: i32, y: i32, z: i32) -> bool {
+        if self.caves_threshold > 1. {
+            return false;
+        }
+        let value = self.caves_noise.get([
+            x as f64 * self.caves_scale,
+            y as f64 * self.caves_scale,
+            z as f64 * self.caves_scale,
+        ]);
+        value > self.caves_threshold
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use bevy::math::ivec3;
+    use chunk::{BinaryCodec, ChunkPos};
+    use crate::logic::block::{BlockDefinition, BlockRegistry};
+    use crate::logic::world::generator::{NoiseWorldGenerator, WorldGenerator};
+    use crate::logic::world::WorldSeed;
+
+    fn generator(seed: u64) -> NoiseWorldGenerator {
+        let mut generator = NoiseWorldGenerator::default();
+        generator.set_seed(&WorldSeed(seed));
+        let grass = BlockDefinition { id: 2, name: "grass".to_string(), ..Default::default() };
+        generator.set_block_registry(&BlockRegistry::new([grass]).unwrap()).unwrap();
+        generator
+    }
+
+    fn chunk_bytes(generator: &NoiseWorldGenerator, pos: ChunkPos) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        generator.generate_chunk(pos).encode(&mut bytes).unwrap();
+        bytes
+    }
+
+    fn positions() -> Vec<ChunkPos> {
+        (-2..2).flat_map(|x| (-2..2).flat_map(move |y| (0..5).map(move |z| ivec3(x, y, z).into()))).collect()
+    }
+
+    #[test]
+    fn same_seed_same_chunks_in_any_order() {
+        let first = generator(12345);
+        let forward: Vec<_> = positions().into_iter().map(|pos| chunk_bytes(&first, pos)).collect();
+
+        // Отдельный генератор с тем же seed генерирует чанки в обратном порядке
+        let second = generator(12345);
+        let mut backward: Vec<_> = positions().into_iter().rev().map(|pos| chunk_bytes(&second, pos)).collect();
+        backward.reverse();
+
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    fn same_seed_same_chunks_from_threads() {
+        let generator = Arc::new(generator(777));
+        let expected: Vec<_> = positions().into_iter().map(|pos| chunk_bytes(&generator, pos)).collect();
+
+        let handles: Vec<_> = (0..4)
+            .map(|thread| {
+                let generator = Arc::clone(&generator);
+                std::thread::spawn(move || {
+                    let mut positions = positions();
+                    positions.rotate_left(thread * 7);
+                    positions.into_iter().map(|pos| (pos, chunk_bytes(&generator, pos))).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            for (pos, bytes) in handle.join().unwrap() {
+                let index = positions().iter().position(|p| *p == pos).unwrap();
+                assert_eq!(bytes, expected[index]);
+            }
+        }
+    }
+
+    #[test]
+    fn any_height_is_generated() {
+        let deep_pos = ivec3(3, -5, -40).into();
+        assert!(generator(42).generate_chunk(deep_pos).into_iter().all(|(_, block)| block.is_some()));
+
+        let mut generator = generator(42);
+        generator.caves_threshold = 0.55;
+        let deep = generator.generate_chunk(deep_pos);
+        let high = generator.generate_chunk(ivec3(3, -5, 1000).into());
+        assert!(deep.into_iter().any(|(_, block)| block.is_some()));
+        assert!(deep.into_iter().any(|(_, block)| block.is_none()), "Caves are generated at any depth");
+        assert!(high.into_iter().all(|(_, block)| block.is_none()));
+
+        // Высота поверхности из общей карты высот совпадает со сгенерированными чанками колонны
+        for (x, y) in [(48, -80), (63, -65), (50, -70)] {
+            let surface = generator.surface_height(x, y);
+            let below = ivec3(x, y, surface - 1);
+            let chunk = generator.generate_chunk(ChunkPos::from_global_coord(below));
+            let local = (below - ChunkPos::from_global_coord(below).get_absolute_coord()).try_into().unwrap();
+            assert!(chunk[&local].is_some() || generator.is_cave(below.x, below.y, below.z));
+            let above = ivec3(x, y, surface);
+            let chunk = generator.generate_chunk(ChunkPos::from_global_coord(above));
+            let local = (above - ChunkPos::from_global_coord(above).get_absolute_coord()).try_into().unwrap();
+            assert!(chunk[&local].is_none());
+        }
+        assert!(generator.sky_exposure(ivec3(3, -5, 1000).into()).get(0, 0));
+        assert!(!generator.sky_exposure(ivec3(3, -5, -40).into()).get(0, 0));
+    }
+
+    #[test]
+    fn different_seeds_different_chunks() {
+        let pos = ivec3(0, 0, 2).into();
+        assert_ne!(chunk_bytes(&generator(1), pos), chunk_bytes(&generator(2), pos));
+    }
+}
@@ -0,0 +1,21 @@
+use chunk::ChunkPos;
+use crate::logic::block::{BlockRegistry, BlockRegistryError};
+use crate::logic::chunk::Chunk;
+use crate::logic::world::generator::WorldGenerator;
+use crate::logic::world::WorldSeed;
+
+/// Генератор пустого мира, все чанки состоят только из воздуха
+#[derive(Default)]
+pub struct VoidWorldGenerator;
+
+impl WorldGenerator for VoidWorldGenerator {
+    fn set_seed(&mut self, _seed: &WorldSeed) {}
+
+    fn set_block_registry(&mut self, _registry: &BlockRegistry) -> Result<(), BlockRegistryError> {
+        Ok(())
+    }
+
+    fn generate_chunk(&self, _pos: ChunkPos) -> Chunk {
+        Chunk::new(())
+    }
+}
@@ -0,0 +1,111 @@
+use std::sync::Arc;
+use bevy::prelude::{Deref, Resource};
+use chunk::{CHUNK_SIZE, ChunkPos};
+use crate::logic::block::{BlockRegistry, BlockRegistryError};
+use crate::logic::chunk::Chunk;
+use crate::logic::light::SkyExposure;
+use crate::logic::world::generator::{FlatWorldGenerator, NoiseWorldGenerator, VoidWorldGenerator};
+use crate::logic::world::WorldSeed;
+
+/// Генератор чанков мира.
+///
+/// Генерация выполняется в [bevy::tasks::AsyncComputeTaskPool] параллельно для разных чанков, поэтому результат
+/// должен зависеть только от seed и позиции чанка, но не от порядка генерации. Мир не ограничен по высоте, генератор
+/// должен поддерживать любые координаты чанков, в том числе отрицательные
+pub trait WorldGenerator: Send + Sync + 'static {
+    /// Устанавливает seed генератора, вызывается до начала генерации.
+    /// Каждый этап генерации должен получать свой seed через [WorldSeed::derive]
+    fn set_seed(&mut self, seed: &WorldSeed);
+
+    /// Передает генератору реестр блоков, вызывается до начала генерации.
+    /// Генераторы хранят имена блоков и получают по ним идентификаторы из реестра, если какого-то блока нет в реестре
+    /// возвращается ошибка
+    fn set_block_registry(&mut self, registry: &BlockRegistry) -> Result<(), BlockRegistryError>;
+
+    /// Генерирует чанк по переданным координатам
+    fn generate_chunk(&self, pos: ChunkPos) -> Chunk;
+
+    /// Высота поверхности колонны блоков по абсолютным координатам: z над самым верхним генерируемым блоком.
+    /// По умолчанию [i32::MIN], то есть над любым чанком открытое небо
+    fn surface_height(&self, _x: i32, _y: i32) -> i32 {
+        i32::MIN
+    }
+
+    /// Колонны чанка над которыми нет генерируемых блоков, см [SkyExposure]
+    fn sky_exposure(&self, pos: ChunkPos) -> SkyExposure {
+        let origin = pos.get_absolute_coord();
+        let top = origin.z + CHUNK_SIZE as i32;
+        SkyExposure::from_fn(|x, y| self.surface_height(origin.x + x as i32, origin.y + y as i32) <= top)
+    }
+}
+
+/// Любая функция от позиции чанка является генератором не зависящим от seed, удобно для тестов
+impl<F: Fn(ChunkPos) -> Chunk + Send + Sync + 'static> WorldGenerator for F {
+    fn set_seed(&mut self, _seed: &WorldSeed) {}
+
+    fn set_block_registry(&mut self, _registry: &BlockRegistry) -> Result<(), BlockRegistryError> {
+        Ok(())
+    }
+
+    fn generate_chunk(&self, pos: ChunkPos) -> Chunk {
+        self(pos)
+    }
+}
+
+/// Создает генератор по имени: `noise`, `flat`, `superflat` или `void`. Для неизвестного имени возвращает [None]
+pub fn world_generator_by_name(name: &str) -> Option<Box<dyn WorldGenerator>> {
+    match name {
+        "noise" => { Some(Box::new(NoiseWorldGenerator::default())) }
+        "flat" => { Some(Box::new(FlatWorldGenerator::flat(64))) }
+        "superflat" => { Some(Box::new(FlatWorldGenerator::superflat())) }
+        "void" => { Some(Box::new(VoidWorldGenerator)) }
+        _ => { None }
+    }
+}
+
+/// Генератор используемый миром, может свободно клонироваться для передачи в асинхронные задачи
+#[derive(Resource, Clone, Deref)]
+pub struct SharedWorldGenerator(Arc<dyn WorldGenerator>);
+
+impl SharedWorldGenerator {
+    pub fn new(generator: Box<dyn WorldGenerator>) -> Self {
+        Self(Arc::from(generator))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{ivec3, uvec3};
+    use crate::logic::block::{Block, BlockDefinition, BlockRegistry, BlockRegistryError};
+    use crate::logic::world::generator::{FlatWorldGenerator, world_generator_by_name, WorldGenerator};
+
+    fn registry() -> BlockRegistry {
+        BlockRegistry::new([
+            BlockDefinition { id: 1, name: "bedrock".to_string(), ..Default::default() },
+            BlockDefinition { id: 2, name: "grass".to_string(), ..Default::default() },
+        ]).unwrap()
+    }
+
+    /// Возвращает блок в начале чанка на нулевой высоте, сгенерированный генератором с переданным именем
+    fn ground_block(name: &str) -> Option<Block> {
+        let mut generator = world_generator_by_name(name).unwrap();
+        generator.set_block_registry(&registry()).unwrap();
+        generator.generate_chunk(ivec3(0, 0, 0).into())[&uvec3(0, 0, 0).try_into().unwrap()]
+    }
+
+    #[test]
+    fn generators_are_created_by_name() {
+        let registry = registry();
+        assert_eq!(ground_block("flat"), registry.block("grass").ok());
+        assert_eq!(ground_block("superflat"), registry.block("bedrock").ok());
+        assert_eq!(ground_block("void"), None);
+        assert!(world_generator_by_name("noise").is_some());
+        assert!(world_generator_by_name("mountains").is_none());
+    }
+
+    #[test]
+    fn unknown_block_is_reported() {
+        let mut generator = FlatWorldGenerator::new(vec![("sand".to_string(), 1)]);
+        assert!(matches!(generator.set_block_registry(&registry()), Err(BlockRegistryError::UnknownBlock(_))));
+    }
+}
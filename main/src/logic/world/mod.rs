@@ -1,5 +1,17 @@
+pub mod generator;
+
 mod world_plugin;
 mod world;
+mod world_storage;
+mod world_seed;
+mod world_metadata;
+mod unloaded_chunk_cache;
+mod chunk_tickets;
 
 pub use world::World;
-pub use world_plugin::{WorldPlugin, ChunkUpdateEvent};
+pub use world_seed::{GenerationStage, WorldSeed};
+pub use world_metadata::{WorldMetadata, WorldMetadataError};
+pub use chunk_tickets::TicketKind;
+pub use world_storage::WorldStorageSettings;
+pub use world_plugin::{WorldPlugin, WorldCorePlugin, ChunkUpdateEvent, ChunkLoadingSettings, add_loaded_chunks};
+pub use world_plugin::ChunkIntegrationBudget;
@@ -0,0 +1,88 @@
+use std::collections::VecDeque;
+use bevy::prelude::{FromWorld, Resource};
+use bevy::utils::HashMap;
+use chunk::ChunkPos;
+use crate::logic::chunk::Chunk;
+use crate::logic::world::ChunkLoadingSettings;
+
+/// Недавно выгруженные чанки, позволяют вернуть чанк в мир без чтения с диска и генерации.
+///
+/// Хранит не больше заданного количества чанков, при переполнении вытесняются выгруженные раньше всех
+#[derive(Resource)]
+pub struct UnloadedChunkCache {
+    capacity: usize,
+    chunks: HashMap<ChunkPos, Chunk>,
+
+    /// Чанки в порядке выгрузки
+    order: VecDeque<ChunkPos>,
+}
+
+impl UnloadedChunkCache {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, chunks: HashMap::new(), order: VecDeque::new() }
+    }
+
+    /// Добавляет выгруженный чанк, заменяя старую версию если она есть
+    pub fn insert(&mut self, pos: ChunkPos, chunk: Chunk) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.chunks.remove(&pos).is_some() {
+            self.order.retain(|cached| *cached != pos);
+        }
+        while self.order.len() >= self.capacity {
+            let Some(oldest) = self.order.pop_front() else {
+                break;
+            };
+            self.chunks.remove(&oldest);
+        }
+        self.chunks.insert(pos, chunk);
+        self.order.push_back(pos);
+    }
+
+    /// Забирает чанк из кеша
+    pub fn take(&mut self, pos: &ChunkPos) -> Option<Chunk> {
+        let chunk = self.chunks.remove(pos)?;
+        self.order.retain(|cached| cached != pos);
+        Some(chunk)
+    }
+}
+
+impl FromWorld for UnloadedChunkCache {
+    fn from_world(world: &mut bevy::prelude::World) -> Self {
+        Self::new(world.resource::<ChunkLoadingSettings>().unloaded_cache_capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{ivec3, uvec3};
+    use crate::logic::block::{Block, BlockId};
+    use crate::logic::chunk::Chunk;
+    use crate::logic::world::unloaded_chunk_cache::UnloadedChunkCache;
+
+    fn chunk(id: u16) -> Chunk {
+        let mut chunk = Chunk::new(());
+        chunk.set(&uvec3(0, 0, 0).try_into().unwrap(), Some(Block::new(BlockId(id))));
+        chunk
+    }
+
+    fn id(chunk: &Chunk) -> u16 {
+        chunk[&uvec3(0, 0, 0).try_into().unwrap()].unwrap().id().0
+    }
+
+    #[test]
+    fn oldest_chunks_are_evicted() {
+        let mut cache = UnloadedChunkCache::new(2);
+        cache.insert(ivec3(0, 0, 0).into(), chunk(1));
+        cache.insert(ivec3(1, 0, 0).into(), chunk(2));
+        // Повторная выгрузка заменяет старую версию и делает чанк самым новым
+        cache.insert(ivec3(0, 0, 0).into(), chunk(3));
+        cache.insert(ivec3(2, 0, 0).into(), chunk(4));
+
+        assert!(cache.take(&ivec3(1, 0, 0).into()).is_none());
+        assert_eq!(cache.take(&ivec3(0, 0, 0).into()).map(|chunk| id(&chunk)), Some(3));
+        assert!(cache.take(&ivec3(0, 0, 0).into()).is_none());
+        assert_eq!(cache.take(&ivec3(2, 0, 0).into()).map(|chunk| id(&chunk)), Some(4));
+    }
+}
@@ -1,87 +1,208 @@
-use std::cmp::min;
-use std::sync::{Arc, RwLock};
-use bevy::math::uvec3;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
 use bevy::prelude::Resource;
-use bevy::utils::HashSet;
-use noise::NoiseFn;
-use chunk::{CHUNK_SIZE, ChunkPos};
-use crate::logic::block::{Block, BlockType};
+use bevy::utils::{HashMap, HashSet};
+use chunk::{AbsoluteBlockPos, ChunkPos};
+use crate::logic::block::Block;
 use crate::logic::chunk::{Chunk, ChunkMap};
-
-pub const WORLD_HEIGHT: usize = 256;
-pub const WORLD_HEIGHT_CHUNKS: usize = WORLD_HEIGHT / CHUNK_SIZE;
-
-type Noise = noise::Fbm<noise::SuperSimplex>;
+use crate::logic::light::{ChunkLight, LightMap};
+use crate::logic::world::chunk_tickets::{ChunkTicket, ChunkTickets, TicketId, TicketKind};
 
 /// Структура мира
-#[derive(Resource)]
+#[derive(Resource, Default)]
 pub struct World {
-    pub noise: Noise,
-
     /// Список загруженных чанков
     pub chunk_map: ChunkMap,
-}
 
-impl Default for World {
-    fn default() -> Self {
-        let mut noise = noise::Fbm::new(3);
-        noise.frequency = 0.05;
-        noise.persistence = 0.25;
-        World {
-            noise,
-            chunk_map: ChunkMap::default(),
-        }
-    }
+    /// Освещенность загруженных чанков, содержит те же ключи что и [Self::chunk_map]
+    pub light_map: LightMap,
+
+    /// Загруженные чанки измененные с момента загрузки или последнего сохранения
+    dirty_chunks: Mutex<HashSet<ChunkPos>>,
+
+    /// Блоки измененные через [World::set_block], освещение и события для них еще не обработаны
+    changed_blocks: Mutex<Vec<AbsoluteBlockPos>>,
+
+    /// Тикеты загрузки чанков, см [World::add_ticket]
+    tickets: Mutex<ChunkTickets>,
+
+    /// Уровни загрузки чанков по якорям и тикетам, см [World::chunk_level]
+    chunk_levels: RwLock<HashMap<ChunkPos, TicketKind>>,
 }
 
 impl World {
     /// Возвращает загружен ли чанк по переданной позиции
     pub fn is_chunk_loaded(&self, pos: &ChunkPos) -> bool {
-        self.chunk_map.read().unwrap().contains_key(pos)
+        self.chunk_map.contains_key(pos)
     }
 
     /// Возвращает ссылку на чанк по [ChunkCoord] если такой чанк загружен в память
     pub fn get_chunk(&self, coord: &ChunkPos) -> Option<Arc<RwLock<Chunk>>> {
-        self.chunk_map.read().unwrap().get(coord).map(|arc| Arc::clone(arc))
+        self.chunk_map.get(coord)
     }
 
     /// Возвращает список загруженных чанков
     pub fn get_chunk_keys(&self) -> HashSet<ChunkPos> {
-        self.chunk_map.read().unwrap().iter().map(|(k, _)| { *k }).collect()
+        self.chunk_map.keys().into_iter().collect()
+    }
+
+    /// Добавляет новый чанк вместе с его освещенностью, если чанк по этим координатам уже загружен паникует.
+    /// Свет соседних чанков нужно распространить отдельно, см [crate::logic::light::LightUpdater::on_chunk_loaded]
+    pub fn add_chunk(&self, pos: ChunkPos, chunk: Chunk, light: ChunkLight) {
+        assert!(!self.chunk_map.contains_key(&pos));
+        // Освещенность добавляется первой, чтобы загруженный чанк всегда имел освещенность
+        self.light_map.insert(pos, Arc::new(RwLock::new(light)));
+        self.chunk_map.insert(pos, Arc::new(RwLock::new(chunk)));
+    }
+
+    /// Удаляет чанк и возвращает его, если чанк по этим координатам уже удален паникует
+    pub fn remove_chunk(&self, coord: &ChunkPos) -> Arc<RwLock<Chunk>> {
+        let chunk = self.chunk_map.remove(coord).unwrap();
+        self.light_map.remove(coord);
+        chunk
+    }
+
+    /// Возвращает блок по абсолютным координатам, [None] если чанк с этим блоком не загружен
+    pub fn get_block(&self, pos: AbsoluteBlockPos) -> Option<Option<Block>> {
+        let chunk_pos = ChunkPos::from(pos);
+        let block_pos = chunk_pos.try_global_pos_into_chunk_pos(pos).unwrap();
+        let chunk = self.get_chunk(&chunk_pos)?;
+        let block = chunk.read().unwrap()[&block_pos];
+        Some(block)
+    }
+
+    /// Устанавливает блок по абсолютным координатам, [None] удаляет блок. Возвращает false если чанк с этим блоком
+    /// не загружен.
+    ///
+    /// Освещение пересчитывается и [crate::logic::world::ChunkUpdateEvent::BlockChanged] отправляется
+    /// [crate::logic::world::WorldPlugin] в конце кадра
+    pub fn set_block(&self, pos: AbsoluteBlockPos, block: Option<Block>) -> bool {
+        let chunk_pos = ChunkPos::from(pos);
+        let block_pos = chunk_pos.try_global_pos_into_chunk_pos(pos).unwrap();
+        let Some(chunk) = self.get_chunk(&chunk_pos) else {
+            return false;
+        };
+        let mut chunk = chunk.write().unwrap();
+        if chunk[&block_pos] == block {
+            return true;
+        }
+        chunk.set(&block_pos, block);
+        self.mark_chunk_dirty(chunk_pos);
+        self.changed_blocks.lock().unwrap().push(pos);
+        true
+    }
+
+    /// Возвращает блоки измененные с момента последнего вызова
+    pub fn take_changed_blocks(&self) -> Vec<AbsoluteBlockPos> {
+        std::mem::take(&mut *self.changed_blocks.lock().unwrap())
     }
 
-    /// Добавляет новый чанк, если чанк по этим координатам уже загружен паникует
-    pub fn add_chunk(&self, pos: ChunkPos, chunk: Chunk) {
-        let mut chunk_map = self.chunk_map.write().unwrap();
-        assert!(!chunk_map.contains_key(&pos));
-        let chunk = Arc::new(RwLock::new(chunk));
-        chunk_map.insert(pos, chunk);
+    /// Помечает чанк как измененный, такой чанк будет сохранен на диск при выгрузке или автосохранении
+    pub fn mark_chunk_dirty(&self, pos: ChunkPos) {
+        self.dirty_chunks.lock().unwrap().insert(pos);
     }
 
-    /// Удаляет чанк, если чанк по этим координатам уже удален паникует
-    pub fn remove_chunk(&self, coord: &ChunkPos) {
-        self.chunk_map.write().unwrap().remove(&coord).unwrap();
+    /// Снимает с чанка пометку об изменении, возвращает была ли пометка
+    pub fn take_dirty_chunk(&self, pos: &ChunkPos) -> bool {
+        self.dirty_chunks.lock().unwrap().remove(pos)
+    }
+
+    /// Снимает пометку об изменении со всех чанков и возвращает их список
+    pub fn take_dirty_chunks(&self) -> HashSet<ChunkPos> {
+        std::mem::take(&mut *self.dirty_chunks.lock().unwrap())
+    }
+
+    /// Добавляет тикет загрузки: чанки в радиусе `radius` вокруг `pos` загружаются с уровнем не ниже `kind`, пока
+    /// тикет не удален через [World::remove_ticket] или не истек `duration`. Время действия считается по
+    /// [bevy::time::Time] с момента применения тикета.
+    ///
+    /// Позволяет загружать области мира без сущностей с [world_anchor::WorldAnchor], тикеты применяются
+    /// [crate::logic::world::WorldPlugin] в следующем кадре
+    pub fn add_ticket(&self, pos: ChunkPos, radius: u32, kind: TicketKind, duration: Option<Duration>) -> TicketId {
+        self.tickets.lock().unwrap().add(pos, radius, kind, duration)
+    }
+
+    /// Удаляет тикет загрузки, возвращает false если тикет уже удален или истек
+    pub fn remove_ticket(&self, id: TicketId) -> bool {
+        self.tickets.lock().unwrap().remove(id)
+    }
+
+    /// Удаляет истекшие к `now` тикеты и возвращает изменились ли тикеты с прошлого вызова.
+    /// `now` - время [bevy::time::Time::elapsed]
+    pub fn take_tickets_changed(&self, now: Duration) -> bool {
+        self.tickets.lock().unwrap().take_changed(now)
+    }
+
+    /// Возвращает действующие тикеты загрузки
+    pub fn tickets(&self) -> Vec<ChunkTicket> {
+        self.tickets.lock().unwrap().iter().copied().collect()
+    }
+
+    /// Возвращает уровень загрузки чанка, [None] если ни один якорь или тикет не требует загрузки чанка
+    pub fn chunk_level(&self, pos: &ChunkPos) -> Option<TicketKind> {
+        self.chunk_levels.read().unwrap().get(pos).copied()
+    }
+
+    /// Возвращает загруженные чанки уровня не ниже [TicketKind::Tick]
+    pub fn ticking_chunks(&self) -> Vec<ChunkPos> {
+        self.chunk_levels.read().unwrap().iter()
+            .filter(|(pos, level)| **level >= TicketKind::Tick && self.is_chunk_loaded(pos))
+            .map(|(pos, _)| *pos)
+            .collect()
+    }
+
+    /// Устанавливает уровень загрузки чанка, возвращает изменился ли уровень
+    pub fn set_chunk_level(&self, pos: ChunkPos, level: Option<TicketKind>) -> bool {
+        let mut chunk_levels = self.chunk_levels.write().unwrap();
+        let old_level = match level {
+            Some(level) => { chunk_levels.insert(pos, level) }
+            None => { chunk_levels.remove(&pos) }
+        };
+        old_level != level
+    }
+
+    /// Заменяет уровни загрузки всех чанков, возвращает чанки уровень которых изменился
+    pub fn replace_chunk_levels(&self, levels: HashMap<ChunkPos, TicketKind>) -> Vec<ChunkPos> {
+        let mut chunk_levels = self.chunk_levels.write().unwrap();
+        let mut changed: Vec<ChunkPos> = chunk_levels.iter()
+            .filter(|(pos, level)| levels.get(*pos) != Some(level))
+            .map(|(pos, _)| *pos)
+            .collect();
+        changed.extend(levels.keys().filter(|pos| !chunk_levels.contains_key(*pos)));
+        *chunk_levels = levels;
+        changed
     }
 }
 
-pub fn gen_chunk(pos: ChunkPos, noise: &Noise) -> Chunk {
-    let mut chunk = Chunk::new(());
+#[cfg(test)]
+mod tests {
+    use bevy::math::ivec3;
+    use chunk::ChunkPos;
+    use crate::logic::block::{Block, BlockId};
+    use crate::logic::chunk::Chunk;
+    use crate::logic::light::ChunkLight;
+    use crate::logic::world::World;
 
-    for x in 0..CHUNK_SIZE {
-        for y in 0..CHUNK_SIZE {
-            let h = noise.get([(pos.x * CHUNK_SIZE as i32 + x as i32) as f64 * 0.14, (pos.y * CHUNK_SIZE as i32 + y as i32) as f64 * 0.14]);
-            let h = (h + 1.0) / 2.0;
-            let h = h / 2.0 + 0.2;
+    #[test]
+    fn set_and_get_block() {
+        let world = World::default();
+        let chunk_pos: ChunkPos = ivec3(-1, 0, 0).into();
+        world.add_chunk(chunk_pos, Chunk::new(()), ChunkLight::new());
 
-            let h = (128f64 * h) as i32;
-            let h = h - (pos.z * CHUNK_SIZE as i32);
-            if h <= 0 { continue; }
+        let stone = Some(Block::new(BlockId(1)));
+        assert_eq!(world.get_block(ivec3(-1, 15, 0).into()), Some(None));
+        assert!(world.set_block(ivec3(-1, 15, 0).into(), stone));
+        assert_eq!(world.get_block(ivec3(-1, 15, 0).into()), Some(stone));
+        assert_eq!(world.get_chunk(&chunk_pos).unwrap().read().unwrap()[&ivec3(15, 15, 0).try_into().unwrap()], stone);
 
-            for z in 0..min(h as usize, CHUNK_SIZE) {
-                let pos = uvec3(x as u32, y as u32, z as u32).try_into().unwrap();
-                chunk[&pos] = Some(Block::new(BlockType::GRASS));
-            }
-        }
+        // Повторная установка того же блока ничего не меняет
+        assert!(world.set_block(ivec3(-1, 15, 0).into(), stone));
+        assert_eq!(world.take_changed_blocks().len(), 1);
+        assert!(world.take_dirty_chunk(&chunk_pos));
+
+        // Чанк с неотрицательными координатами не загружен
+        assert_eq!(world.get_block(ivec3(0, 15, 0).into()), None);
+        assert!(!world.set_block(ivec3(0, 15, 0).into(), stone));
+        assert!(world.take_changed_blocks().is_empty());
     }
-    chunk
 }
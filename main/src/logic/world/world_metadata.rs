@@ -0,0 +1,154 @@
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::logic::world::generator::world_generator_by_name;
+use crate::logic::world::{WorldPlugin, WorldSeed};
+
+const METADATA_FILE: &str = "world.cfg";
+
+/// Генератор используемый если он не задан ни в параметрах запуска, ни в директории мира
+const DEFAULT_GENERATOR: &str = "noise";
+
+/// Параметры генерации мира.
+///
+/// Записываются в директорию мира при ее создании, файл состоит из строк вида `key = value`. При следующих запусках
+/// мир генерируется с сохраненными параметрами, иначе новые чанки не совпадут с сохраненными
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WorldMetadata {
+    pub seed: WorldSeed,
+    pub generator: String,
+}
+
+#[derive(Debug)]
+pub enum WorldMetadataError {
+    Io(PathBuf, io::Error),
+    Parse(PathBuf, String),
+    UnknownGenerator(String),
+    SeedMismatch { saved: WorldSeed, requested: WorldSeed },
+    GeneratorMismatch { saved: String, requested: String },
+}
+
+impl Display for WorldMetadataError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WorldMetadataError::Io(path, err) => { write!(f, "Can't access {}: {}", path.display(), err) }
+            WorldMetadataError::Parse(path, err) => { write!(f, "Can't parse {}: {}", path.display(), err) }
+            WorldMetadataError::UnknownGenerator(name) => { write!(f, "Unknown generator {}", name) }
+            WorldMetadataError::SeedMismatch { saved, requested } => {
+                write!(f, "World was created with seed {}, but seed {} is requested", saved.0, requested.0)
+            }
+            WorldMetadataError::GeneratorMismatch { saved, requested } => {
+                write!(f, "World was created with generator {}, but generator {} is requested", saved, requested)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WorldMetadataError {}
+
+impl WorldMetadata {
+    /// Возвращает параметры мира в директории `world_dir`.
+    ///
+    /// Если мир уже создан, то используются сохраненные параметры, а явно заданные `seed` и `generator` должны с
+    /// ними совпадать. Иначе параметры берутся из аргументов и записываются в директорию мира
+    pub fn resolve(
+        world_dir: &Path,
+        seed: Option<WorldSeed>,
+        generator: Option<&str>,
+    ) -> Result<Self, WorldMetadataError> {
+        if let Some(generator) = generator {
+            if world_generator_by_name(generator).is_none() {
+                return Err(WorldMetadataError::UnknownGenerator(generator.to_string()));
+            }
+        }
+
+        let Some(saved) = Self::read(world_dir)? else {
+            let metadata = Self {
+                seed: seed.unwrap_or_default(),
+                generator: generator.unwrap_or(DEFAULT_GENERATOR).to_string(),
+            };
+            metadata.write(world_dir)?;
+            return Ok(metadata);
+        };
+
+        if let Some(seed) = seed.filter(|seed| *seed != saved.seed) {
+            return Err(WorldMetadataError::SeedMismatch { saved: saved.seed, requested: seed });
+        }
+        if let Some(generator) = generator.filter(|generator| *generator != saved.generator) {
+            return Err(WorldMetadataError::GeneratorMismatch {
+                saved: saved.generator,
+                requested: generator.to_string(),
+            });
+        }
+        Ok(saved)
+    }
+
+    /// Читает параметры мира, если мир еще не создан возвращает [None]
+    pub fn read(world_dir: &Path) -> Result<Option<Self>, WorldMetadataError> {
+        let path = world_dir.join(METADATA_FILE);
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => { content }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => { return Ok(None); }
+            Err(err) => { return Err(WorldMetadataError::Io(path, err)); }
+        };
+
+        let (mut seed, mut generator) = (None, None);
+        for line in content.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+            match line.split_once('=').map(|(key, value)| (key.trim(), value.trim())) {
+                Some(("seed", value)) => { seed = value.parse::<u64>().ok().map(WorldSeed) }
+                Some(("generator", value)) => { generator = Some(value.to_string()) }
+                _ => { return Err(WorldMetadataError::Parse(path, format!("incorrect line {}", line))); }
+            }
+        }
+        match (seed, generator) {
+            (Some(seed), Some(generator)) => { Ok(Some(Self { seed, generator })) }
+            _ => { Err(WorldMetadataError::Parse(path, "seed or generator is missing".to_string())) }
+        }
+    }
+
+    pub fn write(&self, world_dir: &Path) -> Result<(), WorldMetadataError> {
+        let path = world_dir.join(METADATA_FILE);
+        std::fs::create_dir_all(world_dir)
+            .and_then(|_| std::fs::write(&path, format!("seed = {}\ngenerator = {}\n", self.seed.0, self.generator)))
+            .map_err(|err| WorldMetadataError::Io(path, err))
+    }
+
+    /// Создает [WorldPlugin] с генератором мира
+    pub fn world_plugin(&self) -> WorldPlugin {
+        let generator = world_generator_by_name(&self.generator)
+            .unwrap_or_else(|| panic!("Unknown generator {}", self.generator));
+        WorldPlugin::new_boxed(generator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::logic::world::{WorldMetadata, WorldSeed};
+    use crate::logic::world::world_metadata::WorldMetadataError;
+
+    #[test]
+    fn saved_metadata_is_used_on_later_runs() {
+        let dir = tempfile::tempdir().unwrap();
+        let world_dir = dir.path().join("world");
+        assert!(WorldMetadata::read(&world_dir).unwrap().is_none());
+
+        let created = WorldMetadata::resolve(&world_dir, Some(WorldSeed(42)), Some("flat")).unwrap();
+        assert_eq!(created, WorldMetadata { seed: WorldSeed(42), generator: "flat".to_string() });
+
+        assert_eq!(WorldMetadata::resolve(&world_dir, None, None).unwrap(), created);
+        assert_eq!(WorldMetadata::resolve(&world_dir, Some(WorldSeed(42)), Some("flat")).unwrap(), created);
+    }
+
+    #[test]
+    fn mismatch_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        WorldMetadata::resolve(dir.path(), Some(WorldSeed(1)), None).unwrap();
+
+        let result = WorldMetadata::resolve(dir.path(), Some(WorldSeed(2)), None);
+        assert!(matches!(result, Err(WorldMetadataError::SeedMismatch { .. })));
+        let result = WorldMetadata::resolve(dir.path(), None, Some("void"));
+        assert!(matches!(result, Err(WorldMetadataError::GeneratorMismatch { .. })));
+        let result = WorldMetadata::resolve(dir.path(), None, Some("mountains"));
+        assert!(matches!(result, Err(WorldMetadataError::UnknownGenerator(_))));
+    }
+}
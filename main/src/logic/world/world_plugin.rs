@@ -1,26 +1,112 @@
-use std::cmp::min;
-use bevy::math::ivec3;
+use std::io;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+use bevy::app::AppExit;
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic};
 use bevy::prelude::*;
 use bevy::tasks::{AsyncComputeTaskPool, Task};
 use bevy::utils::HashMap;
 use futures_lite::future::{block_on, poll_once};
-use chunk::ChunkPos;
-use world_anchor::{WorldAnchor, WorldAnchorInChunkPos};
+use chunk::{AbsoluteBlockPos, CHUNK_SIZE, ChunkPos};
+use world_anchor::{load_area_chunks, LoadShape, WorldAnchor, WorldAnchorInChunkPos};
+use crate::logic::block::{BlockRegistry, SharedBlockRegistry};
 use crate::logic::chunk::Chunk;
-use crate::logic::world::world::{gen_chunk, World, WORLD_HEIGHT_CHUNKS};
+use crate::logic::frame_budget::FrameBudget;
+use crate::logic::light::{ChunkLight, compute_chunk_light, LightUpdater};
+use crate::logic::world::generator::{NoiseWorldGenerator, SharedWorldGenerator, WorldGenerator};
+use crate::logic::world::world::World;
+use crate::logic::world::TicketKind;
+use crate::logic::world::world_storage::{WorldStorage, WorldStorageSettings};
+use crate::logic::world::unloaded_chunk_cache::UnloadedChunkCache;
+use crate::logic::world::WorldSeed;
 
-pub struct WorldPlugin;
+/// Плагин мира, отвечает за загрузку, генерацию, сохранение и выгрузку чанков
+pub struct WorldPlugin {
+    /// Генератор передается в [SharedWorldGenerator] при сборке плагина
+    generator: Mutex<Option<Box<dyn WorldGenerator>>>,
+}
+
+impl WorldPlugin {
+    pub fn new(generator: impl WorldGenerator) -> Self {
+        Self::new_boxed(Box::new(generator))
+    }
+
+    pub fn new_boxed(generator: Box<dyn WorldGenerator>) -> Self {
+        Self {
+            generator: Mutex::new(Some(generator)),
+        }
+    }
+}
+
+impl WorldPlugin {
+    /// Число загруженных чанков ожидающих добавления в мир, см [ChunkIntegrationBudget]
+    pub const CHUNKS_TO_INTEGRATE: DiagnosticId = DiagnosticId::from_u128(171249405839120958861472097164207011921);
 
+    /// Число загруженных чанков уровня не ниже [TicketKind::Tick], см [World::ticking_chunks]
+    pub const TICKING_CHUNKS: DiagnosticId = DiagnosticId::from_u128(300173498821735258930990453101366863011);
+}
+
+impl Default for WorldPlugin {
+    fn default() -> Self {
+        Self::new(NoiseWorldGenerator::default())
+    }
+}
+
+/// Seed мира берется из ресурса [WorldSeed] если он был добавлен до плагина.
+/// Требует [SharedBlockRegistry], см [crate::logic::block::BlockRegistryPlugin]
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
+        let mut generator = self.generator.lock().unwrap().take().expect("WorldPlugin can be built only once");
+        let seed = app.world.get_resource::<WorldSeed>().copied().unwrap_or_default();
+        generator.set_seed(&seed);
+        if let Err(err) = generator.set_block_registry(app.world.resource::<SharedBlockRegistry>()) {
+            panic!("Can't configure world generator: {}", err);
+        }
+        info!("World seed: {}", seed.0);
+
         app
-            .init_resource::<World>()
+            .add_plugins(WorldCorePlugin)
+            .insert_resource(seed)
+            .insert_resource(SharedWorldGenerator::new(generator))
+            .init_resource::<WorldStorageSettings>()
+            .init_resource::<WorldStorage>()
+            .init_resource::<AutosaveTimer>()
+            .init_resource::<ChunkLoadingSettings>()
+            .init_resource::<ChunkIntegrationBudget>()
+            .init_resource::<UnloadedChunkCache>()
+            .init_resource::<ChunkUnloadTimers>()
             .init_resource::<ChunkLoadingQueue>()
+            .init_resource::<ChunkLoadingQueueCameras>()
             .init_resource::<ChunkLoadingTasks>()
-            .add_event::<ChunkUpdateEvent>()
+            .init_resource::<ChunkSavingQueue>()
+            .init_resource::<ChunkSavingTasks>()
             .add_systems(Update, manage_chunk_loading_state)
+            .add_systems(Update, prioritize_chunks_in_view.after(manage_chunk_loading_state))
             .add_systems(Update, load_new_chunks_from_queue)
             .add_systems(Update, spawn_loaded_chunks)
+            .add_systems(Update, autosave_dirty_chunks)
+            .add_systems(Update, save_chunks_from_queue)
+            .add_systems(Update, collect_saved_chunks)
+            .add_systems(Update, measure_ticking_chunks)
+            .add_systems(Last, flush_world_on_exit)
+            .register_diagnostic(Diagnostic::new(Self::CHUNKS_TO_INTEGRATE, "chunks_to_integrate", 20))
+            .register_diagnostic(Diagnostic::new(Self::TICKING_CHUNKS, "ticking_chunks", 20))
+        ;
+    }
+}
+
+/// Состояние мира без источника чанков: ресурс [World], события [ChunkUpdateEvent] и обработка изменений блоков.
+///
+/// Добавляется [WorldPlugin], отдельно используется сетевым клиентом, которому чанки присылает сервер.
+/// Требует [SharedBlockRegistry]
+pub struct WorldCorePlugin;
+
+impl Plugin for WorldCorePlugin {
+    fn build(&self, app: &mut App) {
+        app
+            .init_resource::<World>()
+            .add_event::<ChunkUpdateEvent>()
+            .add_systems(PostUpdate, apply_block_changes)
         ;
     }
 }
@@ -29,121 +115,821 @@ impl Plugin for WorldPlugin {
 pub enum ChunkUpdateEvent {
     Loaded(ChunkPos),
     Unloaded(ChunkPos),
+
+    /// Изменилась освещенность чанка или освещенность соседних блоков на его границе
+    LightUpdated(ChunkPos),
+
+    /// Изменился блок, см [World::set_block]
+    BlockChanged(AbsoluteBlockPos),
+
+    /// Изменился уровень загруженного чанка, см [World::chunk_level]
+    LevelChanged(ChunkPos),
 }
 
+/// Настройки загрузки и выгрузки чанков вокруг [WorldAnchor]
+#[derive(Resource)]
+pub struct ChunkLoadingSettings {
+    /// Запас к радиусам якорей: чанки дальше области загрузки на этот запас выгружаются сразу
+    pub unload_margin: u32,
+
+    /// Сколько чанк в пределах запаса остается загруженным после выхода из области загрузки
+    pub unload_delay: Duration,
+
+    /// Сколько недавно выгруженных чанков хранится в памяти, см [UnloadedChunkCache]
+    pub unloaded_cache_capacity: usize,
+
+    /// Сколько задач загрузки чанков может выполняться одновременно
+    pub max_loading_tasks: usize,
+
+    /// Насколько позже загружаются чанки вне поля зрения камеры: приоритет чанка прямо позади камеры
+    /// умножается на `1 + view_direction_weight`
+    pub view_direction_weight: f32,
+
+    /// Очередь загрузки пересортировывается после смещения камеры на это расстояние в блоках с прошлой сортировки
+    pub resort_distance: f32,
+
+    /// Очередь загрузки пересортировывается после поворота камеры на этот угол в радианах с прошлой сортировки
+    pub resort_angle: f32,
+}
+
+impl Default for ChunkLoadingSettings {
+    fn default() -> Self {
+        Self {
+            unload_margin: 1,
+            unload_delay: Duration::from_secs(10),
+            unloaded_cache_capacity: 512,
+            max_loading_tasks: 1024,
+            view_direction_weight: 1.,
+            resort_distance: CHUNK_SIZE as f32 / 2.,
+            resort_angle: 0.2,
+        }
+    }
+}
+
+/// Сколько загруженных чанков добавляется в мир за кадр, остальные ждут следующих кадров
+#[derive(Resource, Clone, Copy, Deref, DerefMut)]
+pub struct ChunkIntegrationBudget(pub FrameBudget);
+
+impl Default for ChunkIntegrationBudget {
+    fn default() -> Self {
+        Self(FrameBudget { max_items: 32, max_time: Duration::from_millis(4) })
+    }
+}
+
+/// Время выхода из области загрузки для чанков ожидающих выгрузки, см [ChunkLoadingSettings::unload_delay]
+#[derive(Resource, Default, Deref, DerefMut)]
+struct ChunkUnloadTimers(HashMap<ChunkPos, Duration>);
+
 #[derive(Resource, Default, Deref, DerefMut)]
 struct ChunkLoadingQueue(Vec<ChunkLoadInfo>);
 
+/// Позиции и направления камер при последней сортировке [ChunkLoadingQueue]
+#[derive(Resource, Default, Deref, DerefMut)]
+struct ChunkLoadingQueueCameras(Vec<(Vec3, Vec3)>);
+
+#[derive(Resource, Default, Deref, DerefMut)]
+struct ChunkLoadingTasks(HashMap<ChunkPos, ChunkLoadingTask>);
+
+struct ChunkLoadingTask {
+    /// Приоритет чанка в [ChunkLoadingQueue] при запуске задачи, готовые чанки добавляются в мир в этом порядке
+    priority: f32,
+    task: Task<(Chunk, ChunkLight)>,
+}
+
+/// Чанки ожидающие сохранения на диск
+///
+/// Чанк не может сохраняться несколькими задачами одновременно, иначе более старая версия может перезаписать более
+/// новую, поэтому пока предыдущее сохранение не завершилось чанк ждет в этой очереди
+#[derive(Resource, Default, Deref, DerefMut)]
+struct ChunkSavingQueue(HashMap<ChunkPos, Arc<RwLock<Chunk>>>);
+
 #[derive(Resource, Default, Deref, DerefMut)]
-struct ChunkLoadingTasks(HashMap<ChunkPos, Task<Chunk>>);
+struct ChunkSavingTasks(HashMap<ChunkPos, Task<io::Result<()>>>);
+
+#[derive(Resource, Deref, DerefMut)]
+struct AutosaveTimer(Timer);
+
+impl FromWorld for AutosaveTimer {
+    fn from_world(world: &mut bevy::prelude::World) -> Self {
+        let interval = world.resource::<WorldStorageSettings>().autosave_interval;
+        AutosaveTimer(Timer::new(interval, TimerMode::Repeating))
+    }
+}
 
 #[derive(Copy, Clone)]
 struct ChunkLoadInfo {
     pos: ChunkPos,
-    priority: i32,
+
+    /// Наименьшее расстояние до центров областей загрузки, деленное на важность области
+    distance: f32,
+
+    /// Чем меньше, тем раньше загружается чанк, учитывает направление взгляда камер
+    priority: f32,
 }
 
 
-/// Загружает управлаяет очередью загрузки чанков, а так же выгружает не нужные чанки из памяти
+/// Загружает управлаяет очередью загрузки чанков, а так же выгружает не нужные чанки из памяти.
+///
+/// Области загрузки задаются якорями [WorldAnchor] с уровнем [TicketKind::Render] и тикетами
+/// [World::add_ticket], уровень чанка равен наибольшему уровню среди областей в которые он входит
 fn manage_chunk_loading_state(
+    time: Res<Time>,
+    settings: Res<ChunkLoadingSettings>,
     world: Res<World>,
+    block_registry: Res<SharedBlockRegistry>,
+    mut unload_timers: ResMut<ChunkUnloadTimers>,
+    mut unloaded_chunk_cache: ResMut<UnloadedChunkCache>,
     mut chunk_loading_queue: ResMut<ChunkLoadingQueue>,
+    mut queue_cameras: ResMut<ChunkLoadingQueueCameras>,
+    mut chunk_saving_queue: ResMut<ChunkSavingQueue>,
     mut chunk_event_writer: EventWriter<ChunkUpdateEvent>,
-    chunk_loading_tasks: ResMut<ChunkLoadingTasks>,
+    mut chunk_loading_tasks: ResMut<ChunkLoadingTasks>,
     changed_world_anchors_pos: Query<(), Changed<WorldAnchorInChunkPos>>,
     changed_world_anchors_conf: Query<(), Changed<WorldAnchor>>,
     world_anchors_pos: Query<(&WorldAnchorInChunkPos, &WorldAnchor)>,
+    cameras: Query<&GlobalTransform, With<Camera>>,
 ) {
-    // Если позиции и параметры якорей не изменились то пересчитывать не нужно
-    if changed_world_anchors_pos.is_empty() && changed_world_anchors_conf.is_empty() {
+    // Если якоря и тикеты не изменились и ни один чанк не дождался выгрузки то пересчитывать не нужно
+    let now = time.elapsed();
+    let is_unload_delay_expired = unload_timers.values()
+        .any(|since| now.saturating_sub(*since) >= settings.unload_delay);
+    let is_tickets_changed = world.take_tickets_changed(now);
+    if changed_world_anchors_pos.is_empty() && changed_world_anchors_conf.is_empty() && !is_tickets_changed
+        && !is_unload_delay_expired {
         return;
     }
     info!("manage_chunk_loading_state()");
 
+    // Области загрузки якорей и тикетов, тикет загружает квадратную область
+    let mut areas: Vec<(ChunkPos, WorldAnchor, TicketKind)> = world_anchors_pos.iter()
+        .map(|(anchor_pos, world_anchor)| (anchor_pos.pos, world_anchor.clone(), TicketKind::Render))
+        .collect();
+    areas.extend(world.tickets().into_iter().map(|ticket| {
+        let area = WorldAnchor {
+            load_radius: ticket.radius,
+            vertical_load_radius: ticket.radius,
+            shape: LoadShape::Square,
+            ..default()
+        };
+        (ticket.pos, area, ticket.kind)
+    }));
+
     // Список чанков которые нужно удалить
     let mut chunks_to_unload = world.get_chunk_keys();
 
     // Список чанков которые нужно загрузить
     let mut chunks_to_load = HashMap::<ChunkPos, ChunkLoadInfo>::new();
 
-    // Итерируемся по всем WorldAnchor
-    for (pos, world_anchor) in world_anchors_pos.iter() {
-        let load_radius = world_anchor.load_radius as i32;
-
-        // Получаем координаты чанка в котором находится WorldAnchor
-        let mut anchor_chunk_coord = pos.pos;
-        anchor_chunk_coord.z = 0;
-
-        if load_radius == 0 { continue; }
-
-        for x in anchor_chunk_coord.x - load_radius + 1..anchor_chunk_coord.x + load_radius {
-            for y in anchor_chunk_coord.y - load_radius + 1..anchor_chunk_coord.y + load_radius {
-                for z in 0..(WORLD_HEIGHT_CHUNKS as i32) {
-                    let pos = ivec3(x, y, z).into();
-                    // Удаляем чанк находящийся внутри радиуса из списка чанков на удаление
-                    if !chunks_to_unload.remove(&pos) {
-                        // Если такого чанка вообще не было среди загруженных, добавляем его в очередь на загрузку
-                        if !world.is_chunk_loaded(&pos) && !chunk_loading_tasks.contains_key(&pos) {
-                            let chunk_load_info = ChunkLoadInfo {
-                                pos,
-                                priority: anchor_chunk_coord.distance_squared(*pos),
-                            };
-                            // TODO если тут уже есть значение нужно выставить правильный приоритет
-                            chunks_to_load.insert(pos, chunk_load_info);
-                        }
-                    }
-                }
+    // Уровни чанков, чанк может входить в несколько областей, уровень берется наибольший
+    let mut chunk_levels = HashMap::<ChunkPos, TicketKind>::new();
+
+    for (center, area, kind) in areas.iter() {
+        for pos in load_area_chunks(*center, area) {
+            let level = chunk_levels.entry(pos).or_insert(*kind);
+            *level = (*level).max(*kind);
+
+            // Удаляем чанк находящийся внутри области загрузки из списка чанков на удаление
+            if chunks_to_unload.remove(&pos) {
+                continue;
             }
+            // Если такого чанка вообще не было среди загруженных, добавляем его в очередь на загрузку
+            if !world.is_chunk_loaded(&pos) && !chunk_loading_tasks.contains_key(&pos) {
+                let distance = (center.distance_squared(*pos) as f32).sqrt() / area.clamped_importance();
+                // Расстояние берется по ближайшей с учетом важности области
+                let chunk_load_info = chunks_to_load.entry(pos)
+                    .or_insert(ChunkLoadInfo { pos, distance, priority: 0. });
+                chunk_load_info.distance = chunk_load_info.distance.min(distance);
+            }
+        }
+    }
+
+    // Чанки вышедшие из области загрузки, но не дальше запаса, выгружаются только после задержки. Так чанки на
+    // границе области не выгружаются и не загружаются заново пока якорь колеблется между соседними чанками.
+    // До выгрузки такие чанки сохраняют прежний уровень
+    let unload_areas: Vec<(ChunkPos, WorldAnchor)> = areas.iter()
+        .map(|(center, area, _)| (*center, area.expanded(settings.unload_margin)))
+        .collect();
+    let mut delayed_chunks = HashMap::new();
+    chunks_to_unload.retain(|pos| {
+        if !unload_areas.iter().any(|(center, area)| area.contains(*center, *pos)) {
+            return true;
         }
+        let since = unload_timers.get(pos).copied().unwrap_or(now);
+        if now.saturating_sub(since) >= settings.unload_delay {
+            return true;
+        }
+        delayed_chunks.insert(*pos, since);
+        if let Some(level) = world.chunk_level(pos) {
+            chunk_levels.insert(*pos, level);
+        }
+        false
+    });
+    **unload_timers = delayed_chunks;
+
+    // Задачи загрузки чанков вышедших из всех областей загрузки больше не нужны, удаление задачи отменяет ее
+    let tasks_count = chunk_loading_tasks.len();
+    chunk_loading_tasks.retain(|pos, _| chunk_levels.contains_key(pos));
+    if chunk_loading_tasks.len() < tasks_count {
+        info!("Cancelled {} chunk loading tasks", tasks_count - chunk_loading_tasks.len());
     }
 
-    // Удаляем старые чанки
+    for pos in world.replace_chunk_levels(chunk_levels) {
+        if world.is_chunk_loaded(&pos) && !chunks_to_unload.contains(&pos) {
+            chunk_event_writer.send(ChunkUpdateEvent::LevelChanged(pos));
+        }
+    }
+
+    // Удаляем старые чанки, измененные перед этим отправляем на сохранение
+    for chunk_coord in chunks_to_unload.iter() {
+        let chunk = world.remove_chunk(chunk_coord);
+        unloaded_chunk_cache.insert(*chunk_coord, chunk.read().unwrap().clone());
+        if world.take_dirty_chunk(chunk_coord) {
+            chunk_saving_queue.insert(*chunk_coord, chunk);
+        }
+        chunk_event_writer.send(ChunkUpdateEvent::Unloaded(*chunk_coord))
+    }
+
+    // Чанки под выгруженными снова освещаются открытым небом
+    let mut light_updater = LightUpdater::new(&block_registry, &world);
     for chunk_coord in chunks_to_unload {
-        world.remove_chunk(&chunk_coord);
-        chunk_event_writer.send(ChunkUpdateEvent::Unloaded(chunk_coord))
+        light_updater.on_chunk_unloaded(chunk_coord);
+    }
+    for pos in light_updater.into_changed_chunks() {
+        chunk_event_writer.send(ChunkUpdateEvent::LightUpdated(pos));
     }
 
     chunk_loading_queue.clear();
-    chunk_loading_queue.extend(chunks_to_load.iter().map(|pos| pos.1));
-    chunk_loading_queue.sort_by(|a, b| { a.priority.cmp(&b.priority) })
+    chunk_loading_queue.extend(chunks_to_load.into_values());
+    **queue_cameras = camera_views(&cameras);
+    sort_chunk_loading_queue(&mut chunk_loading_queue, &queue_cameras, settings.view_direction_weight);
+}
+
+/// Пересортировывает очередь загрузки когда камера сместилась или повернулась достаточно с прошлой сортировки,
+/// см [ChunkLoadingSettings::resort_distance] и [ChunkLoadingSettings::resort_angle]
+fn prioritize_chunks_in_view(
+    settings: Res<ChunkLoadingSettings>,
+    mut chunk_loading_queue: ResMut<ChunkLoadingQueue>,
+    mut queue_cameras: ResMut<ChunkLoadingQueueCameras>,
+    changed_cameras: Query<(), (Changed<GlobalTransform>, With<Camera>)>,
+    cameras: Query<&GlobalTransform, With<Camera>>,
+) {
+    if changed_cameras.is_empty() || chunk_loading_queue.is_empty() {
+        return;
+    }
+    let cameras = camera_views(&cameras);
+    if !is_view_moved(&queue_cameras, &cameras, settings.resort_distance, settings.resort_angle) {
+        return;
+    }
+    sort_chunk_loading_queue(&mut chunk_loading_queue, &cameras, settings.view_direction_weight);
+    **queue_cameras = cameras;
+}
+
+/// Позиции и направления взгляда камер
+fn camera_views(cameras: &Query<&GlobalTransform, With<Camera>>) -> Vec<(Vec3, Vec3)> {
+    cameras.iter().map(|transform| (transform.translation(), transform.forward())).collect()
+}
+
+/// Сместилась ли какая-то из камер `(позиция, направление)` больше чем на `distance` или повернулась больше чем на
+/// `angle`. Появление или удаление камеры тоже считается изменением
+fn is_view_moved(old: &[(Vec3, Vec3)], new: &[(Vec3, Vec3)], distance: f32, angle: f32) -> bool {
+    old.len() != new.len() || old.iter().zip(new).any(|((old_eye, old_forward), (eye, forward))| {
+        old_eye.distance(*eye) > distance || old_forward.angle_between(*forward) > angle
+    })
+}
+
+fn sort_chunk_loading_queue(queue: &mut ChunkLoadingQueue, cameras: &[(Vec3, Vec3)], view_direction_weight: f32) {
+    for chunk_load_info in queue.iter_mut() {
+        let factor = view_factor(chunk_load_info.pos, cameras, view_direction_weight);
+        chunk_load_info.priority = chunk_load_info.distance * factor;
+    }
+    queue.sort_by(|a, b| a.priority.total_cmp(&b.priority));
+}
+
+/// Множитель приоритета чанка по направлению взгляда камер `(позиция, направление)`. Равен 1 для чанков прямо
+/// перед камерой и `1 + weight` для чанков позади нее, из нескольких камер берется наименьший
+fn view_factor(pos: ChunkPos, cameras: &[(Vec3, Vec3)], weight: f32) -> f32 {
+    let center = pos.get_absolute_coord().as_vec3() + Vec3::splat(CHUNK_SIZE as f32 / 2.);
+    cameras.iter()
+        .map(|(eye, forward)| {
+            let cos = (center - *eye).normalize_or_zero().dot(*forward);
+            1. + weight * (1. - cos) / 2.
+        })
+        .fold(1. + weight, f32::min)
 }
 
 /// Загружает чинки из очереди [ChunkLoadingQueue]
+///
+/// Недавно выгруженные чанки берутся из [UnloadedChunkCache]. Если чанк был ранее сохранен, то он читается из
+/// [WorldStorage], иначе генерируется заново. Освещенность не
+/// сохраняется и рассчитывается в той же задаче, открытое небо над чанком определяется по поверхности генератора.
+/// Одновременно выполняется не больше [ChunkLoadingSettings::max_loading_tasks] задач
 fn load_new_chunks_from_queue(
-    world: Res<World>,
+    settings: Res<ChunkLoadingSettings>,
+    world_generator: Res<SharedWorldGenerator>,
+    block_registry: Res<SharedBlockRegistry>,
+    world_storage: Res<WorldStorage>,
+    chunk_saving_queue: Res<ChunkSavingQueue>,
+    chunk_saving_tasks: Res<ChunkSavingTasks>,
+    mut unloaded_chunk_cache: ResMut<UnloadedChunkCache>,
     mut chunk_loading_queue: ResMut<ChunkLoadingQueue>,
     mut chunk_loading_tasks: ResMut<ChunkLoadingTasks>,
 ) {
     let pool = AsyncComputeTaskPool::get();
-    let size = min(1024 - chunk_loading_tasks.len(), chunk_loading_queue.len());
-    let new_tasks = chunk_loading_queue.drain(0..size)
-        .map(|chunk_loading_info| {
-            let noise = world.noise.clone();
-            let task = pool.spawn(async move {
-                let chunk = gen_chunk(chunk_loading_info.pos, &noise);
-                chunk
-            });
-            (chunk_loading_info.pos, task)
+    let mut size = settings.max_loading_tasks.saturating_sub(chunk_loading_tasks.len());
+    chunk_loading_queue.retain(|chunk_loading_info| {
+        let pos = chunk_loading_info.pos;
+        if size == 0 {
+            return true;
+        }
+        // Версия чанка в кеше новее сохраненной, поэтому ее можно брать не дожидаясь сохранения
+        let cached_chunk = unloaded_chunk_cache.take(&pos);
+        // Пока чанк не сохранен до конца его нельзя читать с диска, иначе прочитаем старую версию
+        let is_saving = chunk_saving_queue.contains_key(&pos) || chunk_saving_tasks.contains_key(&pos);
+        if cached_chunk.is_none() && is_saving {
+            return true;
+        }
+        size -= 1;
+
+        let world_generator = world_generator.clone();
+        let world_storage = world_storage.clone();
+        let block_registry = block_registry.clone();
+        let task = pool.spawn(async move {
+            let chunk = match cached_chunk {
+                Some(chunk) => { chunk }
+                None => { read_or_generate_chunk(&world_storage, &world_generator, pos) }
+            };
+            let light = compute_chunk_light(&block_registry, &chunk, pos, world_generator.sky_exposure(pos));
+            (chunk, light)
         });
-    chunk_loading_tasks.extend(new_tasks);
+        chunk_loading_tasks.insert(pos, ChunkLoadingTask { priority: chunk_loading_info.priority, task });
+        false
+    });
 }
 
+fn read_or_generate_chunk(
+    world_storage: &WorldStorage,
+    world_generator: &SharedWorldGenerator,
+    pos: ChunkPos,
+) -> Chunk {
+    match world_storage.read_chunk(pos) {
+        Ok(Some(chunk)) => { chunk }
+        Ok(None) => { world_generator.generate_chunk(pos) }
+        Err(err) => {
+            warn!("Can't read chunk {:?} from storage, regenerating: {}", pos, err);
+            world_generator.generate_chunk(pos)
+        }
+    }
+}
+
+/// Добавляет загруженные чанки в мир и распространяет свет между ними и их соседями.
+///
+/// За кадр добавляется не больше чанков чем позволяет [ChunkIntegrationBudget], остальные готовые задачи ждут
+/// следующих кадров
 fn spawn_loaded_chunks(
     world: Res<World>,
+    block_registry: Res<SharedBlockRegistry>,
+    budget: Res<ChunkIntegrationBudget>,
+    mut diagnostics: Diagnostics,
     mut chunk_event_writer: EventWriter<ChunkUpdateEvent>,
     mut chunk_loading_tasks: ResMut<ChunkLoadingTasks>,
 ) {
-    chunk_loading_tasks.retain(|pos, task| {
-        let chunk: Option<Chunk> = block_on(poll_once(task));
-        match chunk {
-            None => {
-                true
-            }
-            Some(chunk) => {
-                world.add_chunk(*pos, chunk);
-                chunk_event_writer.send(ChunkUpdateEvent::Loaded(*pos));
+    // Готовые чанки добавляются в порядке очереди загрузки, а не в порядке обхода HashMap
+    let mut finished_chunks: Vec<(ChunkPos, f32)> = chunk_loading_tasks.iter()
+        .filter(|(_, task)| task.task.is_finished())
+        .map(|(pos, task)| (*pos, task.priority))
+        .collect();
+    finished_chunks.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+    // Чанки добавляются по одному, поэтому время добавления и распространения света учитывается в бюджете
+    let mut budget = budget.start();
+    let mut integrated = 0;
+    let loaded_chunks = finished_chunks.iter()
+        .take_while(|_| budget.try_take())
+        .map(|(pos, _)| {
+            let task = chunk_loading_tasks.remove(pos).unwrap();
+            let (chunk, light) = block_on(task.task);
+            integrated += 1;
+            (*pos, chunk, light)
+        });
+    add_loaded_chunks(&world, &block_registry, loaded_chunks, &mut chunk_event_writer);
+
+    diagnostics.add_measurement(WorldPlugin::CHUNKS_TO_INTEGRATE, || (finished_chunks.len() - integrated) as f64);
+}
+
+fn measure_ticking_chunks(
+    world: Res<World>,
+    mut diagnostics: Diagnostics,
+) {
+    diagnostics.add_measurement(WorldPlugin::TICKING_CHUNKS, || world.ticking_chunks().len() as f64);
+}
+
+/// Добавляет чанки в мир, распространяет свет между ними и их соседями и отправляет события о загрузке чанков и
+/// изменении освещенности соседей
+pub fn add_loaded_chunks(
+    world: &World,
+    block_registry: &BlockRegistry,
+    chunks: impl IntoIterator<Item=(ChunkPos, Chunk, ChunkLight)>,
+    chunk_event_writer: &mut EventWriter<ChunkUpdateEvent>,
+) {
+    let mut light_updater = LightUpdater::new(block_registry, world);
+    let mut loaded_chunks = Vec::new();
+    for (pos, chunk, light) in chunks {
+        world.add_chunk(pos, chunk, light);
+        light_updater.on_chunk_loaded(pos);
+        loaded_chunks.push(pos);
+    }
+
+    // Освещенность обновляется до отправки событий, поэтому меши строятся уже с готовым светом
+    let mut light_updated_chunks = light_updater.into_changed_chunks();
+    for pos in loaded_chunks {
+        light_updated_chunks.remove(&pos);
+        chunk_event_writer.send(ChunkUpdateEvent::Loaded(pos));
+    }
+    for pos in light_updated_chunks {
+        chunk_event_writer.send(ChunkUpdateEvent::LightUpdated(pos));
+    }
+}
+
+/// Пересчитывает освещение для блоков измененных через [World::set_block] и отправляет события об изменениях
+fn apply_block_changes(
+    world: Res<World>,
+    block_registry: Res<SharedBlockRegistry>,
+    mut chunk_event_writer: EventWriter<ChunkUpdateEvent>,
+) {
+    let changed_blocks = world.take_changed_blocks();
+    if changed_blocks.is_empty() {
+        return;
+    }
+
+    let mut light_updater = LightUpdater::new(&block_registry, &world);
+    for pos in changed_blocks.iter() {
+        light_updater.on_block_changed(**pos);
+    }
+    for pos in changed_blocks {
+        chunk_event_writer.send(ChunkUpdateEvent::BlockChanged(pos));
+    }
+    for pos in light_updater.into_changed_chunks() {
+        chunk_event_writer.send(ChunkUpdateEvent::LightUpdated(pos));
+    }
+}
+
+/// Периодически отправляет на сохранение все измененные загруженные чанки
+fn autosave_dirty_chunks(
+    time: Res<Time>,
+    world: Res<World>,
+    mut autosave_timer: ResMut<AutosaveTimer>,
+    mut chunk_saving_queue: ResMut<ChunkSavingQueue>,
+) {
+    if !autosave_timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    let dirty_chunks = world.take_dirty_chunks();
+    info!("Autosave {} chunks", dirty_chunks.len());
+    for pos in dirty_chunks {
+        if let Some(chunk) = world.get_chunk(&pos) {
+            chunk_saving_queue.insert(pos, chunk);
+        }
+    }
+}
+
+/// Запускает сохранение чанков из очереди [ChunkSavingQueue]
+fn save_chunks_from_queue(
+    world_storage: Res<WorldStorage>,
+    mut chunk_saving_queue: ResMut<ChunkSavingQueue>,
+    mut chunk_saving_tasks: ResMut<ChunkSavingTasks>,
+) {
+    let pool = AsyncComputeTaskPool::get();
+    chunk_saving_queue.retain(|pos, chunk| {
+        if chunk_saving_tasks.contains_key(pos) {
+            return true;
+        }
+
+        let pos = *pos;
+        let chunk = Arc::clone(chunk);
+        let world_storage = world_storage.clone();
+        let task = pool.spawn(async move {
+            let chunk = chunk.read().unwrap();
+            world_storage.write_chunk(pos, &chunk)
+        });
+        chunk_saving_tasks.insert(pos, task);
+        false
+    });
+}
+
+/// Завершает задачи сохранения чанков
+fn collect_saved_chunks(
+    mut chunk_saving_tasks: ResMut<ChunkSavingTasks>,
+) {
+    chunk_saving_tasks.retain(|pos, task| {
+        match block_on(poll_once(task)) {
+            None => { true }
+            Some(result) => {
+                if let Err(err) = result {
+                    error!("Can't save chunk {:?}: {}", pos, err);
+                }
                 false
             }
         }
-    })
+    });
+}
+
+/// При выходе из приложения синхронно сохраняет все измененные чанки
+fn flush_world_on_exit(
+    world: Res<World>,
+    world_storage: Res<WorldStorage>,
+    mut chunk_saving_queue: ResMut<ChunkSavingQueue>,
+    mut chunk_saving_tasks: ResMut<ChunkSavingTasks>,
+    mut app_exit_events: EventReader<AppExit>,
+) {
+    if app_exit_events.is_empty() {
+        return;
+    }
+    app_exit_events.clear();
+
+    // Сначала дожидаемся уже запущенных сохранений, чтобы они не перезаписали более новые данные
+    for (pos, task) in chunk_saving_tasks.drain() {
+        if let Err(err) = block_on(task) {
+            error!("Can't save chunk {:?}: {}", pos, err);
+        }
+    }
+
+    let mut chunks: HashMap<ChunkPos, Arc<RwLock<Chunk>>> = chunk_saving_queue.drain().collect();
+    for pos in world.take_dirty_chunks() {
+        if let Some(chunk) = world.get_chunk(&pos) {
+            chunks.insert(pos, chunk);
+        }
+    }
+
+    info!("Saving {} chunks before exit", chunks.len());
+    for (pos, chunk) in chunks {
+        if let Err(err) = world_storage.write_chunk(pos, &chunk.read().unwrap()) {
+            error!("Can't save chunk {:?}: {}", pos, err);
+        }
+    }
+    if let Err(err) = world_storage.sync() {
+        error!("Can't sync world storage: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::time::{Duration, Instant};
+    use bevy::app::AppExit;
+    use bevy::math::{ivec3, vec3};
+    use bevy::prelude::*;
+    use bevy::tasks::AsyncComputeTaskPool;
+    use chunk::ChunkPos;
+    use world_anchor::{WorldAnchor, WorldAnchorPlugin};
+    use crate::logic::block::{Block, BlockDefinition, BlockId, BlockRegistry, SharedBlockRegistry};
+    use crate::logic::chunk::Chunk;
+    use crate::logic::world::{ChunkLoadingSettings, TicketKind, World, WorldPlugin, WorldStorageSettings};
+    use crate::logic::world::world_plugin::{AutosaveTimer, ChunkLoadingTasks, ChunkSavingQueue, ChunkSavingTasks};
+    use crate::logic::world::world_plugin::{is_view_moved, view_factor};
+
+    /// Приложение с пустым миром, считает сгенерированные чанки
+    fn app(world_dir: &Path, unload_delay: Duration, generated: Arc<AtomicUsize>) -> App {
+        gated_app(world_dir, unload_delay, generated, Arc::new(AtomicBool::new(true)))
+    }
+
+    /// Приложение с пустым миром, генерация чанков ждет пока `gate` не станет true
+    fn gated_app(world_dir: &Path, unload_delay: Duration, generated: Arc<AtomicUsize>, gate: Arc<AtomicBool>) -> App {
+        let stone = BlockDefinition { id: 1, name: "stone".to_string(), ..default() };
+        let mut app = App::new();
+        app
+            .add_plugins(MinimalPlugins)
+            .insert_resource(SharedBlockRegistry::new(BlockRegistry::new([stone]).unwrap()))
+            .insert_resource(WorldStorageSettings { world_dir: world_dir.to_path_buf(), ..default() })
+            .insert_resource(ChunkLoadingSettings { unload_margin: 1, unload_delay, ..default() })
+            .add_plugins(WorldAnchorPlugin)
+            .add_plugins(WorldPlugin::new(move |_pos: ChunkPos| {
+                while !gate.load(Ordering::SeqCst) {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+                generated.fetch_add(1, Ordering::SeqCst);
+                Chunk::new(())
+            }));
+        app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(8., 8., 8.)),
+            WorldAnchor { load_radius: 2, vertical_load_radius: 1, ..default() },
+        ));
+        app
+    }
+
+    fn move_anchor(app: &mut App, chunk_x: i32) {
+        let mut transforms = app.world.query_filtered::<&mut Transform, With<WorldAnchor>>();
+        transforms.single_mut(&mut app.world).translation.x = chunk_x as f32 * 16. + 8.;
+    }
+
+    fn is_loaded(app: &App, x: i32) -> bool {
+        app.world.resource::<World>().is_chunk_loaded(&ivec3(x, 0, 0).into())
+    }
+
+    /// Загружены ли все чанки области якоря по умолчанию в срезе `x`
+    fn is_slice_loaded(app: &App, x: i32) -> bool {
+        (-1..=1).all(|y| app.world.resource::<World>().is_chunk_loaded(&ivec3(x, y, 0).into()))
+    }
+
+    fn has_loading_tasks(app: &App) -> bool {
+        !app.world.resource::<ChunkLoadingTasks>().is_empty()
+    }
+
+    fn has_saving_tasks(app: &App) -> bool {
+        !app.world.resource::<ChunkSavingQueue>().is_empty() || !app.world.resource::<ChunkSavingTasks>().is_empty()
+    }
+
+    /// Блок в чанке (0, 0, 0) который изменяется и должен пережить перезапуск
+    fn edited_block(app: &App) -> Option<Option<Block>> {
+        app.world.resource::<World>().get_block(ivec3(5, 6, 7).into())
+    }
+
+    fn update_until(app: &mut App, condition: impl Fn(&App) -> bool) {
+        let start = Instant::now();
+        while !condition(app) {
+            assert!(start.elapsed() < Duration::from_secs(30), "Condition is not reached in time");
+            app.update();
+            std::thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    #[test]
+    fn chunks_near_border_are_kept_and_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let generated = Arc::new(AtomicUsize::new(0));
+        let mut app = app(dir.path(), Duration::from_secs(3600), Arc::clone(&generated));
+        // Каждый шаг ждет загрузки всей области, иначе задачи еще не загруженных чанков отменяются следующим шагом
+        update_until(&mut app, |app| (-1..=1).all(|x| is_slice_loaded(app, x)));
+
+        // Чанки вышедшие из области загрузки не дальше запаса остаются загруженными
+        move_anchor(&mut app, 1);
+        update_until(&mut app, |app| is_slice_loaded(app, 2));
+        assert!(is_slice_loaded(&app, -1));
+
+        // Чанки дальше запаса выгружаются сразу
+        move_anchor(&mut app, 3);
+        update_until(&mut app, |app| (2..=4).all(|x| is_slice_loaded(app, x)) && !is_loaded(app, -1));
+        assert!(!is_loaded(&app, 0));
+        assert!(is_slice_loaded(&app, 1));
+        assert!(!has_loading_tasks(&app));
+        assert_eq!(generated.load(Ordering::SeqCst), 18);
+
+        // Недавно выгруженные чанки возвращаются из кеша без генерации
+        move_anchor(&mut app, 0);
+        update_until(&mut app, |app| (-1..=1).all(|x| is_slice_loaded(app, x)) && !has_loading_tasks(app));
+        assert_eq!(generated.load(Ordering::SeqCst), 18);
+    }
+
+    #[test]
+    fn chunks_in_margin_are_unloaded_after_delay() {
+        let dir = tempfile::tempdir().unwrap();
+        let delay = Duration::from_millis(200);
+        let mut app = app(dir.path(), delay, Arc::new(AtomicUsize::new(0)));
+        update_until(&mut app, |app| (-1..=1).all(|x| is_loaded(app, x)));
+
+        move_anchor(&mut app, 1);
+        let moved = Instant::now();
+        update_until(&mut app, |app| is_loaded(app, 2));
+        update_until(&mut app, |app| !is_loaded(app, -1));
+        assert!(moved.elapsed() >= delay);
+        assert!(is_loaded(&app, 0));
+    }
+
+    #[test]
+    fn tickets_load_chunks_without_anchor() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut app = app(dir.path(), Duration::ZERO, Arc::new(AtomicUsize::new(0)));
+        let world = app.world.resource::<World>();
+        let render = world.add_ticket(ivec3(10, 0, 0).into(), 2, TicketKind::Render, None);
+        let load = world.add_ticket(ivec3(12, 0, 0).into(), 2, TicketKind::Load, None);
+        world.add_ticket(ivec3(30, 0, 0).into(), 1, TicketKind::Tick, None);
+        update_until(&mut app, |app| (-1..=1).chain(9..=13).chain(30..=30).all(|x| is_loaded(app, x)));
+
+        // Уровень чанка равен наибольшему уровню областей в которые он входит
+        let level = |app: &App, x: i32| app.world.resource::<World>().chunk_level(&ivec3(x, 0, 0).into());
+        assert_eq!(level(&app, 0), Some(TicketKind::Render));
+        assert_eq!(level(&app, 11), Some(TicketKind::Render));
+        assert_eq!(level(&app, 13), Some(TicketKind::Load));
+        assert!(!is_loaded(&app, 5));
+
+        // Чанки тикета Tick обновляются, но не отображаются
+        assert_eq!(level(&app, 30), Some(TicketKind::Tick));
+        let ticking = app.world.resource::<World>().ticking_chunks();
+        assert!(ticking.contains(&ivec3(30, 0, 0).into()));
+        assert!(ticking.contains(&ivec3(0, 0, 0).into()));
+        assert!(!ticking.contains(&ivec3(13, 0, 0).into()));
+
+        // Чанки удаленного тикета выгружаются, пересекающиеся с другим тикетом понижают уровень
+        assert!(app.world.resource::<World>().remove_ticket(render));
+        update_until(&mut app, |app| !is_loaded(app, 9) && level(app, 11) == Some(TicketKind::Load));
+        assert!(is_loaded(&app, 11));
+
+        // Чанки истекшего тикета выгружаются
+        let duration = Some(Duration::from_secs(2));
+        app.world.resource::<World>().add_ticket(ivec3(20, 0, 0).into(), 1, TicketKind::Load, duration);
+        update_until(&mut app, |app| is_loaded(app, 20));
+        update_until(&mut app, |app| !is_loaded(app, 20));
+        assert_eq!(level(&app, 20), None);
+
+        assert!(app.world.resource::<World>().remove_ticket(load));
+        update_until(&mut app, |app| !is_loaded(app, 12));
+    }
+
+    #[test]
+    fn block_edits_are_saved_on_exit() {
+        let dir = tempfile::tempdir().unwrap();
+        let stone = Some(Block::new(BlockId(1)));
+        {
+            let mut app = app(dir.path(), Duration::ZERO, Arc::new(AtomicUsize::new(0)));
+            update_until(&mut app, |app| (-1..=1).all(|x| is_slice_loaded(app, x)));
+            assert!(app.world.resource::<World>().set_block(ivec3(5, 6, 7).into(), stone));
+            app.update();
+
+            app.world.send_event(AppExit);
+            app.update();
+            assert!(!has_saving_tasks(&app));
+        }
+
+        // Измененный чанк читается с диска, остальные генерируются заново
+        let generated = Arc::new(AtomicUsize::new(0));
+        let mut app = app(dir.path(), Duration::ZERO, Arc::clone(&generated));
+        update_until(&mut app, |app| (-1..=1).all(|x| is_slice_loaded(app, x)) && !has_loading_tasks(app));
+        assert_eq!(edited_block(&app), Some(stone));
+        assert_eq!(generated.load(Ordering::SeqCst), 8);
+    }
+
+    #[test]
+    fn block_edits_are_autosaved() {
+        let dir = tempfile::tempdir().unwrap();
+        let stone = Some(Block::new(BlockId(1)));
+        {
+            let mut app = app(dir.path(), Duration::ZERO, Arc::new(AtomicUsize::new(0)));
+            let interval = Duration::from_millis(100);
+            app.insert_resource(AutosaveTimer(Timer::new(interval, TimerMode::Repeating)));
+            update_until(&mut app, |app| (-1..=1).all(|x| is_slice_loaded(app, x)));
+            assert!(app.world.resource::<World>().set_block(ivec3(5, 6, 7).into(), stone));
+
+            // Приложение закрывается без AppExit, поэтому изменения сохраняет только автосохранение
+            let edited = Instant::now();
+            update_until(&mut app, |app| edited.elapsed() > interval * 3 && !has_saving_tasks(app));
+        }
+
+        let mut app = app(dir.path(), Duration::ZERO, Arc::new(AtomicUsize::new(0)));
+        update_until(&mut app, |app| is_loaded(app, 0));
+        assert_eq!(edited_block(&app), Some(stone));
+    }
+
+    #[test]
+    fn tasks_out_of_load_areas_are_cancelled() {
+        let dir = tempfile::tempdir().unwrap();
+        let generated = Arc::new(AtomicUsize::new(0));
+        let gate = Arc::new(AtomicBool::new(false));
+        let mut app = gated_app(dir.path(), Duration::ZERO, Arc::clone(&generated), Arc::clone(&gate));
+        let mut anchors = app.world.query::<&mut WorldAnchor>();
+        anchors.single_mut(&mut app.world).load_radius = 8;
+        update_until(&mut app, has_loading_tasks);
+
+        // Пока генерация закрыта, задачи старой области отменяются при перемещении якоря
+        move_anchor(&mut app, 100);
+        update_until(&mut app, |app| {
+            has_loading_tasks(app) && app.world.resource::<ChunkLoadingTasks>().keys().all(|pos| pos.x > 50)
+        });
+        gate.store(true, Ordering::SeqCst);
+        update_until(&mut app, |app| (93..=107).all(|x| is_loaded(app, x)) && !has_loading_tasks(app));
+        assert!((-7..=7).all(|x| !is_loaded(&app, x)));
+
+        // Из старой области успевают сгенерироваться только чанки задач уже занявших потоки до отмены
+        let threads = AsyncComputeTaskPool::get().thread_num();
+        assert!(generated.load(Ordering::SeqCst) <= 15 * 15 + threads);
+    }
+
+    #[test]
+    fn chunks_in_view_have_higher_priority() {
+        let cameras = [(vec3(8., 8., 8.), vec3(1., 0., 0.))];
+        let front = view_factor(ivec3(3, 0, 0).into(), &cameras, 1.);
+        let side = view_factor(ivec3(0, 3, 0).into(), &cameras, 1.);
+        let behind = view_factor(ivec3(-3, 0, 0).into(), &cameras, 1.);
+        assert_eq!(front, 1.);
+        assert_eq!(side, 1.5);
+        assert_eq!(behind, 2.);
+
+        // Без камер направление не влияет на приоритет, из нескольких камер берется лучшая
+        assert_eq!(view_factor(ivec3(-3, 0, 0).into(), &[], 1.), 2.);
+        assert_eq!(view_factor(ivec3(3, 0, 0).into(), &[], 1.), 2.);
+        let cameras = [cameras[0], (vec3(8., 8., 8.), vec3(-1., 0., 0.))];
+        assert_eq!(view_factor(ivec3(-3, 0, 0).into(), &cameras, 1.), 1.);
+    }
+
+    #[test]
+    fn queue_is_resorted_only_after_noticeable_camera_move() {
+        let camera = (vec3(8., 8., 8.), vec3(1., 0., 0.));
+        let moved = |offset: Vec3, forward: Vec3| is_view_moved(&[camera], &[(camera.0 + offset, forward)], 8., 0.2);
+        assert!(!moved(vec3(1., 2., 0.), camera.1));
+        assert!(!moved(Vec3::ZERO, vec3(1., 0.1, 0.).normalize()));
+        assert!(moved(vec3(9., 0., 0.), camera.1));
+        assert!(moved(Vec3::ZERO, vec3(1., 1., 0.).normalize()));
+        assert!(is_view_moved(&[], &[camera], 8., 0.2));
+    }
 }
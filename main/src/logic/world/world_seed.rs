@@ -0,0 +1,107 @@
+use std::str::FromStr;
+use bevy::prelude::Resource;
+use strum_macros::EnumIter;
+
+/// Seed мира.
+///
+/// Каждый этап генерации (высоты, пещеры, руды, декорации и тд) получает собственный seed через [WorldSeed::derive],
+/// поэтому этапы не зависят друг от друга, а результат генерации зависит только от seed мира и позиции
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WorldSeed(pub u64);
+
+/// Этапы генерации мира, seed этапа зависит только от seed мира и имени этапа. Этапы добавляются заранее, а их имена
+/// не меняются, иначе изменятся уже созданные миры
+#[derive(EnumIter, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GenerationStage {
+    Height,
+    Caves,
+    Ores,
+    Decorations,
+}
+
+impl GenerationStage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GenerationStage::Height => { "height" }
+            GenerationStage::Caves => { "caves" }
+            GenerationStage::Ores => { "ores" }
+            GenerationStage::Decorations => { "decorations" }
+        }
+    }
+}
+
+impl WorldSeed {
+    /// Возвращает seed для этапа генерации
+    pub fn derive(&self, stage: GenerationStage) -> u64 {
+        split_mix64(self.0 ^ split_mix64(fnv1a(stage.name().as_bytes())))
+    }
+
+    /// Возвращает seed для этапа генерации обрезанный до 32 бит, для шумов из крейта noise
+    pub fn derive_u32(&self, stage: GenerationStage) -> u32 {
+        let seed = self.derive(stage);
+        (seed ^ (seed >> 32)) as u32
+    }
+}
+
+/// Числа используются как есть, любая другая строка хешируется
+impl FromStr for WorldSeed {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Err(());
+        }
+        if let Ok(seed) = s.parse::<u64>() {
+            return Ok(WorldSeed(seed));
+        }
+        if let Ok(seed) = s.parse::<i64>() {
+            return Ok(WorldSeed(seed as u64));
+        }
+        Ok(WorldSeed(fnv1a(s.as_bytes())))
+    }
+}
+
+/// Стабильный между запусками и платформами хеш, в отличие от [std::hash::Hasher]
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325u64;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Перемешивание бит из генератора SplitMix64
+fn split_mix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use strum::IntoEnumIterator;
+    use crate::logic::world::{GenerationStage, WorldSeed};
+
+    #[test]
+    fn derive_is_stable() {
+        let seed = WorldSeed(42);
+        assert_eq!(seed.derive(GenerationStage::Height), WorldSeed(42).derive(GenerationStage::Height));
+        assert_ne!(seed.derive(GenerationStage::Height), WorldSeed(43).derive(GenerationStage::Height));
+
+        // Seed этапов не должны меняться между версиями игры
+        let seeds: Vec<u64> = GenerationStage::iter().map(|stage| seed.derive(stage)).collect();
+        assert_eq!(seeds, vec![2282844022510830080, 2510951653311378347, 1630442002499340923, 7766802389742328299]);
+    }
+
+    #[test]
+    fn parse_seed() {
+        assert_eq!("123".parse::<WorldSeed>(), Ok(WorldSeed(123)));
+        assert_eq!("-1".parse::<WorldSeed>(), Ok(WorldSeed(u64::MAX)));
+        assert_eq!("hello".parse::<WorldSeed>(), "hello".parse::<WorldSeed>());
+        assert_ne!("hello".parse::<WorldSeed>(), "world".parse::<WorldSeed>());
+        assert!("".parse::<WorldSeed>().is_err());
+    }
+}
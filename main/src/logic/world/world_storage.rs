@@ -0,0 +1,99 @@
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use bevy::prelude::{FromWorld, Resource};
+use chunk::{ChunkPos, RegionStorage};
+use crate::logic::chunk::Chunk;
+
+/// Настройки сохранения мира на диск
+#[derive(Resource)]
+pub struct WorldStorageSettings {
+    /// Директория в которой хранятся файлы регионов мира
+    pub world_dir: PathBuf,
+
+    /// Интервал автосохранения измененных чанков
+    pub autosave_interval: Duration,
+}
+
+impl Default for WorldStorageSettings {
+    fn default() -> Self {
+        Self {
+            world_dir: PathBuf::from("world"),
+            autosave_interval: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Хранилище чанков мира на диске.
+///
+/// Может свободно клонироваться для передачи в асинхронные задачи, все клоны работают с одним [RegionStorage]
+#[derive(Resource, Clone)]
+pub struct WorldStorage {
+    storage: Arc<Mutex<RegionStorage>>,
+}
+
+impl WorldStorage {
+    pub fn open(world_dir: PathBuf) -> io::Result<Self> {
+        Ok(Self {
+            storage: Arc::new(Mutex::new(RegionStorage::open(world_dir)?)),
+        })
+    }
+
+    /// Читает сохраненный чанк, если чанк ни разу не сохранялся возвращает [None]
+    pub fn read_chunk(&self, pos: ChunkPos) -> io::Result<Option<Chunk>> {
+        self.storage.lock().unwrap().read_chunk(pos)
+    }
+
+    /// Сохраняет чанк
+    pub fn write_chunk(&self, pos: ChunkPos, chunk: &Chunk) -> io::Result<()> {
+        self.storage.lock().unwrap().write_chunk(pos, chunk)
+    }
+
+    /// Сбрасывает все записанные данные на диск
+    pub fn sync(&self) -> io::Result<()> {
+        self.storage.lock().unwrap().sync()
+    }
+}
+
+impl FromWorld for WorldStorage {
+    fn from_world(world: &mut bevy::prelude::World) -> Self {
+        let world_dir = world.resource::<WorldStorageSettings>().world_dir.clone();
+        WorldStorage::open(world_dir).expect("Can't open world storage")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{ivec3, uvec3};
+    use crate::logic::block::{Block, BlockId};
+    use crate::logic::chunk::Chunk;
+    use crate::logic::world::world_storage::WorldStorage;
+
+    #[test]
+    fn chunks_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let stone = Some(Block::new(BlockId(1)));
+        let grass = Some(Block::new(BlockId(2)));
+        {
+            let storage = WorldStorage::open(dir.path().to_path_buf()).unwrap();
+            let mut chunk = Chunk::new(());
+            chunk.set(&uvec3(1, 2, 3).try_into().unwrap(), stone);
+            storage.write_chunk(ivec3(0, 0, 0).into(), &chunk).unwrap();
+            chunk.set(&uvec3(15, 15, 15).try_into().unwrap(), grass);
+            storage.write_chunk(ivec3(-40, 3, 70).into(), &chunk).unwrap();
+            storage.sync().unwrap();
+        }
+        // Чанки из разных регионов лежат в разных файлах
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 2);
+
+        let storage = WorldStorage::open(dir.path().to_path_buf()).unwrap();
+        let chunk = storage.read_chunk(ivec3(0, 0, 0).into()).unwrap().unwrap();
+        assert_eq!(chunk[&uvec3(1, 2, 3).try_into().unwrap()], stone);
+        assert_eq!(chunk[&uvec3(15, 15, 15).try_into().unwrap()], None);
+        let chunk = storage.read_chunk(ivec3(-40, 3, 70).into()).unwrap().unwrap();
+        assert_eq!(chunk[&uvec3(1, 2, 3).try_into().unwrap()], stone);
+        assert_eq!(chunk[&uvec3(15, 15, 15).try_into().unwrap()], grass);
+        assert!(storage.read_chunk(ivec3(1, 0, 0).into()).unwrap().is_none());
+    }
+}
@@ -1,21 +1,42 @@
 mod camera;
+mod block_interaction;
 mod key_binding;
 mod render;
 mod logic;
+mod launch_config;
+mod headless;
+mod net;
 
+use std::time::Duration;
+use bevy::app::ScheduleRunnerPlugin;
 use bevy::diagnostic::{EntityCountDiagnosticsPlugin, FrameTimeDiagnosticsPlugin};
+use bevy::log::LogPlugin;
 use bevy::math::vec3;
 use bevy::prelude::*;
 use world_anchor::WorldAnchorPlugin;
+use crate::block_interaction::BlockInteractionPlugin;
 use crate::camera::CameraPlugin;
+use crate::headless::HeadlessPlugin;
 use crate::key_binding::KeyBindingsPlugin;
 use crate::render::{ChunkRenderPlugin, WorldMaterialPlugin};
-use crate::logic::world::WorldPlugin;
+use crate::launch_config::LaunchConfig;
+use crate::logic::block::BlockRegistryPlugin;
+use crate::net::{ClientPlugin, ServerPlugin};
 use crate::render::debug::DebugInfoRenderPlugin;
 
 
 fn main() {
-    App::new()
+    let config = LaunchConfig::load();
+    if config.headless {
+        run_headless(config);
+    } else {
+        run_client(config);
+    }
+}
+
+fn run_client(config: LaunchConfig) {
+    let mut app = App::new();
+    app
         // Default bevy plugins setup
         .add_plugins(DefaultPlugins)
 
@@ -23,19 +44,67 @@ fn main() {
         .add_plugins(FrameTimeDiagnosticsPlugin)
         .add_plugins(EntityCountDiagnosticsPlugin)
 
+        // Custom project resources setup
+        .insert_resource(config.meshing)
+        .insert_resource(config.mesh_upload_budget);
+
+    match config.connect {
+        // Мир приходит с сервера, локально чанки не генерируются и не сохраняются
+        Some(addr) => {
+            app
+                .add_plugins(WorldAnchorPlugin)
+                .add_plugins(BlockRegistryPlugin::default())
+                .add_plugins(ClientPlugin { addr });
+        }
+        None => { add_world_plugins(&mut app, &config) }
+    }
+
+    app
         // Custom project plugins setup
         .add_plugins(KeyBindingsPlugin)
         .add_plugins(CameraPlugin)
-        .add_plugins(WorldAnchorPlugin)
         .add_plugins(WorldMaterialPlugin)
-        .add_plugins(WorldPlugin)
         .add_plugins(ChunkRenderPlugin)
+        .add_plugins(BlockInteractionPlugin)
         .add_plugins(DebugInfoRenderPlugin)
 
         .add_systems(Startup, setup)
         .run();
 }
 
+/// Запуск симуляции мира без окна и рендера, например для сервера или нагрузочных тестов
+fn run_headless(config: LaunchConfig) {
+    let mut app = App::new();
+    app
+        .add_plugins(MinimalPlugins.set(ScheduleRunnerPlugin::run_loop(Duration::from_secs_f64(1. / config.tick_rate))))
+        .add_plugins(LogPlugin::default())
+        .add_plugins(HeadlessPlugin::from_config(&config));
+
+    add_world_plugins(&mut app, &config);
+    if let Some(addr) = config.server {
+        app.add_plugins(ServerPlugin { addr });
+    }
+    app.run();
+}
+
+/// Плагины логики мира, общие для обоих режимов запуска
+fn add_world_plugins(app: &mut App, config: &LaunchConfig) {
+    let metadata = match config.world_metadata() {
+        Ok(metadata) => { metadata }
+        Err(err) => {
+            eprintln!("Can't open world: {}", err);
+            std::process::exit(1);
+        }
+    };
+    app
+        .insert_resource(metadata.seed)
+        .insert_resource(config.world_storage_settings())
+        .insert_resource(config.chunk_integration_budget)
+        .add_plugins(WorldAnchorPlugin)
+        .add_plugins(BlockRegistryPlugin::default())
+        .add_plugins(metadata.world_plugin());
+}
+
 // TODO удалить
 fn setup(
     mut commands: Commands,
@@ -0,0 +1,186 @@
+use std::net::SocketAddr;
+use bevy::prelude::*;
+use bevy::utils::HashSet;
+use chunk::AbsoluteBlockPos;
+use world_anchor::{WorldAnchor, WorldAnchorInChunkPos};
+use crate::logic::block::SharedBlockRegistry;
+use crate::logic::light::{compute_chunk_light, LightUpdater};
+use crate::logic::world::{add_loaded_chunks, ChunkUpdateEvent, TicketKind, World, WorldCorePlugin};
+use crate::net::connection::Connection;
+use crate::net::protocol::{AnchorShape, ClientMessage, decode_chunk, PROTOCOL_VERSION, ServerMessage};
+
+/// Плагин клиента: подключается к серверу, получает от него чанки вокруг [WorldAnchor] и отправляет ему изменения
+/// блоков сделанные через [World::set_block].
+///
+/// Используется вместо [crate::logic::world::WorldPlugin], чанки не генерируются и не сохраняются локально.
+/// Изменения блоков применяются сразу, если сервер их отклонит он пришлет настоящее значение блока. Требует
+/// [SharedBlockRegistry] и [world_anchor::WorldAnchorPlugin]
+pub struct ClientPlugin {
+    pub addr: SocketAddr,
+}
+
+impl Plugin for ClientPlugin {
+    fn build(&self, app: &mut App) {
+        let mut connection = match Connection::connect(self.addr) {
+            Ok(connection) => { connection }
+            Err(err) => { panic!("Can't connect to server {}: {}", self.addr, err) }
+        };
+        connection.send(&ClientMessage::Hello { version: PROTOCOL_VERSION });
+        info!("Connected to server {}", self.addr);
+
+        app
+            .add_plugins(WorldCorePlugin)
+            .insert_resource(ServerConnection { connection: Some(connection), remote_changes: HashSet::new() })
+            .add_systems(Update, (send_anchor_position, receive_server_messages).chain())
+            .add_systems(Last, (send_local_block_changes, flush_server_connection).chain())
+        ;
+    }
+}
+
+/// Соединение с сервером
+#[derive(Resource)]
+struct ServerConnection {
+    /// [None] после разрыва соединения
+    connection: Option<Connection>,
+
+    /// Блоки измененные сервером в этом кадре, эти изменения не нужно отправлять обратно
+    remote_changes: HashSet<AbsoluteBlockPos>,
+}
+
+type AnchorChanged = Or<(Changed<WorldAnchorInChunkPos>, Changed<WorldAnchor>)>;
+
+/// Отправляет серверу позицию якоря при переходе якоря в другой чанк или изменении его радиуса
+fn send_anchor_position(
+    mut server: ResMut<ServerConnection>,
+    anchors: Query<(&Transform, &WorldAnchor), AnchorChanged>,
+) {
+    let Some(connection) = &mut server.connection else {
+        return;
+    };
+    // Сервер поддерживает один якорь на клиента
+    if let Some((transform, anchor)) = anchors.iter().next() {
+        connection.send(&ClientMessage::Anchor {
+            pos: transform.translation,
+            load_radius: anchor.load_radius,
+            vertical_load_radius: anchor.vertical_load_radius,
+            shape: AnchorShape::from_load_shape(&anchor.shape),
+        });
+    }
+}
+
+fn receive_server_messages(
+    world: Res<World>,
+    block_registry: Res<SharedBlockRegistry>,
+    mut server: ResMut<ServerConnection>,
+    mut chunk_event_writer: EventWriter<ChunkUpdateEvent>,
+) {
+    let ServerConnection { connection, remote_changes } = &mut *server;
+    let Some(active_connection) = connection else {
+        return;
+    };
+    let messages = match active_connection.receive::<ServerMessage>() {
+        Ok(messages) => { messages }
+        Err(err) => {
+            error!("Connection to server lost: {}", err);
+            *connection = None;
+            return;
+        }
+    };
+
+    let mut loaded_chunks = Vec::new();
+    let mut unloaded_chunks = Vec::new();
+    for message in messages {
+        match message {
+            ServerMessage::Welcome { version } => { info!("Server accepted connection, protocol version {}", version) }
+            ServerMessage::Disconnect { reason } => {
+                error!("Disconnected by server: {}", reason);
+                *connection = None;
+                break;
+            }
+            ServerMessage::ChunkData { pos, data, sky_exposure } => {
+                let chunk = match decode_chunk(&data) {
+                    Ok(chunk) => { chunk }
+                    Err(err) => {
+                        error!("Can't decode chunk {:?}: {}", pos, err);
+                        continue;
+                    }
+                };
+                // Чанк мог быть отправлен повторно после выгрузки, старую версию заменяем
+                if world.is_chunk_loaded(&pos) {
+                    world.remove_chunk(&pos);
+                    chunk_event_writer.send(ChunkUpdateEvent::Unloaded(pos));
+                }
+                loaded_chunks.retain(|(loaded_pos, _, _)| *loaded_pos != pos);
+                // Сервер присылает только чанки вокруг якорей клиента, поэтому все они отображаются
+                world.set_chunk_level(pos, Some(TicketKind::Render));
+                let light = compute_chunk_light(&block_registry, &chunk, pos, sky_exposure);
+                loaded_chunks.push((pos, chunk, light));
+            }
+            ServerMessage::UnloadChunk { pos } => {
+                loaded_chunks.retain(|(loaded_pos, _, _)| *loaded_pos != pos);
+                world.set_chunk_level(pos, None);
+                if world.is_chunk_loaded(&pos) {
+                    world.remove_chunk(&pos);
+                    chunk_event_writer.send(ChunkUpdateEvent::Unloaded(pos));
+                    unloaded_chunks.push(pos);
+                }
+            }
+            ServerMessage::BlockChanged { pos, block } => {
+                // Изменение может относиться к чанку из этой же пачки сообщений, поэтому сначала добавляем чанки
+                if !loaded_chunks.is_empty() {
+                    add_loaded_chunks(&world, &block_registry, loaded_chunks.drain(..), &mut chunk_event_writer);
+                }
+                if world.set_block(pos, block) {
+                    remote_changes.insert(pos);
+                }
+            }
+        }
+    }
+    add_loaded_chunks(&world, &block_registry, loaded_chunks, &mut chunk_event_writer);
+
+    // Чанки под выгруженными снова освещаются открытым небом
+    if !unloaded_chunks.is_empty() {
+        let mut light_updater = LightUpdater::new(&block_registry, &world);
+        for pos in unloaded_chunks {
+            light_updater.on_chunk_unloaded(pos);
+        }
+        for pos in light_updater.into_changed_chunks() {
+            chunk_event_writer.send(ChunkUpdateEvent::LightUpdated(pos));
+        }
+    }
+}
+
+/// Отправляет серверу изменения блоков сделанные на клиенте.
+/// Измененные чанки сохраняет сервер, поэтому пометки об изменении на клиенте снимаются
+fn send_local_block_changes(
+    world: Res<World>,
+    mut server: ResMut<ServerConnection>,
+    mut chunk_events: EventReader<ChunkUpdateEvent>,
+) {
+    let ServerConnection { connection, remote_changes } = &mut *server;
+    for event in chunk_events.iter() {
+        let ChunkUpdateEvent::BlockChanged(pos) = event else {
+            continue;
+        };
+        if remote_changes.contains(pos) {
+            continue;
+        }
+        if let (Some(connection), Some(block)) = (connection.as_mut(), world.get_block(*pos)) {
+            connection.send(&ClientMessage::SetBlock { pos: *pos, block });
+        }
+    }
+    remote_changes.clear();
+    world.take_dirty_chunks();
+}
+
+fn flush_server_connection(
+    mut server: ResMut<ServerConnection>,
+) {
+    let Some(connection) = &mut server.connection else {
+        return;
+    };
+    if let Err(err) = connection.flush() {
+        error!("Connection to server lost: {}", err);
+        server.connection = None;
+    }
+}
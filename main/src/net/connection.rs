@@ -0,0 +1,117 @@
+use std::io;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use chunk::BinaryCodec;
+
+/// Максимальный размер одного сообщения, соединение с сообщением большего размера закрывается
+const MAX_MESSAGE_SIZE: usize = 16 * 1024 * 1024;
+
+/// Размер буфера отправки по умолчанию, см [Connection::set_max_outgoing]
+const MAX_OUTGOING_SIZE: usize = 4 * MAX_MESSAGE_SIZE;
+
+/// Неблокирующее TCP соединение обменивающееся сообщениями.
+///
+/// Каждое сообщение передается как длина (u32) и данные сообщения в формате [BinaryCodec]. Отправленные сообщения
+/// накапливаются в буфере и записываются в сокет в [Connection::flush]. Если другая сторона не читает сообщения и
+/// буфер отправки превышает допустимый размер, [Connection::flush] возвращает ошибку и соединение нужно закрыть
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    max_outgoing: usize,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream, incoming: Vec::new(), outgoing: Vec::new(), max_outgoing: MAX_OUTGOING_SIZE })
+    }
+
+    pub fn connect(addr: SocketAddr) -> io::Result<Self> {
+        Self::new(TcpStream::connect(addr)?)
+    }
+
+    /// Устанавливает максимальный размер буфера отправки в байтах
+    pub fn set_max_outgoing(&mut self, max_outgoing: usize) {
+        self.max_outgoing = max_outgoing;
+    }
+
+    /// Размер еще не записанных в сокет данных в байтах
+    pub fn outgoing_len(&self) -> usize {
+        self.outgoing.len()
+    }
+
+    /// Добавляет сообщение в буфер отправки
+    pub fn send(&mut self, message: &impl BinaryCodec) {
+        let mut data = Vec::new();
+        message.encode(&mut data).expect("Writing to Vec can't fail");
+        (data.len() as u32).encode(&mut self.outgoing).expect("Writing to Vec can't fail");
+        self.outgoing.extend(data);
+    }
+
+    /// Записывает в сокет сколько возможно данных из буфера отправки не блокируя поток. Возвращает ошибку если
+    /// после записи в буфере осталось больше допустимого, см [Connection::set_max_outgoing]
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => { return Err(ErrorKind::WriteZero.into()); }
+                Ok(written) => { self.outgoing.drain(..written); }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => { break; }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => { return Err(err); }
+            }
+        }
+        if self.outgoing.len() > self.max_outgoing {
+            return Err(io::Error::other("send buffer overflow, peer doesn't read messages"));
+        }
+        Ok(())
+    }
+
+    /// Все ли сообщения из буфера отправки записаны в сокет
+    pub fn is_flushed(&self) -> bool {
+        self.outgoing.is_empty()
+    }
+
+    /// Читает все доступные сообщения не блокируя поток. Возвращает ошибку если данные некорректны или если
+    /// соединение закрыто и сообщений больше нет
+    pub fn receive<T: BinaryCodec>(&mut self) -> io::Result<Vec<T>> {
+        let mut buffer = [0u8; 64 * 1024];
+        let mut closed = false;
+        loop {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => {
+                    closed = true;
+                    break;
+                }
+                Ok(read) => { self.incoming.extend_from_slice(&buffer[..read]); }
+                Err(err) if err.kind() == ErrorKind::WouldBlock => { break; }
+                Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                Err(err) => { return Err(err); }
+            }
+        }
+
+        let mut messages = Vec::new();
+        let mut offset = 0;
+        while self.incoming.len() - offset >= 4 {
+            let len = u32::decode(&mut &self.incoming[offset..offset + 4])? as usize;
+            if len > MAX_MESSAGE_SIZE {
+                return Err(io::Error::new(ErrorKind::InvalidData, "message is too large"));
+            }
+            if self.incoming.len() - offset - 4 < len {
+                break;
+            }
+            let mut data = &self.incoming[offset + 4..offset + 4 + len];
+            messages.push(T::decode(&mut data)?);
+            if !data.is_empty() {
+                return Err(io::Error::new(ErrorKind::InvalidData, "message has trailing data"));
+            }
+            offset += 4 + len;
+        }
+        self.incoming.drain(..offset);
+        if closed && messages.is_empty() {
+            return Err(ErrorKind::ConnectionAborted.into());
+        }
+        Ok(messages)
+    }
+}
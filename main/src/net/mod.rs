@@ -0,0 +1,163 @@
+mod protocol;
+mod connection;
+mod server;
+mod client;
+
+pub use server::ServerPlugin;
+pub use client::ClientPlugin;
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::time::{Duration, Instant};
+    use bevy::math::ivec3;
+    use bevy::prelude::*;
+    use chunk::ChunkPos;
+    use world_anchor::{WorldAnchor, WorldAnchorPlugin};
+    use crate::logic::block::{Block, BlockDefinition, BlockId, BlockRegistry, SharedBlockRegistry};
+    use crate::logic::world::generator::FlatWorldGenerator;
+    use crate::logic::world::{World, WorldPlugin, WorldStorageSettings};
+    use crate::net::{ClientPlugin, ServerPlugin};
+    use crate::net::connection::Connection;
+    use crate::net::protocol::{ClientMessage, PROTOCOL_VERSION, ServerMessage};
+    use crate::net::server::Server;
+
+    fn block_registry() -> SharedBlockRegistry {
+        let definitions = [
+            BlockDefinition { id: 1, name: "stone".to_string(), ..Default::default() },
+            BlockDefinition { id: 2, name: "glass".to_string(), transparent: true, ..Default::default() },
+        ];
+        SharedBlockRegistry::new(BlockRegistry::new(definitions).unwrap())
+    }
+
+    fn server_app(world_dir: &std::path::Path) -> App {
+        let mut app = App::new();
+        app
+            .add_plugins(MinimalPlugins)
+            .insert_resource(block_registry())
+            .insert_resource(WorldStorageSettings { world_dir: world_dir.to_path_buf(), ..Default::default() })
+            .add_plugins(WorldAnchorPlugin)
+            .add_plugins(WorldPlugin::new(FlatWorldGenerator::new(vec![("stone".to_string(), 4)])))
+            .add_plugins(ServerPlugin { addr: "127.0.0.1:0".parse().unwrap() });
+        app
+    }
+
+    fn client_app(addr: SocketAddr) -> App {
+        let mut app = App::new();
+        app
+            .add_plugins(MinimalPlugins)
+            .insert_resource(block_registry())
+            .add_plugins(WorldAnchorPlugin)
+            .add_plugins(ClientPlugin { addr });
+        app.world.spawn((
+            TransformBundle::from_transform(Transform::from_xyz(8., 8., 8.)),
+            WorldAnchor { load_radius: 1, vertical_load_radius: 2, ..default() },
+        ));
+        app
+    }
+
+    /// Обновляет все приложения пока условие не выполнится
+    fn update_until(apps: &mut [&mut App], condition: impl Fn(&[&mut App]) -> bool) {
+        let start = Instant::now();
+        while !condition(apps) {
+            assert!(start.elapsed() < Duration::from_secs(30), "Condition is not reached in time");
+            for app in apps.iter_mut() {
+                app.update();
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        }
+    }
+
+    fn world(app: &App) -> &World {
+        app.world.resource::<World>()
+    }
+
+    #[test]
+    fn clients_see_same_blocks() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = server_app(dir.path());
+        let addr = server.world.resource::<Server>().local_addr();
+        let mut client_a = client_app(addr);
+        let mut client_b = client_app(addr);
+
+        let chunks: Vec<ChunkPos> = (-1..2).map(|z| ivec3(0, 0, z).into()).collect();
+        update_until(&mut [&mut server, &mut client_a, &mut client_b], |apps| {
+            apps[1..].iter().all(|app| chunks.iter().all(|pos| world(app).is_chunk_loaded(pos)))
+        });
+        assert_eq!(server.world.resource::<Server>().client_count(), 2);
+
+        let stone = Some(Block::new(BlockId(1)));
+        assert_eq!(world(&client_a).get_block(ivec3(3, 3, 3).into()), Some(stone));
+        assert_eq!(world(&client_b).get_block(ivec3(3, 3, 4).into()), Some(None));
+
+        // Изменение первого клиента применяется сервером и приходит второму клиенту
+        let glass = Some(Block::new(BlockId(2)));
+        assert!(world(&client_a).set_block(ivec3(5, 6, 4).into(), glass));
+        assert!(world(&client_b).set_block(ivec3(1, 1, 3).into(), None));
+        update_until(&mut [&mut server, &mut client_a, &mut client_b], |apps| {
+            apps.iter().all(|app| {
+                world(app).get_block(ivec3(5, 6, 4).into()) == Some(glass)
+                    && world(app).get_block(ivec3(1, 1, 3).into()) == Some(None)
+            })
+        });
+
+        // Изменение неизвестным блоком отклоняется сервером и откатывается на клиенте
+        let unknown = Some(Block::new(BlockId(100)));
+        assert!(world(&client_a).set_block(ivec3(2, 2, 2).into(), unknown));
+        update_until(&mut [&mut server, &mut client_a, &mut client_b], |apps| {
+            world(apps[1]).get_block(ivec3(2, 2, 2).into()) == Some(stone)
+        });
+
+        // Клиент не сохраняет чанки, поэтому пометки об изменении не накапливаются
+        assert!(world(&client_a).take_dirty_chunks().is_empty());
+
+        for pos in chunks {
+            let chunk_a = world(&client_a).get_chunk(&pos).unwrap();
+            let chunk_b = world(&client_b).get_chunk(&pos).unwrap();
+            let chunk_a = chunk_a.read().unwrap();
+            let chunk_b = chunk_b.read().unwrap();
+            assert!(chunk_a.into_iter().zip(chunk_b.into_iter()).all(|((_, a), (_, b))| a == b));
+        }
+    }
+
+    #[test]
+    fn wrong_protocol_version_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = server_app(dir.path());
+        let addr = server.world.resource::<Server>().local_addr();
+
+        let mut connection = Connection::connect(addr).unwrap();
+        connection.send(&ClientMessage::Hello { version: PROTOCOL_VERSION + 1 });
+        connection.flush().unwrap();
+
+        let start = Instant::now();
+        let messages = loop {
+            assert!(start.elapsed() < Duration::from_secs(30), "Server didn't answer in time");
+            server.update();
+            let messages = connection.receive::<ServerMessage>().unwrap();
+            if !messages.is_empty() {
+                break messages;
+            }
+            std::thread::sleep(Duration::from_millis(2));
+        };
+        assert!(matches!(messages[0], ServerMessage::Disconnect { .. }));
+        assert_eq!(server.world.resource::<Server>().client_count(), 0);
+    }
+
+    #[test]
+    fn connection_to_peer_not_reading_is_closed() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut connection = Connection::connect(listener.local_addr().unwrap()).unwrap();
+        let (_peer, _) = listener.accept().unwrap();
+        connection.set_max_outgoing(1024 * 1024);
+
+        // Другая сторона ничего не читает, поэтому после заполнения буферов сокета растет буфер отправки
+        let message = ServerMessage::Disconnect { reason: "x".repeat(64 * 1024) };
+        let result = (0..10_000).map(|_| {
+            connection.send(&message);
+            connection.flush()
+        }).find(|result| result.is_err());
+        assert!(result.is_some());
+        assert!(connection.outgoing_len() > 1024 * 1024);
+    }
+}
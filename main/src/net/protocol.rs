@@ -0,0 +1,332 @@
+use std::io;
+use std::io::{Read, Write};
+use bevy::math::{IVec3, Vec3};
+use chunk::{AbsoluteBlockPos, BinaryCodec, CHUNK_SIZE, ChunkPos};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use world_anchor::LoadShape;
+use crate::logic::block::Block;
+use crate::logic::chunk::Chunk;
+use crate::logic::light::SkyExposure;
+
+/// Версия протокола, клиент и сервер с разными версиями не могут работать вместе
+pub const PROTOCOL_VERSION: u16 = 3;
+
+/// Сообщения клиента серверу
+#[derive(Clone, PartialEq, Debug)]
+pub enum ClientMessage {
+    /// Первое сообщение после подключения
+    Hello { version: u16 },
+
+    /// Позиция якоря клиента, сервер загружает мир и отправляет чанки в радиусе `load_radius` вокруг нее,
+    /// по вертикали в радиусе `vertical_load_radius`
+    Anchor { pos: Vec3, load_radius: u32, vertical_load_radius: u32, shape: AnchorShape },
+
+    /// Запрос на изменение блока, [None] удаляет блок
+    SetBlock { pos: AbsoluteBlockPos, block: Option<Block> },
+}
+
+/// Форма области загрузки якоря клиента, см [LoadShape]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AnchorShape {
+    Square,
+    Cylinder,
+    Sphere,
+}
+
+impl AnchorShape {
+    /// Пользовательскую форму нельзя передать по сети, вместо нее сервер использует [AnchorShape::Square]
+    pub fn from_load_shape(shape: &LoadShape) -> Self {
+        match shape {
+            LoadShape::Square | LoadShape::Custom(_) => { AnchorShape::Square }
+            LoadShape::Cylinder => { AnchorShape::Cylinder }
+            LoadShape::Sphere => { AnchorShape::Sphere }
+        }
+    }
+
+    pub fn to_load_shape(self) -> LoadShape {
+        match self {
+            AnchorShape::Square => { LoadShape::Square }
+            AnchorShape::Cylinder => { LoadShape::Cylinder }
+            AnchorShape::Sphere => { LoadShape::Sphere }
+        }
+    }
+}
+
+/// Формат: u8
+impl BinaryCodec for AnchorShape {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let tag: u8 = match self {
+            AnchorShape::Square => { 0 }
+            AnchorShape::Cylinder => { 1 }
+            AnchorShape::Sphere => { 2 }
+        };
+        tag.encode(writer)
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        match u8::decode(reader)? {
+            0 => { Ok(AnchorShape::Square) }
+            1 => { Ok(AnchorShape::Cylinder) }
+            2 => { Ok(AnchorShape::Sphere) }
+            _ => { Err(invalid_data("unknown anchor shape")) }
+        }
+    }
+}
+
+/// Сообщения сервера клиенту
+#[derive(Clone, PartialEq, Debug)]
+pub enum ServerMessage {
+    /// Ответ на [ClientMessage::Hello] если версии протокола совпадают
+    Welcome { version: u16 },
+
+    /// Сервер закрывает соединение
+    Disconnect { reason: String },
+
+    /// Сжатые данные чанка, см [encode_chunk], и колонны чанка над которыми открытое небо
+    ChunkData { pos: ChunkPos, data: Vec<u8>, sky_exposure: SkyExposure },
+
+    /// Чанк больше не отправляется клиенту и может быть выгружен
+    UnloadChunk { pos: ChunkPos },
+
+    /// Блок изменился, отправляется только клиентам которым отправлен чанк с этим блоком
+    BlockChanged { pos: AbsoluteBlockPos, block: Option<Block> },
+}
+
+/// Формат: тип сообщения (u8), далее поля сообщения по порядку
+impl BinaryCodec for ClientMessage {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            ClientMessage::Hello { version } => {
+                0u8.encode(writer)?;
+                version.encode(writer)
+            }
+            ClientMessage::Anchor { pos, load_radius, vertical_load_radius, shape } => {
+                1u8.encode(writer)?;
+                encode_vec3(*pos, writer)?;
+                load_radius.encode(writer)?;
+                vertical_load_radius.encode(writer)?;
+                shape.encode(writer)
+            }
+            ClientMessage::SetBlock { pos, block } => {
+                2u8.encode(writer)?;
+                encode_ivec3(**pos, writer)?;
+                block.encode(writer)
+            }
+        }
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        match u8::decode(reader)? {
+            0 => { Ok(ClientMessage::Hello { version: u16::decode(reader)? }) }
+            1 => {
+                Ok(ClientMessage::Anchor {
+                    pos: decode_vec3(reader)?,
+                    load_radius: u32::decode(reader)?,
+                    vertical_load_radius: u32::decode(reader)?,
+                    shape: AnchorShape::decode(reader)?,
+                })
+            }
+            2 => { Ok(ClientMessage::SetBlock { pos: decode_ivec3(reader)?.into(), block: Option::decode(reader)? }) }
+            _ => { Err(invalid_data("unknown client message")) }
+        }
+    }
+}
+
+/// Формат: тип сообщения (u8), далее поля сообщения по порядку
+impl BinaryCodec for ServerMessage {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            ServerMessage::Welcome { version } => {
+                0u8.encode(writer)?;
+                version.encode(writer)
+            }
+            ServerMessage::Disconnect { reason } => {
+                1u8.encode(writer)?;
+                encode_bytes(reason.as_bytes(), writer)
+            }
+            ServerMessage::ChunkData { pos, data, sky_exposure } => {
+                2u8.encode(writer)?;
+                encode_ivec3(**pos, writer)?;
+                encode_bytes(data, writer)?;
+                encode_sky_exposure(sky_exposure, writer)
+            }
+            ServerMessage::UnloadChunk { pos } => {
+                3u8.encode(writer)?;
+                encode_ivec3(**pos, writer)
+            }
+            ServerMessage::BlockChanged { pos, block } => {
+                4u8.encode(writer)?;
+                encode_ivec3(**pos, writer)?;
+                block.encode(writer)
+            }
+        }
+    }
+
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        match u8::decode(reader)? {
+            0 => { Ok(ServerMessage::Welcome { version: u16::decode(reader)? }) }
+            1 => {
+                let reason = String::from_utf8(decode_bytes(reader)?)
+                    .map_err(|_| invalid_data("incorrect disconnect reason"))?;
+                Ok(ServerMessage::Disconnect { reason })
+            }
+            2 => {
+                Ok(ServerMessage::ChunkData {
+                    pos: decode_ivec3(reader)?.into(),
+                    data: decode_bytes(reader)?,
+                    sky_exposure: decode_sky_exposure(reader)?,
+                })
+            }
+            3 => { Ok(ServerMessage::UnloadChunk { pos: decode_ivec3(reader)?.into() }) }
+            4 => {
+                let pos = decode_ivec3(reader)?.into();
+                Ok(ServerMessage::BlockChanged { pos, block: Option::decode(reader)? })
+            }
+            _ => { Err(invalid_data("unknown server message")) }
+        }
+    }
+}
+
+/// Кодирует и сжимает чанк для передачи по сети
+pub fn encode_chunk(chunk: &Chunk) -> io::Result<Vec<u8>> {
+    let mut encoder = DeflateEncoder::new(Vec::new(), Compression::fast());
+    chunk.encode(&mut encoder)?;
+    encoder.finish()
+}
+
+/// Распаковывает чанк закодированный [encode_chunk]
+pub fn decode_chunk(data: &[u8]) -> io::Result<Chunk> {
+    Chunk::decode(&mut DeflateDecoder::new(data))
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn encode_ivec3<W: Write>(value: IVec3, writer: &mut W) -> io::Result<()> {
+    value.x.encode(writer)?;
+    value.y.encode(writer)?;
+    value.z.encode(writer)
+}
+
+fn decode_ivec3<R: Read>(reader: &mut R) -> io::Result<IVec3> {
+    Ok(IVec3::new(i32::decode(reader)?, i32::decode(reader)?, i32::decode(reader)?))
+}
+
+fn encode_vec3<W: Write>(value: Vec3, writer: &mut W) -> io::Result<()> {
+    value.x.encode(writer)?;
+    value.y.encode(writer)?;
+    value.z.encode(writer)
+}
+
+fn decode_vec3<R: Read>(reader: &mut R) -> io::Result<Vec3> {
+    Ok(Vec3::new(f32::decode(reader)?, f32::decode(reader)?, f32::decode(reader)?))
+}
+
+/// Формат: по одному биту на колонну в порядке x, затем y
+fn encode_sky_exposure<W: Write>(sky_exposure: &SkyExposure, writer: &mut W) -> io::Result<()> {
+    let size = CHUNK_SIZE as u32;
+    let mut bytes = vec![0u8; CHUNK_SIZE * CHUNK_SIZE / 8];
+    for x in 0..size {
+        for y in 0..size {
+            if sky_exposure.get(x, y) {
+                let index = (x * size + y) as usize;
+                bytes[index / 8] |= 1 << (index % 8);
+            }
+        }
+    }
+    writer.write_all(&bytes)
+}
+
+fn decode_sky_exposure<R: Read>(reader: &mut R) -> io::Result<SkyExposure> {
+    let mut bytes = vec![0u8; CHUNK_SIZE * CHUNK_SIZE / 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(SkyExposure::from_fn(|x, y| {
+        let index = (x * CHUNK_SIZE as u32 + y) as usize;
+        bytes[index / 8] & (1 << (index % 8)) != 0
+    }))
+}
+
+/// Формат: длина (u32), далее байты
+fn encode_bytes<W: Write>(bytes: &[u8], writer: &mut W) -> io::Result<()> {
+    (bytes.len() as u32).encode(writer)?;
+    writer.write_all(bytes)
+}
+
+fn decode_bytes<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let len = u32::decode(reader)? as usize;
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(invalid_data("not enough data"));
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::{ivec3, uvec3, vec3};
+    use chunk::BinaryCodec;
+    use crate::logic::block::{Block, BlockId};
+    use crate::logic::chunk::Chunk;
+    use crate::logic::light::SkyExposure;
+    use crate::net::protocol::{AnchorShape, ClientMessage, decode_chunk, encode_chunk, PROTOCOL_VERSION, ServerMessage};
+
+    fn round_trip<T: BinaryCodec>(value: &T) -> T {
+        let mut bytes = Vec::new();
+        value.encode(&mut bytes).unwrap();
+        T::decode(&mut bytes.as_slice()).unwrap()
+    }
+
+    #[test]
+    fn messages_round_trip() {
+        let messages = [
+            ClientMessage::Hello { version: PROTOCOL_VERSION },
+            ClientMessage::Anchor {
+                pos: vec3(1.5, -2., 64.),
+                load_radius: 8,
+                vertical_load_radius: 3,
+                shape: AnchorShape::Sphere,
+            },
+            ClientMessage::SetBlock { pos: ivec3(-1, 2, 3).into(), block: Some(Block::new(BlockId(7))) },
+            ClientMessage::SetBlock { pos: ivec3(0, 0, 0).into(), block: None },
+        ];
+        for message in messages {
+            assert_eq!(round_trip(&message), message);
+        }
+
+        let messages = [
+            ServerMessage::Welcome { version: PROTOCOL_VERSION },
+            ServerMessage::Disconnect { reason: "Версия".to_string() },
+            ServerMessage::ChunkData {
+                pos: ivec3(1, -1, 0).into(),
+                data: vec![1, 2, 3],
+                sky_exposure: SkyExposure::from_fn(|x, y| x > y),
+            },
+            ServerMessage::UnloadChunk { pos: ivec3(4, 5, 6).into() },
+            ServerMessage::BlockChanged { pos: ivec3(-7, 8, 9).into(), block: None },
+        ];
+        for message in messages {
+            assert_eq!(round_trip(&message), message);
+        }
+        assert!(ServerMessage::decode(&mut [9u8].as_slice()).is_err());
+    }
+
+    #[test]
+    fn chunk_is_compressed() {
+        let mut chunk = Chunk::new(());
+        let pos = uvec3(1, 2, 3).try_into().unwrap();
+        chunk.set(&pos, Some(Block::new(BlockId(3))));
+
+        let data = encode_chunk(&chunk).unwrap();
+        let mut uncompressed = Vec::new();
+        chunk.encode(&mut uncompressed).unwrap();
+        assert!(data.len() < uncompressed.len());
+
+        let decoded = decode_chunk(&data).unwrap();
+        assert_eq!(decoded[&pos], Some(Block::new(BlockId(3))));
+        assert!(chunk.into_iter().zip(decoded.into_iter()).all(|((_, a), (_, b))| a == b));
+    }
+}
@@ -0,0 +1,337 @@
+use std::io::ErrorKind;
+use std::net::{SocketAddr, TcpListener};
+use bevy::prelude::*;
+use bevy::utils::{HashMap, HashSet};
+use chunk::ChunkPos;
+use world_anchor::{load_area_chunks, WorldAnchor, WorldAnchorInChunkPos};
+use crate::logic::block::SharedBlockRegistry;
+use crate::logic::world::{ChunkUpdateEvent, World};
+use crate::logic::world::generator::SharedWorldGenerator;
+use crate::net::connection::Connection;
+use crate::net::protocol::{ClientMessage, encode_chunk, PROTOCOL_VERSION, ServerMessage};
+
+/// Плагин сервера: принимает клиентов по TCP, отправляет им чанки вокруг их якорей, применяет их изменения блоков
+/// и рассылает изменения блоков всем клиентам.
+///
+/// Для каждого клиента создается сущность с [WorldAnchor], поэтому мир вокруг клиентов загружается
+/// [crate::logic::world::WorldPlugin]. Требует [crate::logic::world::WorldPlugin] и [world_anchor::WorldAnchorPlugin]
+pub struct ServerPlugin {
+    pub addr: SocketAddr,
+}
+
+impl Plugin for ServerPlugin {
+    fn build(&self, app: &mut App) {
+        let server = match Server::bind(self.addr) {
+            Ok(server) => { server }
+            Err(err) => { panic!("Can't start server on {}: {}", self.addr, err) }
+        };
+        info!("Server started on {}", server.local_addr());
+
+        app
+            .insert_resource(server)
+            .init_resource::<ServerSettings>()
+            .add_systems(Update, (accept_clients, receive_client_messages, stream_chunks).chain())
+            .add_systems(Last, (broadcast_block_changes, flush_client_connections).chain())
+        ;
+    }
+}
+
+/// Настройки сервера
+#[derive(Resource)]
+pub struct ServerSettings {
+    /// Максимальный радиус загрузки мира вокруг клиента, больший радиус запрошенный клиентом уменьшается до этого.
+    /// Ограничивает так же радиус по вертикали
+    pub max_load_radius: u32,
+
+    /// Сколько чанков отправляется одному клиенту за тик
+    pub chunks_per_tick: usize,
+
+    /// Чанки не отправляются клиенту пока в буфере отправки его соединения больше байт чем это значение, поэтому
+    /// медленный клиент получает чанки со своей скоростью, а не отключается из-за переполнения буфера
+    pub max_pending_bytes: usize,
+
+    /// Соединение с клиентом закрывается если его буфер отправки превышает это значение
+    pub max_outgoing_bytes: usize,
+}
+
+impl Default for ServerSettings {
+    fn default() -> Self {
+        Self {
+            max_load_radius: 16,
+            chunks_per_tick: 64,
+            max_pending_bytes: 4 * 1024 * 1024,
+            max_outgoing_bytes: 64 * 1024 * 1024,
+        }
+    }
+}
+
+/// Сервер и подключенные к нему клиенты
+#[derive(Resource)]
+pub struct Server {
+    listener: TcpListener,
+    clients: HashMap<u64, RemoteClient>,
+    next_client_id: u64,
+}
+
+impl Server {
+    pub fn bind(addr: SocketAddr) -> std::io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self { listener, clients: HashMap::new(), next_client_id: 0 })
+    }
+
+    /// Адрес на котором сервер принимает клиентов, полезен если при запуске был указан порт 0
+    pub fn local_addr(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    /// Количество клиентов прошедших проверку версии протокола
+    pub fn client_count(&self) -> usize {
+        self.clients.values().filter(|client| client.welcomed).count()
+    }
+}
+
+struct RemoteClient {
+    connection: Connection,
+    addr: SocketAddr,
+
+    /// Клиент прислал [ClientMessage::Hello] с верной версией протокола
+    welcomed: bool,
+
+    /// Сущность с якорем клиента, создается при получении первой позиции якоря
+    anchor: Option<Entity>,
+
+    /// Чанки отправленные клиенту
+    sent_chunks: HashSet<ChunkPos>,
+
+    /// Область загрузки якоря клиента, пересчитывается только при изменении якоря
+    load_area: Option<ClientLoadArea>,
+
+    /// Соединение нужно закрыть после отправки оставшихся сообщений
+    disconnecting: bool,
+}
+
+/// Чанки области загрузки якоря находящегося в чанке `center`, см [load_area_chunks]
+struct ClientLoadArea {
+    center: ChunkPos,
+    chunks: Vec<ChunkPos>,
+}
+
+fn accept_clients(
+    settings: Res<ServerSettings>,
+    mut server: ResMut<Server>,
+) {
+    loop {
+        match server.listener.accept() {
+            Ok((stream, addr)) => {
+                let mut connection = match Connection::new(stream) {
+                    Ok(connection) => { connection }
+                    Err(err) => {
+                        warn!("Can't accept client {}: {}", addr, err);
+                        continue;
+                    }
+                };
+                connection.set_max_outgoing(settings.max_outgoing_bytes);
+                info!("Client {} connected", addr);
+                let id = server.next_client_id;
+                server.next_client_id += 1;
+                server.clients.insert(id, RemoteClient {
+                    connection,
+                    addr,
+                    welcomed: false,
+                    anchor: None,
+                    sent_chunks: HashSet::new(),
+                    load_area: None,
+                    disconnecting: false,
+                });
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => { break; }
+            Err(err) => {
+                warn!("Can't accept client: {}", err);
+                break;
+            }
+        }
+    }
+}
+
+fn receive_client_messages(
+    mut commands: Commands,
+    world: Res<World>,
+    block_registry: Res<SharedBlockRegistry>,
+    settings: Res<ServerSettings>,
+    mut server: ResMut<Server>,
+) {
+    let mut disconnected = Vec::new();
+    for (id, client) in server.clients.iter_mut() {
+        if client.disconnecting {
+            continue;
+        }
+        let messages = match client.connection.receive::<ClientMessage>() {
+            Ok(messages) => { messages }
+            Err(err) => {
+                info!("Client {} disconnected: {}", client.addr, err);
+                disconnected.push(*id);
+                continue;
+            }
+        };
+
+        for message in messages {
+            match message {
+                ClientMessage::Hello { version } => {
+                    if version != PROTOCOL_VERSION {
+                        let reason = format!("Unsupported protocol version {}, server uses {}",
+                                             version, PROTOCOL_VERSION);
+                        client.connection.send(&ServerMessage::Disconnect { reason });
+                        client.disconnecting = true;
+                        break;
+                    }
+                    client.welcomed = true;
+                    client.connection.send(&ServerMessage::Welcome { version: PROTOCOL_VERSION });
+                }
+                _ if !client.welcomed => {
+                    client.connection.send(&ServerMessage::Disconnect { reason: "Expected hello".to_string() });
+                    client.disconnecting = true;
+                    break;
+                }
+                ClientMessage::Anchor { pos, load_radius, vertical_load_radius, shape } => {
+                    let anchor = WorldAnchor {
+                        load_radius: load_radius.min(settings.max_load_radius),
+                        vertical_load_radius: vertical_load_radius.min(settings.max_load_radius),
+                        shape: shape.to_load_shape(),
+                        ..default()
+                    };
+                    let transform = Transform::from_translation(pos);
+                    match client.anchor {
+                        Some(entity) => { commands.entity(entity).insert((transform, anchor)); }
+                        None => {
+                            let entity = commands.spawn((TransformBundle::from_transform(transform), anchor)).id();
+                            client.anchor = Some(entity);
+                        }
+                    }
+                }
+                ClientMessage::SetBlock { pos, block } => {
+                    // Клиент может менять только блоки которые он видит и только на известные серверу
+                    let is_known = match block {
+                        None => { true }
+                        Some(block) => { block_registry.get(block.id()).is_some() }
+                    };
+                    let is_visible = client.sent_chunks.contains(&ChunkPos::from(pos));
+                    if !is_known || !is_visible || !world.set_block(pos, block) {
+                        // Возвращаем клиенту настоящее значение блока, чтобы отменить его предсказание
+                        if let Some(block) = world.get_block(pos) {
+                            client.connection.send(&ServerMessage::BlockChanged { pos, block });
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    for id in disconnected {
+        remove_client(&mut commands, &mut server, id);
+    }
+}
+
+/// Отправляет клиентам загруженные чанки в радиусе их якорей и сообщает о чанках вышедших из радиуса
+fn stream_chunks(
+    world: Res<World>,
+    world_generator: Res<SharedWorldGenerator>,
+    settings: Res<ServerSettings>,
+    mut server: ResMut<Server>,
+    anchors: Query<(&WorldAnchorInChunkPos, Ref<WorldAnchor>)>,
+) {
+    for client in server.clients.values_mut() {
+        let Some((anchor_pos, anchor)) = client.anchor.and_then(|entity| anchors.get(entity).ok()) else {
+            continue;
+        };
+        // Та же область что загружает crate::logic::world::WorldPlugin вокруг якоря. Чанки уже отсортированы по
+        // расстоянию до якоря, список строится заново только при переходе якоря в другой чанк или изменении его формы
+        let center = anchor_pos.pos;
+        if anchor.is_changed() || !client.load_area.as_ref().is_some_and(|area| area.center == center) {
+            client.load_area = Some(ClientLoadArea { center, chunks: load_area_chunks(center, &anchor) });
+        }
+        let RemoteClient { connection, sent_chunks, load_area, .. } = client;
+        sent_chunks.retain(|pos| {
+            let keep = anchor.contains(center, *pos) && world.is_chunk_loaded(pos);
+            if !keep {
+                connection.send(&ServerMessage::UnloadChunk { pos: *pos });
+            }
+            keep
+        });
+
+        let chunks_to_send: Vec<ChunkPos> = load_area.iter()
+            .flat_map(|area| area.chunks.iter().copied())
+            .filter(|pos| !sent_chunks.contains(pos) && world.is_chunk_loaded(pos))
+            .take(settings.chunks_per_tick)
+            .collect();
+        for pos in chunks_to_send {
+            if connection.outgoing_len() > settings.max_pending_bytes {
+                break;
+            }
+            let Some(chunk) = world.get_chunk(&pos) else {
+                continue;
+            };
+            let data = match encode_chunk(&chunk.read().unwrap()) {
+                Ok(data) => { data }
+                Err(err) => {
+                    error!("Can't encode chunk {:?}: {}", pos, err);
+                    continue;
+                }
+            };
+            let sky_exposure = world_generator.sky_exposure(pos);
+            connection.send(&ServerMessage::ChunkData { pos, data, sky_exposure });
+            sent_chunks.insert(pos);
+        }
+    }
+}
+
+/// Рассылает изменения блоков клиентам которым отправлены чанки с этими блоками
+fn broadcast_block_changes(
+    world: Res<World>,
+    mut server: ResMut<Server>,
+    mut chunk_events: EventReader<ChunkUpdateEvent>,
+) {
+    for event in chunk_events.iter() {
+        let ChunkUpdateEvent::BlockChanged(pos) = event else {
+            continue;
+        };
+        let Some(block) = world.get_block(*pos) else {
+            continue;
+        };
+        let chunk_pos = ChunkPos::from(*pos);
+        for client in server.clients.values_mut() {
+            if client.sent_chunks.contains(&chunk_pos) {
+                client.connection.send(&ServerMessage::BlockChanged { pos: *pos, block });
+            }
+        }
+    }
+}
+
+fn flush_client_connections(
+    mut commands: Commands,
+    mut server: ResMut<Server>,
+) {
+    let mut disconnected = Vec::new();
+    for (id, client) in server.clients.iter_mut() {
+        if let Err(err) = client.connection.flush() {
+            info!("Client {} disconnected: {}", client.addr, err);
+            disconnected.push(*id);
+        } else if client.disconnecting && client.connection.is_flushed() {
+            info!("Client {} disconnected by server", client.addr);
+            disconnected.push(*id);
+        }
+    }
+    for id in disconnected {
+        remove_client(&mut commands, &mut server, id);
+    }
+}
+
+fn remove_client(commands: &mut Commands, server: &mut Server, id: u64) {
+    let Some(client) = server.clients.remove(&id) else {
+        return;
+    };
+    if let Some(entity) = client.anchor {
+        commands.entity(entity).despawn();
+    }
+    info!("{} clients connected", server.client_count());
+}
@@ -1,7 +1,8 @@
 use std::ops::{Add, Deref};
-use bevy::math::IVec3;
+use bevy::math::{IVec3, Rect, Vec3};
 use strum_macros::EnumIter;
 use chunk::AbsoluteBlockPos;
+use crate::logic::light::MAX_LIGHT;
 use crate::render::MeshPart;
 
 /// Направление стороны блока в абсолютных координатах
@@ -42,7 +43,7 @@ impl Into<IVec3> for AbsoluteBlockFaceDirection {
 impl AbsoluteBlockFaceDirection {
     /// Возвращает массив позиций для текущего [AbsoluteBlockFaceDirection]
     /// Для описания стороны блока нужно 4 вершины каждая из которых описывается 3мя координатами
-    fn get_vertex_positions(&self) -> &'static [[f32; 3]; 4] {
+    pub fn get_vertex_positions(&self) -> &'static [[f32; 3]; 4] {
         match self {
             AbsoluteBlockFaceDirection::PosX => {
                 &[
@@ -155,15 +156,16 @@ impl AbsoluteBlockFaceDirection {
     /// Возвращает массив позиций текстуры для текущего [AbsoluteBlockFaceDirection]
     /// Количество позиций текстуры должно соответствовать количеству вершин у вертекса,
     /// у каждой вершины своя позиция текстуры, см [Self::get_vertex_positions]
-    /// Для описания позиции текстуры нужны 2 координаты
+    /// Для описания позиции текстуры нужны 2 координаты, ось v направлена вниз по изображению, поэтому у боковых
+    /// граней верх текстуры совпадает с верхом блока
     fn get_uvs(&self) -> &'static [[f32; 2]; 4] {
         match self {
             AbsoluteBlockFaceDirection::PosX => {
                 &[
-                    [0., 0.],
-                    [1., 0.],
-                    [1., 1.],
                     [0., 1.],
+                    [1., 1.],
+                    [1., 0.],
+                    [0., 0.],
                 ]
             }
             AbsoluteBlockFaceDirection::NegX => {
@@ -176,62 +178,144 @@ impl AbsoluteBlockFaceDirection {
             }
             AbsoluteBlockFaceDirection::PosY => {
                 &[
-                    [1., 0.],
-                    [0., 0.],
                     [0., 1.],
                     [1., 1.],
+                    [1., 0.],
+                    [0., 0.],
                 ]
             }
             AbsoluteBlockFaceDirection::NegY => {
                 &[
-                    [0., 0.],
                     [1., 0.],
-                    [1., 1.],
+                    [0., 0.],
                     [0., 1.],
+                    [1., 1.],
                 ]
             }
             AbsoluteBlockFaceDirection::PosZ => {
                 &[
-                    [0., 0.],
-                    [1., 0.],
-                    [1., 1.],
                     [0., 1.],
+                    [1., 1.],
+                    [1., 0.],
+                    [0., 0.],
                 ]
             }
             AbsoluteBlockFaceDirection::NegZ => {
                 &[
-                    [1., 0.],
-                    [0., 0.],
                     [0., 1.],
                     [1., 1.],
+                    [1., 0.],
+                    [0., 0.],
                 ]
             }
         }
     }
 
+    /// Возвращает индексы осей вдоль которых меняются координаты текстуры u и v
+    fn get_uv_axes(&self) -> (usize, usize) {
+        match self {
+            AbsoluteBlockFaceDirection::PosX | AbsoluteBlockFaceDirection::NegX => { (1, 2) }
+            AbsoluteBlockFaceDirection::PosY | AbsoluteBlockFaceDirection::NegY => { (0, 2) }
+            AbsoluteBlockFaceDirection::PosZ | AbsoluteBlockFaceDirection::NegZ => { (0, 1) }
+        }
+    }
+
+    /// Возвращает прямоугольник из граней нескольких блоков, `size` задает размер прямоугольника в блоках, размер
+    /// вдоль нормали грани должен быть равен 1. Текстура из прямоугольника атласа `rect` повторяется для каждого блока.
+    /// `shading` задает освещение вершин в порядке [Self::get_vertex_positions]
+    pub fn quad(self, size: Vec3, rect: Rect, shading: FaceShading) -> BlockFaceQuad {
+        let FaceShading { ao, light } = shading;
+        let positions = self.get_vertex_positions().map(|pos| (Vec3::from(pos) * size).to_array());
+        let (u_axis, v_axis) = self.get_uv_axes();
+        let uvs = self.get_uvs().map(|[u, v]| [u * size[u_axis], v * size[v_axis]]);
+        let tile = [rect.min.x, rect.min.y, rect.width(), rect.height()];
+        // Затенение интерполируется вдоль диагонали разбивающей прямоугольник на треугольники, поэтому выбираем
+        // диагональ между более светлыми вершинами, иначе затенение одного угла растягивается на всю грань
+        let flipped = ao[0] + ao[2] < ao[1] + ao[3];
+        let ao = ao.map(|ao| AO_FACTORS[ao as usize]);
+        let colors = light.map(|[sky, block]| [sky / MAX_LIGHT as f32, block / MAX_LIGHT as f32, 0., 1.]);
+        BlockFaceQuad { direction: self, positions, uvs, tiles: [tile; 4], ao, colors, flipped }
+    }
+
     /// Индексы описывают в какой в последовательности замыкать вершины в треугольники
     /// От направления обхода зависит в какую сторону будет смотреть плоскость треугольника, с
     /// другой стороны треугольник не будет виден
     fn get_indexes() -> &'static [u32; 6] {
         &[0, 1, 2, 2, 3, 0]
     }
+
+    /// Индексы с разбиением на треугольники по второй диагонали, см [Self::get_indexes]
+    fn get_flipped_indexes() -> &'static [u32; 6] {
+        &[1, 2, 3, 3, 0, 1]
+    }
 }
 
-impl MeshPart for AbsoluteBlockFaceDirection {
+/// Множители яркости вершины в зависимости от количества закрывающих ее соседних блоков,
+/// 0 - вершина в углу между тремя блоками, 3 - соседних блоков нет
+pub const AO_FACTORS: [f32; 4] = [0.4, 0.6, 0.8, 1.0];
+
+/// Освещение вершин грани, см [AbsoluteBlockFaceDirection::quad]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FaceShading {
+    /// Затенение вершин соседними блоками, см [AO_FACTORS]
+    pub ao: [u8; 4],
+
+    /// Свет неба и свет блоков у вершин, от 0 до [MAX_LIGHT]
+    pub light: [[f32; 2]; 4],
+}
+
+/// Прямоугольник из граней блоков с текстурой из атласа, см [AbsoluteBlockFaceDirection::quad]
+pub struct BlockFaceQuad {
+    direction: AbsoluteBlockFaceDirection,
+    positions: [[f32; 3]; 4],
+
+    /// Координаты текстуры в блоках, дробная часть задает позицию внутри тайла атласа
+    uvs: [[f32; 2]; 4],
+
+    /// Тайл атласа в формате `[min_u, min_v, width, height]`, одинаковый для всех вершин
+    tiles: [[f32; 4]; 4],
+
+    /// Множители яркости вершин, см [AO_FACTORS]
+    ao: [f32; 4],
+
+    /// Цвета вершин, в красном канале свет неба, в зеленом свет блоков, от 0 до 1
+    colors: [[f32; 4]; 4],
+
+    /// Разбит ли прямоугольник на треугольники по второй диагонали
+    flipped: bool,
+}
+
+impl MeshPart for BlockFaceQuad {
     fn get_indexes(&self) -> &[u32] {
-        AbsoluteBlockFaceDirection::get_indexes()
+        if self.flipped {
+            AbsoluteBlockFaceDirection::get_flipped_indexes()
+        } else {
+            AbsoluteBlockFaceDirection::get_indexes()
+        }
     }
 
     fn get_positions(&self) -> &[[f32; 3]] {
-        self.get_vertex_positions()
+        &self.positions
     }
 
     fn get_normals(&self) -> &[[f32; 3]] {
-        self.get_normals()
+        self.direction.get_normals()
     }
 
     fn get_uvs(&self) -> &[[f32; 2]] {
-        self.get_uvs()
+        &self.uvs
+    }
+
+    fn get_tiles(&self) -> &[[f32; 4]] {
+        &self.tiles
+    }
+
+    fn get_ao(&self) -> &[f32] {
+        &self.ao
+    }
+
+    fn get_colors(&self) -> &[[f32; 4]] {
+        &self.colors
     }
 }
 
@@ -0,0 +1,58 @@
+use bevy::pbr::{MaterialPipeline, MaterialPipelineKey};
+use bevy::prelude::*;
+use bevy::reflect::{TypePath, TypeUuid};
+use bevy::render::mesh::{MeshVertexAttribute, MeshVertexBufferLayout};
+use bevy::render::render_resource::{
+    AsBindGroup, RenderPipelineDescriptor, ShaderRef, SpecializedMeshPipelineError, VertexFormat,
+};
+
+/// Тайл атласа текстур в формате `[min_u, min_v, width, height]`. Координаты текстуры вершин [Mesh::ATTRIBUTE_UV_0]
+/// задаются в блоках, шейдер повторяет тайл для каждого блока, поэтому грани нескольких блоков можно объединять
+pub const ATTRIBUTE_TILE_RECT: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_TileRect", 731_905_418, VertexFormat::Float32x4);
+
+/// Множитель яркости вершины от затенения соседними блоками (ambient occlusion)
+pub const ATTRIBUTE_AO: MeshVertexAttribute =
+    MeshVertexAttribute::new("Vertex_Ao", 731_905_419, VertexFormat::Float32);
+
+/// Материал блоков мира, использует атлас текстур блоков и освещение PBR
+#[derive(AsBindGroup, TypeUuid, TypePath, Clone)]
+#[uuid = "5d3c3f0e-8a53-4b39-9f5c-2d8f0c6f7a41"]
+pub struct BlockMaterial {
+    #[texture(0)]
+    #[sampler(1)]
+    pub atlas: Handle<Image>,
+}
+
+impl Material for BlockMaterial {
+    fn vertex_shader() -> ShaderRef {
+        "shaders/block.wgsl".into()
+    }
+
+    fn fragment_shader() -> ShaderRef {
+        "shaders/block.wgsl".into()
+    }
+
+    fn specialize(
+        _pipeline: &MaterialPipeline<Self>,
+        descriptor: &mut RenderPipelineDescriptor,
+        layout: &MeshVertexBufferLayout,
+        _key: MaterialPipelineKey<Self>,
+    ) -> Result<(), SpecializedMeshPipelineError> {
+        // Prepass (в том числе рендер теней) использует стандартный шейдер bevy, которому нужны только стандартные
+        // атрибуты, поэтому его раскладку вершин не меняем
+        if descriptor.label.as_deref() == Some("prepass_pipeline") {
+            return Ok(());
+        }
+        let vertex_layout = layout.get_layout(&[
+            Mesh::ATTRIBUTE_POSITION.at_shader_location(0),
+            Mesh::ATTRIBUTE_NORMAL.at_shader_location(1),
+            Mesh::ATTRIBUTE_UV_0.at_shader_location(2),
+            ATTRIBUTE_TILE_RECT.at_shader_location(3),
+            ATTRIBUTE_AO.at_shader_location(4),
+            Mesh::ATTRIBUTE_COLOR.at_shader_location(5),
+        ])?;
+        descriptor.vertex.buffers = vec![vertex_layout];
+        Ok(())
+    }
+}
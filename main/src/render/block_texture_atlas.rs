@@ -0,0 +1,208 @@
+use std::path::Path;
+use std::sync::Arc;
+use bevy::log::warn;
+use bevy::math::{Rect, UVec2, vec2};
+use bevy::prelude::{Deref, Image, Resource};
+use bevy::render::render_resource::{Extent3d, TextureDimension, TextureFormat};
+use bevy::render::texture::{CompressedImageFormats, ImageSampler, ImageType};
+use bevy::utils::HashMap;
+use strum::IntoEnumIterator;
+use crate::logic::block::{BlockId, BlockRegistry};
+use crate::render::AbsoluteBlockFaceDirection;
+
+/// Размер текстуры используемой вместо отсутствующих, если не удалось загрузить ни одной текстуры
+const DEFAULT_TILE_SIZE: u32 = 16;
+
+/// Атлас текстур блоков.
+///
+/// Собирается при запуске из текстур всех блоков [BlockRegistry] в одно изображение, хранит прямоугольники UV
+/// координат для каждой грани каждого блока. Блоки и грани без текстуры получают текстуру-заглушку
+pub struct BlockTextureAtlas {
+    /// UV прямоугольники граней, индексируются [BlockId] и порядковым номером [AbsoluteBlockFaceDirection]
+    faces: Vec<Option<[Rect; 6]>>,
+
+    /// UV прямоугольник текстуры-заглушки
+    missing: Rect,
+}
+
+impl BlockTextureAtlas {
+    /// Загружает текстуры блоков из переданной директории и собирает атлас, возвращает атлас и его изображение.
+    /// Все текстуры должны быть одного размера, текстуры которые не удалось загрузить заменяются заглушкой
+    pub fn build(registry: &BlockRegistry, textures_dir: &Path) -> (Self, Image) {
+        let mut tile_size = None;
+        // Загруженные текстуры, нулевой тайл атласа всегда заглушка, поэтому индекс тайла на единицу больше
+        let mut tiles: Vec<Vec<u8>> = Vec::new();
+        let mut name_to_tile: HashMap<&str, usize> = HashMap::new();
+        for definition in registry.iter() {
+            for face in AbsoluteBlockFaceDirection::iter() {
+                let name = match definition.textures.get(face.into()) {
+                    Some(name) if !name_to_tile.contains_key(name) => { name }
+                    _ => { continue; }
+                };
+                let tile = load_texture(&textures_dir.join(name)).and_then(|(size, data)| {
+                    match tile_size {
+                        None => { tile_size = Some(size) }
+                        Some(tile_size) if tile_size != size => {
+                            return Err(format!("size {} differs from atlas tile size {}", size, tile_size));
+                        }
+                        _ => {}
+                    }
+                    Ok(data)
+                });
+                match tile {
+                    Ok(data) => {
+                        tiles.push(data);
+                        name_to_tile.insert(name, tiles.len());
+                    }
+                    Err(err) => {
+                        warn!("Can't load block texture {}: {}", name, err);
+                        name_to_tile.insert(name, 0);
+                    }
+                }
+            }
+        }
+
+        let tile_size = tile_size.unwrap_or(UVec2::splat(DEFAULT_TILE_SIZE));
+        tiles.insert(0, missing_texture(tile_size));
+        let (size, data, rects) = pack_tiles(tile_size, &tiles);
+
+        let mut faces = Vec::new();
+        for definition in registry.iter() {
+            let index = definition.id as usize;
+            if faces.len() <= index {
+                faces.resize(index + 1, None);
+            }
+            let mut block_faces = [rects[0]; 6];
+            for face in AbsoluteBlockFaceDirection::iter() {
+                if let Some(name) = definition.textures.get(face.into()) {
+                    block_faces[face as usize] = rects[name_to_tile[name]];
+                }
+            }
+            faces[index] = Some(block_faces);
+        }
+
+        let mut image = Image::new(
+            Extent3d { width: size.x, height: size.y, depth_or_array_layers: 1 },
+            TextureDimension::D2,
+            data,
+            TextureFormat::Rgba8UnormSrgb,
+        );
+        // Линейная фильтрация смешивает соседние тайлы атласа
+        image.sampler_descriptor = ImageSampler::nearest();
+
+        (Self { faces, missing: rects[0] }, image)
+    }
+
+    /// Возвращает UV прямоугольник текстуры грани блока
+    pub fn get_face_rect(&self, id: BlockId, face: AbsoluteBlockFaceDirection) -> Rect {
+        match self.faces.get(id.0 as usize) {
+            Some(Some(faces)) => { faces[face as usize] }
+            _ => { self.missing }
+        }
+    }
+}
+
+/// Атлас используемый рендером, может свободно клонироваться для передачи в асинхронные задачи
+#[derive(Resource, Clone, Deref)]
+pub struct SharedBlockTextureAtlas(Arc<BlockTextureAtlas>);
+
+impl SharedBlockTextureAtlas {
+    pub fn new(atlas: BlockTextureAtlas) -> Self {
+        Self(Arc::new(atlas))
+    }
+}
+
+/// Загружает текстуру и возвращает ее размер и пиксели в формате [TextureFormat::Rgba8UnormSrgb]
+fn load_texture(path: &Path) -> Result<(UVec2, Vec<u8>), String> {
+    let extension = path.extension().and_then(|ext| ext.to_str()).unwrap_or_default();
+    let bytes = std::fs::read(path).map_err(|err| err.to_string())?;
+    let image = Image::from_buffer(&bytes, ImageType::Extension(extension), CompressedImageFormats::NONE, true)
+        .map_err(|err| err.to_string())?;
+    let image = image.convert(TextureFormat::Rgba8UnormSrgb).ok_or("unsupported texture format")?;
+    Ok((image.size().as_uvec2(), image.data))
+}
+
+/// Текстура-заглушка в виде шахматной доски
+fn missing_texture(size: UVec2) -> Vec<u8> {
+    let mut data = Vec::with_capacity((size.x * size.y * 4) as usize);
+    for y in 0..size.y {
+        for x in 0..size.x {
+            let is_magenta = (x * 2 < size.x) == (y * 2 < size.y);
+            data.extend_from_slice(if is_magenta { &[255, 0, 255, 255] } else { &[0, 0, 0, 255] });
+        }
+    }
+    data
+}
+
+/// Раскладывает тайлы одинакового размера по квадратной сетке.
+///
+/// Возвращает размер изображения, его пиксели и UV прямоугольники тайлов. Прямоугольники сужены на половину
+/// текселя, чтобы на границах граней не было видно соседних тайлов
+fn pack_tiles(tile_size: UVec2, tiles: &[Vec<u8>]) -> (UVec2, Vec<u8>, Vec<Rect>) {
+    let columns = (tiles.len() as f32).sqrt().ceil().max(1.) as u32;
+    let rows = (tiles.len() as u32).div_ceil(columns).max(1);
+    let size = UVec2::new(columns, rows) * tile_size;
+
+    let row_bytes = (tile_size.x * 4) as usize;
+    let mut data = vec![0; (size.x * size.y * 4) as usize];
+    let mut rects = Vec::with_capacity(tiles.len());
+    for (index, tile) in tiles.iter().enumerate() {
+        let origin = UVec2::new(index as u32 % columns, index as u32 / columns) * tile_size;
+        for y in 0..tile_size.y {
+            let start = (((origin.y + y) * size.x + origin.x) * 4) as usize;
+            let tile_start = y as usize * row_bytes;
+            data[start..start + row_bytes].copy_from_slice(&tile[tile_start..tile_start + row_bytes]);
+        }
+
+        let min = origin.as_vec2() + 0.5;
+        let max = (origin + tile_size).as_vec2() - 0.5;
+        let scale = vec2(1. / size.x as f32, 1. / size.y as f32);
+        rects.push(Rect::from_corners(min * scale, max * scale));
+    }
+    (size, data, rects)
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::math::UVec2;
+    use crate::logic::block::{BlockDefinition, BlockId, BlockRegistry};
+    use crate::render::AbsoluteBlockFaceDirection;
+    use crate::render::block_texture_atlas::{BlockTextureAtlas, pack_tiles};
+
+    #[test]
+    fn pack_tiles_into_grid() {
+        let tiles: Vec<Vec<u8>> = (0..3).map(|i| vec![i; 2 * 2 * 4]).collect();
+        let (size, data, rects) = pack_tiles(UVec2::splat(2), &tiles);
+        assert_eq!(size, UVec2::splat(4));
+
+        // Третий тайл в левом нижнем углу, правый нижний угол пустой
+        let pixel = |x: u32, y: u32| data[((y * size.x + x) * 4) as usize];
+        assert_eq!(pixel(0, 0), 0);
+        assert_eq!(pixel(3, 1), 1);
+        assert_eq!(pixel(1, 2), 2);
+        assert_eq!(pixel(3, 3), 0);
+
+        for (i, a) in rects.iter().enumerate() {
+            assert!(a.min.cmpge(bevy::math::Vec2::ZERO).all() && a.max.cmple(bevy::math::Vec2::ONE).all());
+            for b in rects.iter().skip(i + 1) {
+                assert!(a.intersect(*b).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn missing_textures_use_placeholder() {
+        let mut stone = BlockDefinition { id: 1, name: "stone".to_string(), ..Default::default() };
+        stone.textures.all = Some("stone.png".to_string());
+        let registry = BlockRegistry::new([stone]).unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let (atlas, image) = BlockTextureAtlas::build(&registry, dir.path());
+        assert_eq!(image.size().x, image.size().y);
+
+        // Текстура не найдена, поэтому и камень и неизвестный блок используют заглушку
+        let stone_rect = atlas.get_face_rect(BlockId(1), AbsoluteBlockFaceDirection::PosZ);
+        let unknown_rect = atlas.get_face_rect(BlockId(5), AbsoluteBlockFaceDirection::PosZ);
+        assert_eq!(stone_rect, unknown_rect);
+    }
+}
@@ -1,26 +1,139 @@
-use std::sync::RwLock;
-use bevy::prelude::Mesh;
+use bevy::math::{IVec3, Rect, UVec3, Vec3};
+use bevy::prelude::{Mesh, Resource};
 use strum::IntoEnumIterator;
-use chunk::{AbsoluteBlockPos, ChunkBlockPos, ChunkPos};
-use crate::logic::chunk::{Chunk, ChunkMap};
-use crate::render::{AbsoluteBlockFaceDirection, MeshBuilder};
+use chunk::{CHUNK_SIZE, ChunkBlockPos};
+use crate::logic::block::{Block, BlockRegistry};
+use crate::render::{AbsoluteBlockFaceDirection, BlockTextureAtlas, FaceShading, MeshBuilder};
+use crate::render::chunk_mesh_snapshot::ChunkMeshSnapshot;
 
-/// Строит [Mesh] для чанка
-pub fn build_chunk_mesh(chunk_map: &ChunkMap, chunk: &Chunk, chunk_pos: ChunkPos) -> Mesh {
+/// Способ построения меша чанка
+#[derive(Resource, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MeshingMode {
+    /// Каждая видимая грань блока добавляется в меш отдельным прямоугольником
+    Naive,
+
+    /// Соседние видимые грани лежащие в одной плоскости и имеющие одну текстуру объединяются в прямоугольники
+    /// максимального размера, это в разы уменьшает количество вершин
+    #[default]
+    Greedy,
+}
+
+/// Строит [Mesh] для чанка по его снимку
+pub fn build_chunk_mesh(
+    mode: MeshingMode,
+    registry: &BlockRegistry,
+    atlas: &BlockTextureAtlas,
+    snapshot: &ChunkMeshSnapshot,
+) -> Mesh {
+    let context = MeshingContext { registry, snapshot };
+    match mode {
+        MeshingMode::Naive => { build_naive_chunk_mesh(&context, atlas) }
+        MeshingMode::Greedy => { build_greedy_chunk_mesh(&context, atlas) }
+    }
+}
+
+/// Чанк и загруженный мир вокруг него
+struct MeshingContext<'a> {
+    registry: &'a BlockRegistry,
+    snapshot: &'a ChunkMeshSnapshot,
+}
+
+fn build_naive_chunk_mesh(context: &MeshingContext, atlas: &BlockTextureAtlas) -> Mesh {
     let mut builder = MeshBuilder::new();
 
     // Итерируемся по всем блокам
-    for (block_pos, block) in chunk.into_iter() {
-        if let Some(_) = block {
-            // Устанавливаем координаты блока в билдер (теперь добавленные меши будут автоматически
-            // сдвинуты на эту величину
-            builder.set_transition(block_pos.into());
-
-            // Проходимся по всем граням блока
-            for face_dir in AbsoluteBlockFaceDirection::iter() {
-                // Если блок не имеет соседа со стороны проверяемой грани, то добавляем эту грань в меш
-                if !is_need_to_render_face(chunk_map, chunk, chunk_pos, block_pos, face_dir) {
-                    builder.add_mesh_data(face_dir);
+    for (block_pos, block) in context.snapshot.chunk_blocks() {
+        // Устанавливаем координаты блока в билдер (теперь добавленные меши будут автоматически
+        // сдвинуты на эту величину
+        builder.set_transition(block_pos.into());
+
+        // Проходимся по всем граням блока
+        for face_dir in AbsoluteBlockFaceDirection::iter() {
+            // Если блок не закрыт непрозрачным соседом со стороны проверяемой грани, то добавляем эту грань в меш
+            if !context.is_need_to_render_face(block_pos, face_dir) {
+                let rect = atlas.get_face_rect(block.id(), face_dir);
+                let shading = context.get_face_shading(block_pos, face_dir);
+                builder.add_mesh_data(face_dir.quad(Vec3::ONE, rect, shading));
+            }
+        }
+    }
+    builder.build()
+}
+
+/// Тайл атласа и освещение вершин грани, см [build_greedy_chunk_mesh]
+type GreedyFace = (Rect, FaceShading);
+
+fn build_greedy_chunk_mesh(context: &MeshingContext, atlas: &BlockTextureAtlas) -> Mesh {
+    let mut builder = MeshBuilder::new();
+
+    for face_dir in AbsoluteBlockFaceDirection::iter() {
+        let normal: IVec3 = face_dir.into();
+        let normal_axis = normal.abs().to_array().iter().position(|value| *value == 1).unwrap();
+        // Оси плоскости грани, i - строки маски, j - столбцы
+        let (i_axis, j_axis) = match normal_axis {
+            0 => { (1, 2) }
+            1 => { (0, 2) }
+            _ => { (0, 1) }
+        };
+
+        // Проходим чанк слоями перпендикулярными нормали грани
+        for layer in 0..CHUNK_SIZE {
+            // Маска видимых граней слоя, для каждой грани хранится ее тайл атласа и освещение вершин,
+            // объединяются только грани у которых они полностью совпадают
+            let mut mask: [[Option<GreedyFace>; CHUNK_SIZE]; CHUNK_SIZE] = [[None; CHUNK_SIZE]; CHUNK_SIZE];
+            for (i, row) in mask.iter_mut().enumerate() {
+                for (j, face) in row.iter_mut().enumerate() {
+                    let mut pos = UVec3::ZERO;
+                    pos[normal_axis] = layer as u32;
+                    pos[i_axis] = i as u32;
+                    pos[j_axis] = j as u32;
+                    let block_pos: ChunkBlockPos = pos.try_into().unwrap();
+                    if let Some(block) = context.block(block_pos) {
+                        if !context.is_need_to_render_face(block_pos, face_dir) {
+                            let rect = atlas.get_face_rect(block.id(), face_dir);
+                            *face = Some((rect, context.get_face_shading(block_pos, face_dir)));
+                        }
+                    }
+                }
+            }
+
+            for i in 0..CHUNK_SIZE {
+                let mut j = 0;
+                while j < CHUNK_SIZE {
+                    let face = match mask[i][j] {
+                        None => {
+                            j += 1;
+                            continue;
+                        }
+                        Some(face) => { face }
+                    };
+
+                    // Расширяем прямоугольник вдоль строки, затем добавляем следующие строки пока они целиком совпадают
+                    let mut width = 1;
+                    while j + width < CHUNK_SIZE && mask[i][j + width] == Some(face) {
+                        width += 1;
+                    }
+                    let mut height = 1;
+                    while i + height < CHUNK_SIZE
+                        && mask[i + height][j..j + width].iter().all(|other| *other == Some(face)) {
+                        height += 1;
+                    }
+                    for row in mask[i..i + height].iter_mut() {
+                        row[j..j + width].fill(None);
+                    }
+
+                    let mut origin = Vec3::ZERO;
+                    origin[normal_axis] = layer as f32;
+                    origin[i_axis] = i as f32;
+                    origin[j_axis] = j as f32;
+                    let mut size = Vec3::ONE;
+                    size[i_axis] = height as f32;
+                    size[j_axis] = width as f32;
+
+                    builder.set_transition(origin);
+                    let (rect, shading) = face;
+                    builder.add_mesh_data(face_dir.quad(size, rect, shading));
+                    j += width;
                 }
             }
         }
@@ -28,34 +141,264 @@ pub fn build_chunk_mesh(chunk_map: &ChunkMap, chunk: &Chunk, chunk_pos: ChunkPos
     builder.build()
 }
 
-/// Возвращает нужно ли рендерить данную грань блока
-fn is_need_to_render_face(
-    chunk_map: &ChunkMap,
-    chunk: &Chunk,
-    chunk_pos: ChunkPos,
-    block_pos: ChunkBlockPos,
-    face_dir: AbsoluteBlockFaceDirection,
-) -> bool {
-    // Вычисляем глобальные координаты блока соседствующего с данным с переданной стороны
-    let global_pos: AbsoluteBlockPos = chunk_pos + block_pos + face_dir;
-
-    if let Ok(local_pos) = chunk_pos.try_global_pos_into_chunk_pos(global_pos) {
-        // Соседний блок находится в текущем чанке
-        chunk[&local_pos].is_some()
-    } else {
-        // Соседний блок находится в соседнем чанке
-        let neighbor_chunk_pos = ChunkPos::from_global_coord(*global_pos);
-        let chunk_map = chunk_map.read().unwrap();
-        let neighbor_chunk = chunk_map.get(&neighbor_chunk_pos);
-        match neighbor_chunk {
-            None => {
-                // Если соседний чанк не загружен то не рендерим грани блоков обращенных к нему
-                true
-            }
-            Some(chunk) => {
-                let block_pos = neighbor_chunk_pos.try_global_pos_into_chunk_pos(global_pos).unwrap();
-                chunk.read().unwrap()[&block_pos].is_some()
-            }
-        }
-    }
-}
\ No newline at end of file
+impl<'a> MeshingContext<'a> {
+    fn block(&self, block_pos: ChunkBlockPos) -> Option<Block> {
+        self.snapshot.block(block_pos.as_ivec3()).flatten()
+    }
+
+    /// Возвращает нужно ли рендерить данную грань блока
+    fn is_need_to_render_face(&self, block_pos: ChunkBlockPos, face_dir: AbsoluteBlockFaceDirection) -> bool {
+        let normal: IVec3 = face_dir.into();
+        // Если соседний чанк не загружен то не рендерим грани блоков обращенных к нему
+        self.is_opaque_at(block_pos.as_ivec3() + normal).unwrap_or(true)
+    }
+
+    /// Возвращает освещение вершин грани блока в порядке [AbsoluteBlockFaceDirection::get_vertex_positions].
+    ///
+    /// Затенение от 0 (вершина в углу между тремя блоками) до 3 (соседних блоков нет), см [crate::render::AO_FACTORS].
+    /// Освещенность вершины - средняя освещенность прозрачных блоков вокруг нее в слое перед гранью
+    fn get_face_shading(&self, block_pos: ChunkBlockPos, face_dir: AbsoluteBlockFaceDirection) -> FaceShading {
+        let normal: IVec3 = face_dir.into();
+        // Блоки влияющие на освещение лежат в слое перед гранью
+        let front = block_pos.as_ivec3() + normal;
+        let is_opaque = |pos: IVec3| -> bool { self.is_opaque_at(pos).unwrap_or(false) };
+
+        let mut ao = [0; 4];
+        let mut light = [[0.; 2]; 4];
+        for (index, vertex) in face_dir.get_vertex_positions().iter().enumerate() {
+            // Для каждой вершины проверяем два соседних по сторонам блока и один по диагонали
+            let mut sides = [IVec3::ZERO; 2];
+            let mut side_index = 0;
+            for axis in 0..3 {
+                if normal[axis] != 0 {
+                    continue;
+                }
+                sides[side_index][axis] = if vertex[axis] > 0.5 { 1 } else { -1 };
+                side_index += 1;
+            }
+            let side1 = is_opaque(front + sides[0]);
+            let side2 = is_opaque(front + sides[1]);
+            let corner = is_opaque(front + sides[0] + sides[1]);
+            ao[index] = if side1 && side2 { 0 } else { 3 - side1 as u8 - side2 as u8 - corner as u8 };
+
+            // Свет не проходит по диагонали между двумя непрозрачными блоками
+            let samples = [
+                (front, true),
+                (front + sides[0], !side1),
+                (front + sides[1], !side2),
+                (front + sides[0] + sides[1], !(corner || side1 && side2)),
+            ];
+            let mut count = 0;
+            for (pos, _) in samples.iter().filter(|(_, is_visible)| *is_visible) {
+                if let Some(sample) = self.light_at(*pos) {
+                    light[index][0] += sample[0] as f32;
+                    light[index][1] += sample[1] as f32;
+                    count += 1;
+                }
+            }
+            if count > 0 {
+                light[index] = light[index].map(|value| value / count as f32);
+            }
+        }
+        FaceShading { ao, light }
+    }
+
+    /// Возвращает закрывает ли блок по переданным координатам относительно чанка соседние грани, координаты могут
+    /// выходить за границы чанка на один блок. Если нужный чанк не загружен возвращает [None]
+    fn is_opaque_at(&self, pos: IVec3) -> Option<bool> {
+        self.snapshot.block(pos).map(|block| self.registry.is_opaque(&block))
+    }
+
+    /// Возвращает свет неба и свет блоков по переданным координатам относительно чанка, координаты могут
+    /// выходить за границы чанка на один блок. Если нужный чанк не загружен возвращает [None]
+    fn light_at(&self, pos: IVec3) -> Option<[u8; 2]> {
+        self.snapshot.light(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+    use bevy::math::{ivec3, uvec3, Vec3};
+    use bevy::prelude::Mesh;
+    use bevy::render::mesh::VertexAttributeValues;
+    use crate::logic::block::{Block, BlockDefinition, BlockId, BlockRegistry};
+    use crate::logic::chunk::{Chunk, ChunkMap};
+    use crate::logic::light::{compute_chunk_light, LightMap, SkyExposure};
+    use crate::render::{BlockTextureAtlas, MeshingMode};
+    use crate::render::AbsoluteBlockFaceDirection;
+    use crate::render::chunk_mesh_builder::{build_chunk_mesh, MeshingContext};
+    use crate::render::chunk_mesh_snapshot::ChunkMeshSnapshot;
+
+    fn registry() -> BlockRegistry {
+        let definitions = [
+            BlockDefinition { id: 1, name: "stone".to_string(), ..Default::default() },
+            BlockDefinition { id: 2, name: "glass".to_string(), transparent: true, ..Default::default() },
+            BlockDefinition {
+                id: 3,
+                name: "torch".to_string(),
+                transparent: true,
+                light_emission: 14,
+                ..Default::default()
+            },
+        ];
+        BlockRegistry::new(definitions).unwrap()
+    }
+
+    /// Снимок чанка без загруженных соседей
+    fn snapshot(registry: &BlockRegistry, chunk: &Chunk) -> ChunkMeshSnapshot {
+        let pos = ivec3(0, 0, 0).into();
+        let (chunk_map, light_map) = (ChunkMap::default(), LightMap::default());
+        let light = compute_chunk_light(registry, chunk, pos, SkyExposure::default());
+        light_map.insert(pos, Arc::new(RwLock::new(light)));
+        chunk_map.insert(pos, Arc::new(RwLock::new(chunk.clone())));
+        ChunkMeshSnapshot::new(&chunk_map, &light_map, pos).unwrap()
+    }
+
+    fn build(mode: MeshingMode, chunk: &Chunk) -> Mesh {
+        let registry = registry();
+        let dir = tempfile::tempdir().unwrap();
+        let (atlas, _) = BlockTextureAtlas::build(&registry, dir.path());
+        build_chunk_mesh(mode, &registry, &atlas, &snapshot(&registry, chunk))
+    }
+
+    fn quads(mesh: &Mesh) -> Vec<[Vec3; 4]> {
+        let Some(VertexAttributeValues::Float32x3(positions)) = mesh.attribute(Mesh::ATTRIBUTE_POSITION) else {
+            panic!("Mesh has no positions")
+        };
+        positions.chunks(4).map(|quad| [0, 1, 2, 3].map(|i| Vec3::from(quad[i]))).collect()
+    }
+
+    fn area(mesh: &Mesh) -> f32 {
+        quads(mesh).iter().map(|[a, b, _, d]| (*b - *a).cross(*d - *a).length()).sum()
+    }
+
+    /// Чанк с гладкой поверхностью на высоте 4 и несколькими отдельно стоящими блоками над ней
+    fn test_chunk() -> Chunk {
+        let mut chunk = Chunk::new(());
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..4 {
+                    chunk.set(&uvec3(x, y, z).try_into().unwrap(), Some(Block::new(BlockId(1))));
+                }
+            }
+        }
+        for (x, y) in [(2, 3), (3, 3), (10, 12)] {
+            chunk.set(&uvec3(x, y, 4).try_into().unwrap(), Some(Block::new(BlockId(1))));
+        }
+        chunk.set(&uvec3(7, 7, 4).try_into().unwrap(), Some(Block::new(BlockId(2))));
+        chunk
+    }
+
+    #[test]
+    fn greedy_merges_flat_surface() {
+        let mut chunk = Chunk::new(());
+        for x in 0..16 {
+            for y in 0..16 {
+                chunk.set(&uvec3(x, y, 0).try_into().unwrap(), Some(Block::new(BlockId(1))));
+            }
+        }
+        // Соседние чанки не загружены, поэтому видны только верхние грани
+        assert_eq!(quads(&build(MeshingMode::Naive, &chunk)).len(), 256);
+        assert_eq!(quads(&build(MeshingMode::Greedy, &chunk)).len(), 1);
+    }
+
+    #[test]
+    fn greedy_covers_same_area_as_naive() {
+        let chunk = test_chunk();
+        let naive = build(MeshingMode::Naive, &chunk);
+        let greedy = build(MeshingMode::Greedy, &chunk);
+        assert!(quads(&greedy).len() < quads(&naive).len());
+        assert_eq!(area(&greedy), area(&naive));
+    }
+
+    #[test]
+    fn greedy_uvs_tile_per_block() {
+        // Ряд из 3 блоков вдоль x, верхняя и боковые вдоль ряда грани сливаются в прямоугольники 3x1
+        let mut chunk = Chunk::new(());
+        for x in 5..8 {
+            chunk.set(&uvec3(x, 5, 5).try_into().unwrap(), Some(Block::new(BlockId(1))));
+        }
+        let greedy = build(MeshingMode::Greedy, &chunk);
+        let Some(VertexAttributeValues::Float32x2(uvs)) = greedy.attribute(Mesh::ATTRIBUTE_UV_0) else {
+            panic!("Mesh has no uvs")
+        };
+        let quads = quads(&greedy);
+        assert_eq!(quads.len(), 6);
+
+        // Размах координат текстуры по каждой оси равен размеру прямоугольника в блоках вдоль этой оси
+        let span = |uvs: &[[f32; 2]], axis: usize| {
+            let values = uvs.iter().map(|uv| uv[axis]);
+            values.clone().fold(f32::MIN, f32::max) - values.fold(f32::MAX, f32::min)
+        };
+        let mut long_quads = 0;
+        for (quad, uvs) in quads.iter().zip(uvs.chunks(4)) {
+            let extent = quad.iter().fold(Vec3::ZERO, |max, pos| max.max(*pos - quad[0]))
+                - quad.iter().fold(Vec3::ZERO, |min, pos| min.min(*pos - quad[0]));
+            let (u, v) = (span(uvs, 0), span(uvs, 1));
+            if extent.x == 3. {
+                // Грани вдоль ряда: u идет вдоль x, v поперек ряда или по высоте
+                assert_eq!((u, v), (3., 1.));
+                long_quads += 1;
+            } else {
+                assert_eq!((u, v), (1., 1.));
+            }
+        }
+        assert_eq!(long_quads, 4);
+    }
+
+    #[test]
+    fn ao_darkens_vertices_near_blocks() {
+        let mut chunk = Chunk::new(());
+        for x in 0..16 {
+            for y in 0..16 {
+                chunk.set(&uvec3(x, y, 0).try_into().unwrap(), Some(Block::new(BlockId(1))));
+            }
+        }
+        chunk.set(&uvec3(5, 5, 1).try_into().unwrap(), Some(Block::new(BlockId(1))));
+        chunk.set(&uvec3(7, 7, 1).try_into().unwrap(), Some(Block::new(BlockId(2))));
+
+        let registry = registry();
+        let snapshot = snapshot(&registry, &chunk);
+        let context = MeshingContext { registry: &registry, snapshot: &snapshot };
+        let ao = |x: u32, y: u32| {
+            context.get_face_shading(uvec3(x, y, 0).try_into().unwrap(), AbsoluteBlockFaceDirection::PosZ).ao
+        };
+        // Блок стоит со стороны вершин с x = 0
+        assert_eq!(ao(6, 5), [2, 3, 3, 2]);
+        // Блок стоит по диагонали от вершины с x = 1, y = 1
+        assert_eq!(ao(4, 4), [3, 3, 2, 3]);
+        // Прозрачные блоки не затеняют
+        assert_eq!(ao(8, 7), [3, 3, 3, 3]);
+        assert_eq!(ao(0, 0), [3, 3, 3, 3]);
+    }
+
+    #[test]
+    fn block_light_is_baked_into_vertex_colors() {
+        let mut chunk = Chunk::new(());
+        for x in 0..16 {
+            for y in 0..16 {
+                chunk.set(&uvec3(x, y, 0).try_into().unwrap(), Some(Block::new(BlockId(1))));
+            }
+        }
+        chunk.set(&uvec3(8, 8, 1).try_into().unwrap(), Some(Block::new(BlockId(3))));
+
+        let mesh = build(MeshingMode::Greedy, &chunk);
+        let Some(VertexAttributeValues::Float32x4(colors)) = mesh.attribute(Mesh::ATTRIBUTE_COLOR) else {
+            panic!("Mesh has no colors")
+        };
+        // Ищем вершину среди граней поверхности, грани факела лежат выше
+        let block_light = |vertex: Vec3| -> f32 {
+            let index = quads(&mesh).iter().enumerate()
+                .filter(|(_, quad)| quad.iter().all(|pos| pos.z == 1.))
+                .find_map(|(index, quad)| quad.iter().position(|pos| *pos == vertex).map(|i| index * 4 + i))
+                .unwrap();
+            colors[index][1]
+        };
+        // Вершина у факела освещена четырьмя блоками с уровнями 14, 13, 13 и 12
+        assert_eq!(block_light(Vec3::new(8., 8., 1.)), 13. / 15.);
+        assert_eq!(block_light(Vec3::new(0., 0., 1.)), 0.);
+        // Свет меняется по поверхности, поэтому она больше не объединяется в один прямоугольник
+        assert!(quads(&mesh).len() > 1);
+    }
+}
@@ -0,0 +1,151 @@
+use bevy::math::{IVec3, ivec3, uvec3};
+use chunk::{CHUNK_SIZE, ChunkBlockPos, ChunkNeighbors, ChunkPos};
+use crate::logic::block::Block;
+use crate::logic::chunk::ChunkMap;
+use crate::logic::light::{LightKind, LightMap};
+
+/// Размер снимка по каждой оси: чанк и по одному слою блоков соседних чанков с каждой стороны
+const SNAPSHOT_SIZE: usize = CHUNK_SIZE + 2;
+
+/// Неизменяемый снимок чанка для построения меша: блоки и освещенность чанка вместе с граничными слоями 26
+/// соседних чанков, 18³ блоков. Снимается один раз при запуске задачи, дальше меш строится без блокировок.
+///
+/// Запоминает соседей которые не были загружены, после их загрузки меш нужно перестроить
+pub struct ChunkMeshSnapshot {
+    /// Блоки по координатам относительно чанка сдвинутым на 1, [None] для блоков незагруженных соседей
+    blocks: Vec<Option<Option<Block>>>,
+
+    /// Свет неба и свет блоков, [None] для блоков незагруженных соседей
+    light: Vec<Option<[u8; 2]>>,
+
+    /// Соседние чанки которые не были загружены при снятии снимка
+    missing_neighbors: Vec<ChunkPos>,
+}
+
+impl ChunkMeshSnapshot {
+    /// Снимает снимок чанка `chunk_pos`, [None] если сам чанк не загружен
+    pub fn new(chunk_map: &ChunkMap, light_map: &LightMap, chunk_pos: ChunkPos) -> Option<Self> {
+        let chunks = chunk_map.neighbourhood(chunk_pos);
+        let lights = light_map.neighbourhood(chunk_pos);
+        lights.center()?;
+        let mut snapshot = Self {
+            blocks: vec![None; SNAPSHOT_SIZE.pow(3)],
+            light: vec![None; SNAPSHOT_SIZE.pow(3)],
+            missing_neighbors: Vec::new(),
+        };
+
+        for neighbor_pos in std::iter::once(chunk_pos).chain(chunk_pos.get_neighbors(ChunkNeighbors::All)) {
+            let offset = *neighbor_pos - *chunk_pos;
+            let (Some(chunk), Some(light)) = (chunks.get(&neighbor_pos), lights.get(&neighbor_pos)) else {
+                if offset == IVec3::ZERO {
+                    return None;
+                }
+                snapshot.missing_neighbors.push(neighbor_pos);
+                continue;
+            };
+            let chunk = chunk.read().unwrap();
+            let light = light.read().unwrap();
+
+            // От соседа в снимок попадает только прилегающий к чанку слой
+            let range = |axis: usize| match offset[axis] {
+                -1 => { -1..0 }
+                0 => { 0..CHUNK_SIZE as i32 }
+                _ => { CHUNK_SIZE as i32..CHUNK_SIZE as i32 + 1 }
+            };
+            for z in range(2) {
+                for y in range(1) {
+                    for x in range(0) {
+                        let pos = ivec3(x, y, z);
+                        let local_pos = (pos - offset * CHUNK_SIZE as i32).try_into().unwrap();
+                        let index = index(pos).unwrap();
+                        snapshot.blocks[index] = Some(chunk[&local_pos]);
+                        let sky = light.get(LightKind::Sky, &local_pos);
+                        snapshot.light[index] = Some([sky, light.get(LightKind::Block, &local_pos)]);
+                    }
+                }
+            }
+        }
+        Some(snapshot)
+    }
+
+    /// Возвращает блок по координатам относительно чанка от -1 до [CHUNK_SIZE] включительно,
+    /// [None] если блок лежит в незагруженном соседе
+    pub fn block(&self, pos: IVec3) -> Option<Option<Block>> {
+        self.blocks[index(pos)?]
+    }
+
+    /// Непустые блоки самого чанка
+    pub fn chunk_blocks(&self) -> impl Iterator<Item=(ChunkBlockPos, Block)> + '_ {
+        let size = CHUNK_SIZE as u32;
+        (0..size.pow(3)).filter_map(move |index| {
+            let pos = uvec3(index % size, index / size % size, index / size / size);
+            let block = self.block(pos.as_ivec3()).flatten()?;
+            Some((pos.try_into().unwrap(), block))
+        })
+    }
+
+    /// Возвращает свет неба и свет блоков по координатам относительно чанка, см [Self::block]
+    pub fn light(&self, pos: IVec3) -> Option<[u8; 2]> {
+        self.light[index(pos)?]
+    }
+
+    /// Соседние чанки которые не были загружены при снятии снимка
+    pub fn missing_neighbors(&self) -> &[ChunkPos] {
+        &self.missing_neighbors
+    }
+}
+
+fn index(pos: IVec3) -> Option<usize> {
+    let pos = pos + IVec3::ONE;
+    if pos.min_element() < 0 || pos.max_element() >= SNAPSHOT_SIZE as i32 {
+        return None;
+    }
+    let pos = pos.as_uvec3();
+    Some(((pos.z as usize * SNAPSHOT_SIZE) + pos.y as usize) * SNAPSHOT_SIZE + pos.x as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, RwLock};
+    use bevy::math::{ivec3, uvec3};
+    use chunk::ChunkPos;
+    use crate::logic::block::{Block, BlockId};
+    use crate::logic::chunk::{Chunk, ChunkMap};
+    use crate::logic::light::{ChunkLight, LightMap};
+    use crate::render::chunk_mesh_snapshot::ChunkMeshSnapshot;
+
+    fn add_chunk(chunk_map: &ChunkMap, light_map: &LightMap, pos: ChunkPos, chunk: Chunk) {
+        light_map.insert(pos, Arc::new(RwLock::new(ChunkLight::new())));
+        chunk_map.insert(pos, Arc::new(RwLock::new(chunk)));
+    }
+
+    #[test]
+    fn snapshot_contains_neighbor_borders() {
+        let (chunk_map, light_map) = (ChunkMap::default(), LightMap::default());
+        assert!(ChunkMeshSnapshot::new(&chunk_map, &light_map, ivec3(0, 0, 0).into()).is_none());
+
+        let stone = Some(Block::new(BlockId(1)));
+        let mut chunk = Chunk::new(());
+        chunk.set(&uvec3(0, 5, 15).try_into().unwrap(), stone);
+        add_chunk(&chunk_map, &light_map, ivec3(0, 0, 0).into(), chunk);
+        let mut neighbor = Chunk::new(());
+        neighbor.set(&uvec3(15, 5, 15).try_into().unwrap(), stone);
+        neighbor.set(&uvec3(14, 5, 15).try_into().unwrap(), stone);
+        add_chunk(&chunk_map, &light_map, ivec3(-1, 0, 0).into(), neighbor);
+        add_chunk(&chunk_map, &light_map, ivec3(1, 1, 1).into(), Chunk::new(()));
+
+        let snapshot = ChunkMeshSnapshot::new(&chunk_map, &light_map, ivec3(0, 0, 0).into()).unwrap();
+        assert_eq!(snapshot.block(ivec3(0, 5, 15)), Some(stone));
+        assert_eq!(snapshot.block(ivec3(-1, 5, 15)), Some(stone));
+        assert_eq!(snapshot.block(ivec3(16, 16, 16)), Some(None));
+        assert_eq!(snapshot.light(ivec3(-1, 5, 15)), Some([0, 0]));
+        // Незагруженные соседи и блоки за пределами граничного слоя отсутствуют в снимке
+        assert_eq!(snapshot.block(ivec3(16, 5, 15)), None);
+        assert_eq!(snapshot.light(ivec3(16, 5, 15)), None);
+        assert_eq!(snapshot.block(ivec3(-2, 5, 15)), None);
+
+        assert_eq!(snapshot.missing_neighbors().len(), 24);
+        assert!(snapshot.missing_neighbors().contains(&ivec3(1, 0, 0).into()));
+        assert!(!snapshot.missing_neighbors().contains(&ivec3(-1, 0, 0).into()));
+    }
+}
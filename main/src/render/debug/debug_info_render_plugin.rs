@@ -5,6 +5,8 @@ use std::fmt::Write;
 use bytesize::ByteSize;
 use memory_stats::memory_stats;
 use crate::camera::PlayerCamera;
+use crate::logic::world::WorldPlugin;
+use crate::render::ChunkRenderPlugin;
 
 /// Отображает дополнительную дебажную информацию
 pub struct DebugInfoRenderPlugin;
@@ -70,16 +72,26 @@ fn update_debug_info(
 
     let mesh_count = mesh_query.iter().len() as u32;
 
+    let value = |id| diagnostics.get(id).and_then(|diagnostic| diagnostic.value()).unwrap_or(0.) as u32;
+    // Отложенная на следующие кадры работа, см [crate::logic::world::ChunkIntegrationBudget] и
+    // [crate::render::MeshUploadBudget]
+    let chunks_to_integrate = value(WorldPlugin::CHUNKS_TO_INTEGRATE);
+    let meshes_to_upload = value(ChunkRenderPlugin::MESHES_TO_UPLOAD);
+    let ticking_chunks = value(WorldPlugin::TICKING_CHUNKS);
+
     let text = &mut text_query.single_mut().sections[0].value;
     text.clear();
     write!(
         text,
-        "  FPS:{:3} x:{:.01} y:{:.01} z:{:.01}, mem={}, e={}, m={}",
+        "  FPS:{:3} x:{:.01} y:{:.01} z:{:.01}, mem={}, e={}, m={}, ci={}, mu={}, tc={}",
         fps,
         player_coord.x, player_coord.y, player_coord.z,
         mem,
         entity_count,
         mesh_count,
+        chunks_to_integrate,
+        meshes_to_upload,
+        ticking_chunks,
     )
         .unwrap();
 }
\ No newline at end of file
@@ -1,5 +1,6 @@
 use bevy::prelude::*;
 use bevy::render::mesh::{Indices, PrimitiveTopology};
+use crate::render::{ATTRIBUTE_AO, ATTRIBUTE_TILE_RECT};
 
 /// Часть меша, используется в [MeshBuilder]
 pub trait MeshPart {
@@ -7,6 +8,9 @@ pub trait MeshPart {
     fn get_positions(&self) -> &[[f32; 3]];
     fn get_normals(&self) -> &[[f32; 3]];
     fn get_uvs(&self) -> &[[f32; 2]];
+    fn get_tiles(&self) -> &[[f32; 4]];
+    fn get_ao(&self) -> &[f32];
+    fn get_colors(&self) -> &[[f32; 4]];
 }
 
 /// Mesh builder для преобразования набора [MeshPart] в [Mesh]
@@ -24,6 +28,15 @@ pub struct MeshBuilder {
     /// Координаты текстуры вершин вертекса
     uvs: Vec<[f32; 2]>,
 
+    /// Тайлы атласа текстур вершин вертекса, см [ATTRIBUTE_TILE_RECT]
+    tiles: Vec<[f32; 4]>,
+
+    /// Множители яркости вершин вертекса, см [ATTRIBUTE_AO]
+    ao: Vec<f32>,
+
+    /// Освещенность вершин вертекса, см [crate::render::FaceShading]
+    colors: Vec<[f32; 4]>,
+
     /// Сдвиг
     /// Этот сдвиг будет применен ко всем [MeshPart::get_positions] при добавлении в [Self]
     transition: Vec3,
@@ -45,13 +58,23 @@ impl MeshBuilder {
         let positions = mesh_data.get_positions();
         let normals = mesh_data.get_normals();
         let uvs = mesh_data.get_uvs();
+        let tiles = mesh_data.get_tiles();
+        let ao = mesh_data.get_ao();
+        let colors = mesh_data.get_colors();
 
-        if positions.len() != normals.len() || positions.len() != uvs.len() {
+        if positions.len() != normals.len()
+            || positions.len() != uvs.len()
+            || positions.len() != tiles.len()
+            || positions.len() != ao.len()
+            || positions.len() != colors.len() {
             panic!(
-                "Incorrect vectors length, positions={}, normals={}, uvs={}",
+                "Incorrect vectors length, positions={}, normals={}, uvs={}, tiles={}, ao={}, colors={}",
                 positions.len(),
                 normals.len(),
                 uvs.len(),
+                tiles.len(),
+                ao.len(),
+                colors.len(),
             );
         }
         let translated_positions = positions
@@ -62,6 +85,9 @@ impl MeshBuilder {
         self.positions.extend(translated_positions);
         self.normals.extend(normals);
         self.uvs.extend(uvs);
+        self.tiles.extend(tiles);
+        self.ao.extend(ao);
+        self.colors.extend(colors);
     }
 
     pub fn build(self) -> Mesh {
@@ -69,6 +95,9 @@ impl MeshBuilder {
         mesh.insert_attribute(Mesh::ATTRIBUTE_POSITION, self.positions);
         mesh.insert_attribute(Mesh::ATTRIBUTE_NORMAL, self.normals);
         mesh.insert_attribute(Mesh::ATTRIBUTE_UV_0, self.uvs);
+        mesh.insert_attribute(ATTRIBUTE_TILE_RECT, self.tiles);
+        mesh.insert_attribute(ATTRIBUTE_AO, self.ao);
+        mesh.insert_attribute(Mesh::ATTRIBUTE_COLOR, self.colors);
         mesh.set_indices(Some(Indices::U32(self.indexes)));
         mesh
     }
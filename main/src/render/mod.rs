@@ -4,9 +4,15 @@ mod mesh_builder;
 mod block_face_mesh;
 mod world_render_plugin;
 mod chunk_mesh_builder;
+mod chunk_mesh_snapshot;
 mod world_material_plugin;
+mod block_texture_atlas;
+mod block_material;
 
 pub use mesh_builder::{MeshBuilder, MeshPart};
-pub use block_face_mesh::{AbsoluteBlockFaceDirection};
-pub use world_render_plugin::ChunkRenderPlugin;
+pub use block_face_mesh::{AbsoluteBlockFaceDirection, FaceShading};
+pub use block_material::{BlockMaterial, ATTRIBUTE_AO, ATTRIBUTE_TILE_RECT};
+pub use chunk_mesh_builder::MeshingMode;
+pub use block_texture_atlas::{BlockTextureAtlas, SharedBlockTextureAtlas};
+pub use world_render_plugin::{ChunkRenderPlugin, MeshUploadBudget};
 pub use world_material_plugin::WorldMaterialPlugin;
@@ -1,11 +1,23 @@
 use bevy::app::{App, Plugin};
+use bevy::asset::FileAssetIo;
 use bevy::prelude::*;
+use crate::logic::block::SharedBlockRegistry;
+use crate::render::{BlockMaterial, BlockTextureAtlas, SharedBlockTextureAtlas};
 
+/// Собирает [BlockTextureAtlas] из текстур блоков и создает материал мира, должен быть добавлен после
+/// [crate::logic::block::BlockRegistryPlugin]
 pub struct WorldMaterialPlugin;
 
 impl Plugin for WorldMaterialPlugin {
     fn build(&self, app: &mut App) {
+        let textures_dir = FileAssetIo::get_base_path().join("assets").join("textures").join("blocks");
+        let (atlas, image) = BlockTextureAtlas::build(app.world.resource::<SharedBlockRegistry>(), &textures_dir);
+        let texture = app.world.resource_mut::<Assets<Image>>().add(image);
+
         app
+            .insert_resource(SharedBlockTextureAtlas::new(atlas))
+            .insert_resource(BlockTextureAtlasImage(texture))
+            .add_plugins(MaterialPlugin::<BlockMaterial>::default())
             .add_systems(Startup, load_world_material)
         ;
     }
@@ -13,22 +25,20 @@ impl Plugin for WorldMaterialPlugin {
 
 #[derive(Resource, Deref)]
 pub struct WorldMaterial {
-    material_handle: Handle<StandardMaterial>,
+    material_handle: Handle<BlockMaterial>,
 }
 
+/// Изображение атласа текстур блоков
+#[derive(Resource, Deref)]
+struct BlockTextureAtlasImage(Handle<Image>);
+
 fn load_world_material(
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<BlockMaterial>>,
+    atlas_image: Res<BlockTextureAtlasImage>,
     mut commands: Commands,
 ) {
-    let texture: Handle<Image> = asset_server.load("dirt.png");
-
-    let material = StandardMaterial {
-        base_color_texture: Some(texture),
-        unlit: false,
-        metallic: 0.,
-        reflectance: 0.,
-        ..default()
+    let material = BlockMaterial {
+        atlas: atlas_image.clone(),
     };
 
     let material_handle = materials.add(material);
@@ -38,4 +48,4 @@ fn load_world_material(
     };
 
     commands.insert_resource(world_material);
-}
\ No newline at end of file
+}
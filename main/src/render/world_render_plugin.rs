@@ -1,22 +1,31 @@
 use std::sync::Arc;
+use std::time::Duration;
 use bevy::app::{App, Plugin, Update};
 use bevy::asset::Assets;
-use bevy::pbr::PbrBundle;
+use bevy::diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic};
 use bevy::prelude::*;
 use bevy::tasks::{AsyncComputeTaskPool, Task};
 use bevy::utils::{HashMap, HashSet};
 use futures_lite::future::poll_once;
 use futures_lite::future::block_on;
-use strum::IntoEnumIterator;
-use chunk::{ChunkNeighborDir, ChunkPos};
-use crate::logic::world::{ChunkUpdateEvent, World};
-use crate::render::chunk_mesh_builder::build_chunk_mesh;
+use chunk::ChunkPos;
+use crate::logic::block::SharedBlockRegistry;
+use crate::logic::frame_budget::FrameBudget;
+use crate::logic::world::{ChunkUpdateEvent, TicketKind, World};
+use crate::render::chunk_mesh_builder::{build_chunk_mesh, MeshingMode};
+use crate::render::chunk_mesh_snapshot::ChunkMeshSnapshot;
 use crate::render::world_material_plugin::WorldMaterial;
+use crate::render::SharedBlockTextureAtlas;
 
 /// Отвечает за генерацию [Mesh] для загруженных [Chunk], а так же за обновление [Mesh] при
 /// обновлении [Chunk]
 pub struct ChunkRenderPlugin;
 
+impl ChunkRenderPlugin {
+    /// Число построенных мешей чанков ожидающих загрузки, см [MeshUploadBudget]
+    pub const MESHES_TO_UPLOAD: DiagnosticId = DiagnosticId::from_u128(60233961263402178349470164807356925371);
+}
+
 impl Plugin for ChunkRenderPlugin {
     fn build(&self, app: &mut App) {
         app
@@ -24,14 +33,28 @@ impl Plugin for ChunkRenderPlugin {
             .init_resource::<WorldUnloadChunksQueue>()
             .init_resource::<WorldRenderedChunks>()
             .init_resource::<WorldLoadChunksTasks>()
+            .init_resource::<WorldMissingNeighbors>()
+            .init_resource::<MeshingMode>()
+            .init_resource::<MeshUploadBudget>()
             .add_systems(Update, read_chunk_events)
             .add_systems(Update, start_load_chunks)
             .add_systems(Update, collect_loaded_chunks)
             .add_systems(Update, unload_chunks)
+            .register_diagnostic(Diagnostic::new(Self::MESHES_TO_UPLOAD, "meshes_to_upload", 20))
         ;
     }
 }
 
+/// Сколько построенных мешей чанков загружается за кадр, остальные ждут следующих кадров
+#[derive(Resource, Clone, Copy, Deref, DerefMut)]
+pub struct MeshUploadBudget(pub FrameBudget);
+
+impl Default for MeshUploadBudget {
+    fn default() -> Self {
+        Self(FrameBudget { max_items: 32, max_time: Duration::from_millis(4) })
+    }
+}
+
 /// Чанки которые необходимо выгрузить из памяти
 ///
 /// Мы не можем обойтись без этой коллекции из-за асинхронной природы работы команд. Может произойти ситуация когда
@@ -45,8 +68,58 @@ struct WorldUnloadChunksQueue(HashSet<ChunkPos>);
 #[derive(Resource, Deref, DerefMut, Default)]
 struct WorldLoadChunksQueue(HashSet<ChunkPos>);
 
+/// Меш чанка и соседи которые не были загружены при снятии снимка, [None] если сам чанк успел выгрузиться
+type ChunkMeshTask = Task<Option<(Mesh, Vec<ChunkPos>)>>;
+
 #[derive(Resource, Deref, DerefMut, Default)]
-struct WorldLoadChunksTasks(HashMap<ChunkPos, Task<Mesh>>);
+struct WorldLoadChunksTasks(HashMap<ChunkPos, ChunkMeshTask>);
+
+/// Отрендеренные чанки меши которых построены без части соседей, их нужно перестроить когда сосед загрузится
+#[derive(Resource, Default)]
+struct WorldMissingNeighbors {
+    /// Незагруженный сосед -> чанки которые его ждут
+    waiting: HashMap<ChunkPos, HashSet<ChunkPos>>,
+
+    /// Чанк -> соседи которых он ждет
+    missing: HashMap<ChunkPos, Vec<ChunkPos>>,
+}
+
+impl WorldMissingNeighbors {
+    fn set(&mut self, pos: ChunkPos, missing: Vec<ChunkPos>) {
+        self.remove(&pos);
+        for neighbor in &missing {
+            self.waiting.entry(*neighbor).or_default().insert(pos);
+        }
+        if !missing.is_empty() {
+            self.missing.insert(pos, missing);
+        }
+    }
+
+    fn remove(&mut self, pos: &ChunkPos) {
+        for neighbor in self.missing.remove(pos).unwrap_or_default() {
+            if let Some(waiting) = self.waiting.get_mut(&neighbor) {
+                waiting.remove(pos);
+                if waiting.is_empty() {
+                    self.waiting.remove(&neighbor);
+                }
+            }
+        }
+    }
+
+    /// Возвращает чанки которые ждали загрузки чанка `loaded`, они больше его не ждут
+    fn take_waiting(&mut self, loaded: &ChunkPos) -> HashSet<ChunkPos> {
+        let waiting = self.waiting.remove(loaded).unwrap_or_default();
+        for pos in &waiting {
+            if let Some(missing) = self.missing.get_mut(pos) {
+                missing.retain(|neighbor| neighbor != loaded);
+                if missing.is_empty() {
+                    self.missing.remove(pos);
+                }
+            }
+        }
+        waiting
+    }
+}
 
 /// Отрендеренные чанки.
 ///
@@ -56,65 +129,106 @@ struct WorldLoadChunksTasks(HashMap<ChunkPos, Task<Mesh>>);
 #[derive(Resource, Default, Deref, DerefMut)]
 struct WorldRenderedChunks(HashMap<ChunkPos, Option<Entity>>);
 
-/// Читает события [ChunkUpdateEvent] и управляет очередью загрузки/выгрузки чанков
+/// Читает события [ChunkUpdateEvent] и управляет очередью загрузки/выгрузки чанков.
+///
+/// Отображаются только чанки уровня [TicketKind::Render], см [World::chunk_level]
 fn read_chunk_events(
+    world: Res<World>,
     rendered_chunks: ResMut<WorldRenderedChunks>,
     mut world_load_chunks_queue: ResMut<WorldLoadChunksQueue>,
     mut world_load_chunks_tasks: ResMut<WorldLoadChunksTasks>,
     mut world_unload_chunks_queue: ResMut<WorldUnloadChunksQueue>,
+    mut missing_neighbors: ResMut<WorldMissingNeighbors>,
     mut chunk_event: EventReader<ChunkUpdateEvent>,
 ) {
     // Обновляем состояние render_state добавляя туда чанки которые необходимо загрузить / выгрузить
     for event in chunk_event.iter() {
         match event {
             ChunkUpdateEvent::Loaded(pos) => {
-                world_load_chunks_queue.insert(*pos);
-                if let Some(task) = world_load_chunks_tasks.remove(pos) {
-                    let _ = task.cancel();
-                }
+                if world.chunk_level(pos) == Some(TicketKind::Render) {
+                    world_load_chunks_queue.insert(*pos);
+                    if let Some(task) = world_load_chunks_tasks.remove(pos) {
+                        let _ = task.cancel();
+                    }
 
-                world_unload_chunks_queue.remove(pos);
+                    world_unload_chunks_queue.remove(pos);
+                }
 
-                // Обновляем все чанки вокруг ново загрузившегося если они уже загружены
-                for dir in ChunkNeighborDir::iter() {
-                    if rendered_chunks.contains_key(&(*pos + dir))
-                        || world_load_chunks_tasks.contains_key(&(*pos + dir))
-                    {
-                        world_load_chunks_queue.insert(*pos + dir);
-                        if let Some(task) = world_load_chunks_tasks.remove(pos) {
-                            let _ = task.cancel();
-                        }
-                    }
+                // Перестраиваем только чанки меши которых строились без этого соседа
+                for waiting in missing_neighbors.take_waiting(pos) {
+                    remesh_chunk(waiting, &rendered_chunks, &mut world_load_chunks_queue, &mut world_load_chunks_tasks);
                 }
             }
-            ChunkUpdateEvent::Unloaded(pos) => {
+            ChunkUpdateEvent::LightUpdated(pos) => {
+                remesh_chunk(*pos, &rendered_chunks, &mut world_load_chunks_queue, &mut world_load_chunks_tasks);
+            }
+            ChunkUpdateEvent::BlockChanged(block_pos) => {
+                // Блок у границы чанка закрывает или открывает грани и меняет затенение блоков соседних чанков,
+                // в том числе соседей по ребрам и вершинам
+                let (min, max) = (**block_pos - IVec3::ONE, **block_pos + IVec3::ONE);
+                for pos in ChunkPos::intersecting(min.into(), max.into()) {
+                    remesh_chunk(pos, &rendered_chunks, &mut world_load_chunks_queue, &mut world_load_chunks_tasks);
+                }
+            }
+            ChunkUpdateEvent::LevelChanged(pos) if world.chunk_level(pos) == Some(TicketKind::Render) => {
+                if !rendered_chunks.contains_key(pos) && !world_load_chunks_tasks.contains_key(pos) {
+                    world_load_chunks_queue.insert(*pos);
+                }
+                world_unload_chunks_queue.remove(pos);
+            }
+            // Чанк перестал отображаться, но может остаться загруженным
+            ChunkUpdateEvent::Unloaded(pos) | ChunkUpdateEvent::LevelChanged(pos) => {
                 world_load_chunks_queue.remove(pos);
                 if let Some(task) = world_load_chunks_tasks.remove(pos) {
                     let _ = task.cancel();
                 }
+                missing_neighbors.remove(pos);
                 world_unload_chunks_queue.insert(*pos);
             }
         }
     }
 }
 
+/// Ставит чанк в очередь на перестроение меша. Еще не отрендеренные чанки пропускаются, они получат актуальное
+/// состояние при первом построении меша
+fn remesh_chunk(
+    pos: ChunkPos,
+    rendered_chunks: &WorldRenderedChunks,
+    world_load_chunks_queue: &mut WorldLoadChunksQueue,
+    world_load_chunks_tasks: &mut WorldLoadChunksTasks,
+) {
+    if rendered_chunks.contains_key(&pos) || world_load_chunks_tasks.contains_key(&pos) {
+        world_load_chunks_queue.insert(pos);
+        if let Some(task) = world_load_chunks_tasks.remove(&pos) {
+            let _ = task.cancel();
+        }
+    }
+}
+
 fn start_load_chunks(
     mut world_load_chunks_queue: ResMut<WorldLoadChunksQueue>,
     mut world_load_chunks_tasks: ResMut<WorldLoadChunksTasks>,
     world: Res<World>,
+    block_registry: Res<SharedBlockRegistry>,
+    block_texture_atlas: Res<SharedBlockTextureAtlas>,
+    meshing_mode: Res<MeshingMode>,
 ) {
     let pool = AsyncComputeTaskPool::get();
     world_load_chunks_queue.retain(|pos| {
-        let chunk = world.get_chunk(&pos);
-        let chunk = match chunk {
-            None => { return true; }
-            Some(chunk) => { chunk }
-        };
+        if !world.is_chunk_loaded(pos) {
+            return true;
+        }
         let chunk_map = Arc::clone(&world.chunk_map);
+        let light_map = Arc::clone(&world.light_map);
+        let block_registry = block_registry.clone();
+        let block_texture_atlas = block_texture_atlas.clone();
+        let meshing_mode = *meshing_mode;
         let pos = *pos;
         let task = pool.spawn(async move {
-            let chunk = chunk.read().unwrap();
-            build_chunk_mesh(&chunk_map, &chunk, pos)
+            // Снимок снимается внутри задачи, чтобы не копировать блоки в основном потоке
+            let snapshot = ChunkMeshSnapshot::new(&chunk_map, &light_map, pos)?;
+            let mesh = build_chunk_mesh(meshing_mode, &block_registry, &block_texture_atlas, &snapshot);
+            Some((mesh, snapshot.missing_neighbors().to_vec()))
         });
         world_load_chunks_tasks.insert(pos, task);
         false
@@ -122,16 +236,27 @@ fn start_load_chunks(
 }
 
 
-/// Создает меши новых чанков и загружает их в память
+/// Создает меши новых чанков и загружает их в память, не больше чем позволяет [MeshUploadBudget] за кадр
 fn collect_loaded_chunks(
     mut world_load_chunks_tasks: ResMut<WorldLoadChunksTasks>,
     mut rendered_chunks: ResMut<WorldRenderedChunks>,
     mut commands: Commands,
     mut assets: ResMut<Assets<Mesh>>,
+    mut missing_neighbors: ResMut<WorldMissingNeighbors>,
+    mut world_load_chunks_queue: ResMut<WorldLoadChunksQueue>,
+    mut diagnostics: Diagnostics,
+    world: Res<World>,
+    budget: Res<MeshUploadBudget>,
     world_material: Res<WorldMaterial>,
 ) {
+    let mut budget = budget.start();
+    let mut meshes_to_upload = 0;
     world_load_chunks_tasks.retain(|pos, task| {
         if !task.is_finished() { return true; }
+        if !budget.try_take() {
+            meshes_to_upload += 1;
+            return true;
+        }
 
         let entity = rendered_chunks.get(pos).unwrap_or(&None);
 
@@ -146,7 +271,16 @@ fn collect_loaded_chunks(
             }
         }
 
-        let mesh: Mesh = block_on(poll_once(task)).unwrap();
+        // Чанк выгрузился пока строился меш, его выгрузка обрабатывается через события
+        let Some((mesh, missing)) = block_on(poll_once(task)).unwrap() else {
+            return false;
+        };
+
+        // Сосед мог загрузиться уже после снятия снимка, тогда его событие загрузки уже обработано
+        if missing.iter().any(|neighbor| world.is_chunk_loaded(neighbor)) {
+            world_load_chunks_queue.insert(*pos);
+        }
+        missing_neighbors.set(*pos, missing);
 
         // Не спавним пустые меши, это сильно бьет по производительности рендера
         if mesh.indices().map(|indexes| { indexes.is_empty() }).unwrap_or(true) {
@@ -156,7 +290,7 @@ fn collect_loaded_chunks(
 
         let mesh = assets.add(mesh);
 
-        let bundle = PbrBundle {
+        let bundle = MaterialMeshBundle {
             mesh,
             material: world_material.clone(),
             transform: Transform::from_translation(pos.get_absolute_coord().as_vec3()),
@@ -168,6 +302,8 @@ fn collect_loaded_chunks(
         rendered_chunks.insert(*pos, Some(entity));
         false
     });
+
+    diagnostics.add_measurement(ChunkRenderPlugin::MESHES_TO_UPLOAD, || meshes_to_upload as f64);
 }
 
 /// Выгружает ненужные меши чанков из памяти